@@ -3,12 +3,14 @@ fn main() {
         .cargo_out_dir("protos")
         .include("src")
         .protoc_extra_arg("--proto_path=src/protos")
-        .inputs(&[
+        .inputs([
             "src/protos/device.proto",
             "src/protos/gamestate.proto",
             "src/protos/input.proto",
             "src/protos/run_mode.proto",
             "src/protos/runtime_status.proto",
+            "src/protos/security.proto",
+            "src/protos/start_pos.proto",
             "src/protos/text.proto",
             "src/protos/timestamp.proto"
         ])
@@ -1,31 +1,37 @@
 package main
 
 import (
+	"bufio"
 	"fmt"
 	"net"
 	"time"
 )
 
-func main() {
-	conn, err := net.Dial("tcp", "127.0.0.1:8080")
+// send writes one command and prints the daemon's reply
+func send(conn net.Conn, reader *bufio.Reader, command string) {
+	fmt.Fprintln(conn, command)
+	reply, err := reader.ReadString('\n')
 	if err != nil {
-		fmt.Printf("Failed to connect: %v\n", err)
+		fmt.Printf("Failed to read reply: %v\n", err)
 		return
 	}
-	conn.Write([]byte{2, 3})
-	connError := conn.Close()
-	if connError != nil {
-		fmt.Printf("Failed to close connection: %v\n", connError)
-	}
+	fmt.Print(reply)
+}
 
-	time.Sleep(2 * time.Second)
-	conn, err = net.Dial("tcp", "127.0.0.1:8080")
+func main() {
+	conn, err := net.Dial("tcp", "127.0.0.1:8080")
 	if err != nil {
 		fmt.Printf("Failed to connect: %v\n", err)
 		return
 	}
-	conn.Write([]byte{3, 3})
-	connError = conn.Close()
+	reader := bufio.NewReader(conn)
+	send(conn, reader, `{"cmd": "run", "mode": "teleop"}`)
+	send(conn, reader, `{"cmd": "gamepad", "buttons": 3}`)
+
+	time.Sleep(2 * time.Second)
+	send(conn, reader, `{"cmd": "gamepad", "buttons": 0}`)
+	send(conn, reader, `{"cmd": "run", "mode": "stop"}`)
+	connError := conn.Close()
 	if connError != nil {
 		fmt.Printf("Failed to close connection: %v\n", connError)
 	}
@@ -0,0 +1,53 @@
+use daybreak::mock_runtime::mock_runtime::{MockRuntime, Script};
+use std::{env, time::Duration};
+
+fn help() {
+    println!("daybreak-mock-runtime - a fake PiE Runtime for developing Daybreak without a robot");
+    println!("Usage: daybreak-mock-runtime [--port PORT] [--interval MS]");
+    println!("  --port PORT     TCP port to listen on (default 8101)");
+    println!("  --interval MS   How often device data and logs are sent (default 100)");
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let mut port: u16 = 8101;
+    let mut script = Script::default();
+
+    let mut i = 1;
+    while i < args.len() {
+        let value = args.get(i + 1);
+        match args[i].as_str() {
+            "--port" => match value.and_then(|value| value.parse().ok()) {
+                Some(value) => port = value,
+                None => {
+                    println!("--port requires a port number.");
+                    std::process::exit(1);
+                }
+            },
+            "--interval" => match value.and_then(|value| value.parse().ok()) {
+                Some(value) => script.interval = Duration::from_millis(value),
+                None => {
+                    println!("--interval requires a number of milliseconds.");
+                    std::process::exit(1);
+                }
+            },
+            "--help" | "-h" => {
+                help();
+                return;
+            }
+            other => {
+                println!("Unknown argument: {}", other);
+                help();
+                std::process::exit(1);
+            }
+        }
+        i += 2;
+    }
+
+    let mock = MockRuntime::bind(&format!("0.0.0.0:{}", port), script);
+    if mock.is_err() {
+        println!("[MockRuntime] Failed to bind to port {}: {}", port, mock.err().unwrap());
+        std::process::exit(1);
+    }
+    mock.unwrap().serve();
+}
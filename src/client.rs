@@ -0,0 +1,397 @@
+#[allow(clippy::module_inception)]
+pub mod client {
+    use std::{env, os::unix::net::UnixStream};
+
+    use protobuf::Message;
+
+    use crate::{
+        error::error::DaybreakError,
+        ipc::ipc::{hello, receive, send, DaemonStatus, Event, Request, Response, RobotType, RunCommand},
+        robot::robotmanager::{
+            daemon_socket_path, device::DevData, input::Input,
+            start_pos::Pos, ConnectOptions, GameAlert, LatencyStats, LinkState, RuntimeStatus, DEFAULT_SESSION,
+        },
+        run_log::run_log::{decode_log_lines, LogLine},
+    };
+
+    /// Talks to the daemon about one robot session, on a fresh connection per request.
+    #[derive(Debug, Clone)]
+    pub struct DaybreakClient {
+        // None for this user's daemon, found (and checked) on every connect
+        socket_path: Option<String>,
+        session: String,
+    }
+
+    impl Default for DaybreakClient {
+        fn default() -> Self {
+            DaybreakClient::new()
+        }
+    }
+
+    impl DaybreakClient {
+        /// A client for the default session on this user's daemon.
+        pub fn new() -> DaybreakClient {
+            DaybreakClient {
+                socket_path: None,
+                session: DEFAULT_SESSION.to_string(),
+            }
+        }
+
+        /// Targets a named robot session instead of the default one.
+        pub fn with_session(mut self, session: &str) -> DaybreakClient {
+            self.session = session.to_string();
+            self
+        }
+
+        /// Reaches a daemon listening somewhere other than the usual socket.
+        pub fn with_socket(mut self, socket_path: &str) -> DaybreakClient {
+            self.socket_path = Some(socket_path.to_string());
+            self
+        }
+
+        pub fn session(&self) -> &str {
+            &self.session
+        }
+
+        // connects and shakes hands, telling the daemon which session we're talking about
+        fn open(&self) -> Result<UnixStream, DaybreakError> {
+            let socket_path = match &self.socket_path {
+                Some(socket_path) => socket_path.clone(),
+                None => daemon_socket_path()?,
+            };
+            let mut stream = UnixStream::connect(socket_path)?;
+            hello(&mut stream, &self.session)?;
+            Ok(stream)
+        }
+
+        // sends one request on a fresh connection and waits for the first response
+        fn request(&self, request: &Request) -> Result<(UnixStream, Response), DaybreakError> {
+            let mut stream = self.open()?;
+            send(&mut stream, request)?;
+            let response = receive(&mut stream)?;
+            Ok((stream, response))
+        }
+
+        fn expect_ok(&self, request: &Request) -> Result<(), DaybreakError> {
+            match self.request(request)?.1 {
+                Response::Ok => Ok(()),
+                other => Err(DaybreakError::unexpected(other)),
+            }
+        }
+
+        // for requests answered with Started and then Ok once they're done
+        fn expect_done(&self, request: &Request) -> Result<(), DaybreakError> {
+            let (mut stream, response) = self.request(request)?;
+            if response != Response::Started {
+                return Err(DaybreakError::unexpected(response));
+            }
+            match receive(&mut stream)? {
+                Response::Ok => Ok(()),
+                other => Err(DaybreakError::unexpected(other)),
+            }
+        }
+
+        fn input_stream(&self, request: &Request) -> Result<RunStream, DaybreakError> {
+            let (stream, response) = self.request(request)?;
+            match response {
+                Response::Ok => Ok(RunStream { stream }),
+                other => Err(DaybreakError::unexpected(other)),
+            }
+        }
+
+        /// Connects the session to Runtime, returning once it's connected or has given up.
+        pub fn connect(&self, ip: &str, robot_type: RobotType, options: ConnectOptions) -> Result<(), DaybreakError> {
+            self.expect_done(&Request::Connect {
+                ip: ip.trim().to_string(),
+                robot_type,
+                options,
+            })
+        }
+
+        /// Idles Runtime and drops the session's robot.
+        pub fn disconnect(&self) -> Result<(), DaybreakError> {
+            self.expect_ok(&Request::Disconnect)
+        }
+
+        /// Trusts the robot's ssh host key, returning its fingerprint and whether it wasn't trusted before.
+        /// With `fingerprint`, only if the robot's key has it. `replace` swaps out a key trusted before.
+        pub fn trust(&self, fingerprint: Option<&str>, replace: bool) -> Result<(String, bool), DaybreakError> {
+            let request = Request::Trust {
+                fingerprint: fingerprint.map(str::to_string),
+                replace,
+            };
+            match self.request(&request)?.1 {
+                Response::Trusted { fingerprint, added } => Ok((fingerprint, added)),
+                other => Err(DaybreakError::unexpected(other)),
+            }
+        }
+
+        /// Changes the run mode. The robot keeps running until the returned stream is stopped or dropped.
+        pub fn run(&self, mode: RunCommand) -> Result<RunStream, DaybreakError> {
+            self.input_stream(&Request::Run(mode))
+        }
+
+        /// Forwards input like `run` without changing the run mode.
+        pub fn listen(&self) -> Result<RunStream, DaybreakError> {
+            self.input_stream(&Request::InputListener)
+        }
+
+        /// A connection to start a run on later, with `RunStream::start`.
+        pub fn run_stream(&self) -> Result<RunStream, DaybreakError> {
+            Ok(RunStream { stream: self.open()? })
+        }
+
+        /// Sends `path`, relative to the working directory, to the robot as its student code.
+        pub fn upload(&self, path: &str) -> Result<(), DaybreakError> {
+            self.expect_done(&Request::Upload {
+                cwd: env::current_dir()?.display().to_string(),
+                path: path.to_string(),
+            })
+        }
+
+        /// Fetches the robot's student code into `path`, relative to the working directory.
+        pub fn download(&self, path: &str) -> Result<(), DaybreakError> {
+            self.expect_done(&Request::Download {
+                cwd: env::current_dir()?.display().to_string(),
+                path: path.to_string(),
+            })
+        }
+
+        /// The robot's devices, None without a robot or before it has sent any.
+        pub fn devices(&self) -> Result<Option<DevData>, DaybreakError> {
+            match self.request(&Request::QueryDevices)?.1 {
+                Response::Devices(Some(bytes)) => Ok(Some(DevData::parse_from_bytes(&bytes)?)),
+                Response::Devices(None) => Ok(None),
+                other => Err(DaybreakError::unexpected(other)),
+            }
+        }
+
+        pub fn runtime_status(&self) -> Result<Option<RuntimeStatus>, DaybreakError> {
+            match self.request(&Request::QueryStatus)?.1 {
+                Response::RuntimeStatus(status) => Ok(status),
+                other => Err(DaybreakError::unexpected(other)),
+            }
+        }
+
+        pub fn latency(&self) -> Result<Option<LatencyStats>, DaybreakError> {
+            match self.request(&Request::QueryLatency)?.1 {
+                Response::Latency(latency) => Ok(latency),
+                other => Err(DaybreakError::unexpected(other)),
+            }
+        }
+
+        pub fn link(&self) -> Result<Option<LinkState>, DaybreakError> {
+            match self.request(&Request::QueryLink)?.1 {
+                Response::Link(link) => Ok(link),
+                other => Err(DaybreakError::unexpected(other)),
+            }
+        }
+
+        /// The newest field event, None before the field has sent one.
+        pub fn game_alert(&self) -> Result<Option<GameAlert>, DaybreakError> {
+            match self.request(&Request::QueryGameState)?.1 {
+                Response::GameAlert(alert) => Ok(alert),
+                other => Err(DaybreakError::unexpected(other)),
+            }
+        }
+
+        /// Sets the side to start autonomous on. False when no robot is connected and it's kept for the next one.
+        pub fn set_start_pos(&self, pos: Pos) -> Result<bool, DaybreakError> {
+            match self.request(&Request::SetStartPos(pos.into()))?.1 {
+                Response::Ok => Ok(true),
+                Response::Queued => Ok(false),
+                other => Err(DaybreakError::unexpected(other)),
+            }
+        }
+
+        pub fn status(&self) -> Result<DaemonStatus, DaybreakError> {
+            match self.request(&Request::Status)?.1 {
+                Response::Status(status) => Ok(status),
+                other => Err(DaybreakError::unexpected(other)),
+            }
+        }
+
+        /// Plays a session recording back in place of a robot.
+        pub fn replay(&self, path: &str) -> Result<(), DaybreakError> {
+            self.expect_ok(&Request::Replay { path: path.to_string() })
+        }
+
+        /// Opens an event stream, with log lines after `since`, or new lines only for `u64::MAX`.
+        pub fn subscribe(&self, since: u64) -> Result<Subscription, DaybreakError> {
+            let mut stream = self.open()?;
+            send(&mut stream, &Request::Subscribe { since })?;
+            Ok(Subscription { stream })
+        }
+
+        /// Leaves every robot idle and stops the daemon.
+        pub fn shutdown(&self) -> Result<(), DaybreakError> {
+            self.expect_ok(&Request::Shutdown)
+        }
+    }
+
+    /// A connection carrying gamepad and keyboard input while the robot runs.
+    /// The daemon stops the robot when it's stopped or dropped.
+    #[derive(Debug)]
+    pub struct RunStream {
+        stream: UnixStream,
+    }
+
+    impl RunStream {
+        /// Asks for a run mode on a stream from `DaybreakClient::run_stream`, without waiting for the answer.
+        pub fn start(&mut self, mode: RunCommand) -> Result<(), DaybreakError> {
+            Ok(send(&mut self.stream, &Request::Run(mode))?)
+        }
+
+        /// Like `start`, but forwards input without changing the run mode.
+        pub fn start_listening(&mut self) -> Result<(), DaybreakError> {
+            Ok(send(&mut self.stream, &Request::InputListener)?)
+        }
+
+        pub fn send_input(&mut self, input: &Input) -> Result<(), DaybreakError> {
+            let input = input.write_to_bytes()?;
+            Ok(send(&mut self.stream, &Request::Input(input))?)
+        }
+
+        /// Ends the run. The daemon hangs up afterwards, so the next run needs a new stream.
+        pub fn stop(&mut self) -> Result<(), DaybreakError> {
+            Ok(send(&mut self.stream, &Request::StopRun)?)
+        }
+
+        pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), DaybreakError> {
+            Ok(self.stream.set_nonblocking(nonblocking)?)
+        }
+    }
+
+    /// What the daemon pushes about a session as it changes.
+    #[derive(Debug)]
+    pub struct Subscription {
+        stream: UnixStream,
+    }
+
+    impl Subscription {
+        /// Blocks for the next event. Keepalives come back as `None`.
+        pub fn next_event(&mut self) -> Result<Option<Event>, DaybreakError> {
+            match receive(&mut self.stream)? {
+                Response::Event(event) => Ok(event),
+                other => Err(DaybreakError::unexpected(other)),
+            }
+        }
+
+        /// Blocks for the next event, keeping only its log lines.
+        pub fn logs(&mut self) -> Result<Vec<LogLine>, DaybreakError> {
+            match self.next_event()? {
+                Some(Event::Logs(batch)) => match decode_log_lines(&batch) {
+                    Some((_, lines)) => Ok(lines),
+                    None => Err(DaybreakError::Decode("malformed log batch".to_string())),
+                },
+                _ => Ok(vec![]),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::{
+            ipc::ipc::{ConnectionState, DaemonError},
+            mock_runtime::mock_runtime::{MockRuntime, Script},
+            robot::robotmanager::input::Source,
+            test_support::test_support::{self, eventually},
+        };
+        use protobuf::{EnumOrUnknown, SpecialFields};
+
+        fn start_daemon(name: &str) -> DaybreakClient {
+            DaybreakClient::new()
+                .with_socket(&test_support::start_daemon(&format!("client-{}", name)))
+                .with_session(&format!("{}-{}", name, std::process::id()))
+        }
+
+        #[test]
+        fn errors_say_what_went_wrong() {
+            let nowhere = DaybreakClient::new().with_socket("/nonexistent/daybreak.sock");
+            assert!(matches!(nowhere.status(), Err(DaybreakError::Io(_))));
+
+            let client = start_daemon("errors");
+            assert!(matches!(client.devices(), Ok(None)));
+            assert!(matches!(
+                client.run(RunCommand::Teleop),
+                Err(DaybreakError::Daemon(DaemonError::NoRobot))
+            ));
+            assert!(matches!(
+                client.disconnect(),
+                Err(DaybreakError::Daemon(DaemonError::NoRobot))
+            ));
+            assert!(matches!(client.set_start_pos(Pos::LEFT), Ok(false)));
+            assert!(matches!(
+                client.trust(None, false),
+                Err(DaybreakError::Daemon(DaemonError::NoRobot))
+            ));
+            let invalid = client.clone().with_session("not a session name");
+            assert!(matches!(
+                invalid.status(),
+                Err(DaybreakError::Daemon(DaemonError::InvalidSession(_)))
+            ));
+        }
+
+        #[test]
+        fn drives_the_mock_runtime() {
+            let mock = MockRuntime::bind("127.0.0.1:0", Script::default()).unwrap();
+            let port = mock.port();
+            let recorded = mock.recorded();
+            mock.spawn();
+            let client = start_daemon("drive");
+
+            let mut events = client.subscribe(0).unwrap();
+            client
+                .connect(
+                    "127.0.0.1",
+                    RobotType::Potato,
+                    ConnectOptions {
+                        port,
+                        ..ConnectOptions::default()
+                    },
+                )
+                .unwrap();
+            assert_eq!(
+                client.status().unwrap().connection,
+                ConnectionState::Connected {
+                    ip: "127.0.0.1".to_string()
+                }
+            );
+
+            let mut run = client.run(RunCommand::Teleop).unwrap();
+            let input = Input {
+                connected: true,
+                buttons: 0b101,
+                axes: vec![0.5, 0.0, 0.0, -0.5],
+                source: EnumOrUnknown::new(Source::GAMEPAD),
+                special_fields: SpecialFields::default(),
+            };
+            run.send_input(&input).unwrap();
+            eventually("the input to reach Runtime", || {
+                recorded
+                    .lock()
+                    .unwrap()
+                    .inputs
+                    .iter()
+                    .any(|inputs| inputs.inputs.iter().any(|sent| sent.buttons == 0b101))
+                    .then_some(())
+            });
+            let devices = eventually("devices", || client.devices().unwrap());
+            assert_eq!(devices.devices, Script::default().devices);
+            let lines = eventually("log lines", || {
+                let lines = events.logs().unwrap();
+                (!lines.is_empty()).then_some(lines)
+            });
+            assert!(lines.iter().all(|line| line.session == client.session()));
+
+            run.stop().unwrap();
+            eventually("the run to stop", || match events.next_event().unwrap() {
+                Some(Event::RunMode(RunCommand::Stop)) => Some(()),
+                _ => None,
+            });
+            client.disconnect().unwrap();
+            assert!(client.devices().unwrap().is_none());
+        }
+    }
+}
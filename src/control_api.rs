@@ -0,0 +1,640 @@
+#[allow(clippy::module_inception)]
+pub mod control_api {
+    use std::{
+        io::{self, BufRead, BufReader, Read, Write},
+        net::{SocketAddr, TcpListener, TcpStream},
+        os::unix::net::UnixStream,
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use ini::Ini;
+    use protobuf::{EnumOrUnknown, Message, SpecialFields};
+    use serde::Deserialize;
+    use serde_json::{json, Map, Value};
+
+    use crate::{
+        daemon::daemonhandler::{local_client, DaemonState},
+        ipc::ipc::{receive, send, Event, Request, Response, RobotType, RunCommand},
+        robot::robotmanager::{
+            device::{param::Val, DevData},
+            input::{Input, Source},
+            is_valid_session_name,
+            ConnectOptions, DEFAULT_SESSION,
+        },
+        run_log::run_log::decode_log_lines,
+    };
+
+    /// Where the API listens when `[api]` doesn't say.
+    pub const DEFAULT_API_ADDR: &str = "127.0.0.1:8080";
+    // a command is one line of JSON, and none of them come close to this
+    const MAX_COMMAND_SIZE: u64 = 64 * 1024;
+
+    /// The `[api]` section of daybreak.ini. The API stays off unless `enabled = true`.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct ApiConfig {
+        pub bind: SocketAddr,
+        /// Asked for in `hello` before anything else. Required when `bind` isn't a loopback address.
+        pub token: Option<String>,
+        /// Uploads are only taken from under here. Defaults to the directory `base` names.
+        pub code_dir: PathBuf,
+    }
+
+    impl ApiConfig {
+        /// `None` when the API is off, or why the section can't be used. A relative `code_dir` is taken from `base`.
+        pub fn from_ini(ini: &Ini, base: &Path) -> Result<Option<ApiConfig>, String> {
+            let section = match ini.section(Some("api")) {
+                Some(section) => section,
+                None => return Ok(None),
+            };
+            if section.get("enabled").map(str::trim) != Some("true") {
+                return Ok(None);
+            }
+            let bind = section.get("bind").unwrap_or(DEFAULT_API_ADDR).trim();
+            let bind: SocketAddr = bind
+                .parse()
+                .map_err(|_| format!("Invalid value for bind: {:?}", bind))?;
+            let token = section
+                .get("token")
+                .map(|token| token.trim().to_string())
+                .filter(|token| !token.is_empty());
+            if token.is_none() && !bind.ip().is_loopback() {
+                return Err(format!("Refusing to serve the API on {} without a token.", bind));
+            }
+            let code_dir = match section.get("code_dir").map(str::trim).filter(|dir| !dir.is_empty()) {
+                Some(dir) => base.join(dir),
+                None => base.to_path_buf(),
+            };
+            Ok(Some(ApiConfig { bind, token, code_dir }))
+        }
+
+        /// A relative `code_dir` is taken from the directory `config` is in.
+        pub fn load(config: &Path) -> Result<Option<ApiConfig>, String> {
+            match Ini::load_from_file(config) {
+                Ok(ini) => ApiConfig::from_ini(&ini, config.parent().unwrap_or(Path::new("/"))),
+                Err(_) => Ok(None),
+            }
+        }
+    }
+
+    // tools name code relative to code_dir, and however they spell it, it can't end up anywhere else
+    fn code_under(code_dir: &Path, path: &str) -> Result<PathBuf, String> {
+        let code_dir = code_dir
+            .canonicalize()
+            .map_err(|e| format!("Can't use the code directory {}: {}", code_dir.display(), e))?;
+        let full_path = code_dir
+            .join(path)
+            .canonicalize()
+            .map_err(|e| format!("Can't upload {:?}: {}", path, e))?;
+        if !full_path.starts_with(&code_dir) {
+            return Err(format!("Can't upload {:?}, it isn't under {}.", path, code_dir.display()));
+        }
+        Ok(full_path)
+    }
+
+    /// One line of JSON from a tool, like `{"cmd": "run", "mode": "teleop"}`.
+    #[derive(Debug, Deserialize)]
+    #[serde(tag = "cmd", rename_all = "snake_case")]
+    enum Command {
+        /// Picks the session and gives the token. Must come first when there is a token.
+        Hello {
+            session: Option<String>,
+            token: Option<String>,
+        },
+        /// `options` takes the same keys as daybreak.ini, like `{"port": 8101}`.
+        Connect {
+            ip: String,
+            robot_type: Option<String>,
+            #[serde(default)]
+            options: Map<String, Value>,
+        },
+        Disconnect,
+        Run { mode: RunCommand },
+        /// A file under the API's `code_dir` on the daemon's machine, relative to it.
+        Upload { path: String },
+        Devices,
+        /// The daemon's `Status` for this session.
+        Status,
+        /// Stands in for a gamepad until the next one. Starts forwarding inputs if no run is open.
+        Gamepad {
+            #[serde(default)]
+            buttons: u64,
+            #[serde(default)]
+            axes: Vec<f32>,
+        },
+        /// Streams events as they happen, with log lines after `since`, or new ones only when left out.
+        Subscribe { since: Option<u64> },
+    }
+
+    // what one tool's connection has going
+    struct Client {
+        state: Arc<DaemonState>,
+        writer: Arc<Mutex<TcpStream>>,
+        session: String,
+        code_dir: PathBuf,
+        // the daemon connection inputs go through, open from `run` or the first `gamepad` until `run stop`
+        run: Option<UnixStream>,
+        subscribed: bool,
+    }
+
+    fn write_line(writer: &Mutex<TcpStream>, value: &Value) -> io::Result<()> {
+        let mut writer = writer.lock().unwrap();
+        writer.write_all(format!("{}\n", value).as_bytes())?;
+        writer.flush()
+    }
+
+    fn failure(error: impl ToString) -> Value {
+        json!({ "ok": false, "error": error.to_string() })
+    }
+
+    fn devices_json(bytes: &[u8]) -> Value {
+        let devices = DevData::parse_from_bytes(bytes).map(|data| data.devices).unwrap_or_default();
+        devices
+            .iter()
+            .map(|device| {
+                let params: Map<String, Value> = device
+                    .params
+                    .iter()
+                    .filter_map(|param| {
+                        let val = match param.val.as_ref()? {
+                            Val::Fval(val) => json!(val),
+                            Val::Ival(val) => json!(val),
+                            Val::Bval(val) => json!(val),
+                        };
+                        Some((param.name.clone(), val))
+                    })
+                    .collect();
+                json!({ "name": device.name, "uid": device.uid, "type": device.type_, "params": params })
+            })
+            .collect()
+    }
+
+    fn event_json(event: Event) -> Value {
+        match event {
+            Event::Devices(bytes) => json!({ "event": "devices", "devices": devices_json(&bytes) }),
+            Event::Logs(batch) => {
+                let lines: Vec<Value> = decode_log_lines(&batch)
+                    .map(|(_, lines)| lines)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|line| {
+                        json!({
+                            "seq": line.seq,
+                            "received_ms": line.received_ms,
+                            "mode": format!("{:?}", line.mode),
+                            "text": line.text,
+                        })
+                    })
+                    .collect();
+                json!({ "event": "logs", "lines": lines })
+            }
+            Event::RunMode(mode) => json!({ "event": "run_mode", "mode": mode }),
+            Event::Connection(state) => json!({ "event": "connection", "state": state }),
+            Event::RuntimeStatus(status) => json!({
+                "event": "runtime_status",
+                "battery": status.battery,
+                "mode": format!("{:?}", status.mode),
+                "shep_connected": status.shep_connected,
+                "dawn_connected": status.dawn_connected,
+            }),
+            Event::Upload(progress) => json!({ "event": "upload", "progress": progress }),
+            Event::Link(link) => json!({ "event": "link", "link": link }),
+            Event::Latency(latency) => json!({ "event": "latency", "latency": latency }),
+            Event::GameAlert(alert) => json!({
+                "event": "game_alert",
+                "seq": alert.seq,
+                "state": format!("{:?}", alert.state),
+            }),
+        }
+    }
+
+    impl Client {
+        fn open(&self, request: &Request) -> io::Result<(UnixStream, Response)> {
+            let mut stream = local_client(&self.state, &self.session)?;
+            send(&mut stream, request)?;
+            let response = receive(&mut stream)?;
+            Ok((stream, response))
+        }
+
+        // sends a request and waits past `Started` for how it ended
+        fn finish(&self, request: &Request) -> io::Result<Response> {
+            let (mut stream, mut response) = self.open(request)?;
+            while response == Response::Started {
+                response = receive(&mut stream)?;
+            }
+            Ok(response)
+        }
+
+        // ends the open run, waiting until the daemon has told the robot to stop
+        fn stop_run(&mut self) {
+            if let Some(mut run) = self.run.take() {
+                let _ = send(&mut run, &Request::StopRun);
+                while receive::<Response>(&mut run).is_ok() {}
+            }
+        }
+
+        fn handle(&mut self, command: Command) -> Value {
+            match command {
+                Command::Hello { session, .. } => {
+                    let session = session.unwrap_or_else(|| DEFAULT_SESSION.to_string());
+                    if !is_valid_session_name(&session) {
+                        return failure(format!("Invalid session name: {:?}", session));
+                    }
+                    self.stop_run();
+                    self.session = session;
+                    json!({ "ok": true })
+                }
+                Command::Connect {
+                    ip,
+                    robot_type,
+                    options: values,
+                } => {
+                    let robot_type = robot_type.unwrap_or_else(|| "potato".to_string());
+                    let robot_type = match RobotType::from_name(&robot_type) {
+                        Some(robot_type) => robot_type,
+                        None => return failure(format!("Unknown robot type: {:?}", robot_type)),
+                    };
+                    let mut options = ConnectOptions::default();
+                    for (key, value) in values {
+                        let value = match value {
+                            Value::String(value) => value,
+                            value => value.to_string(),
+                        };
+                        if let Err(e) = options.set(&key, &value) {
+                            return failure(e);
+                        }
+                    }
+                    self.stop_run();
+                    respond_json(self.finish(&Request::Connect {
+                        ip,
+                        robot_type,
+                        options,
+                    }))
+                }
+                Command::Disconnect => {
+                    self.stop_run();
+                    respond_json(self.finish(&Request::Disconnect))
+                }
+                Command::Run { mode } => {
+                    self.stop_run();
+                    if mode == RunCommand::Stop {
+                        return respond_json(self.finish(&Request::Run(mode)));
+                    }
+                    match self.open(&Request::Run(mode)) {
+                        Ok((run, Response::Ok)) => {
+                            self.run = Some(run);
+                            json!({ "ok": true })
+                        }
+                        other => respond_json(other.map(|(_, response)| response)),
+                    }
+                }
+                Command::Upload { path } => {
+                    let full_path = match code_under(&self.code_dir, &path) {
+                        Ok(full_path) => full_path,
+                        Err(e) => return failure(e),
+                    };
+                    let (cwd, file) = match (full_path.parent(), full_path.file_name()) {
+                        (Some(cwd), Some(file)) => (cwd, file),
+                        _ => return failure(format!("Not a file: {:?}", path)),
+                    };
+                    respond_json(self.finish(&Request::Upload {
+                        cwd: cwd.to_string_lossy().to_string(),
+                        path: Path::new(file).to_string_lossy().to_string(),
+                    }))
+                }
+                Command::Devices => match self.finish(&Request::QueryDevices) {
+                    Ok(Response::Devices(devices)) => json!({
+                        "ok": true,
+                        "devices": devices.as_deref().map_or(json!([]), devices_json),
+                    }),
+                    other => respond_json(other),
+                },
+                Command::Status => match self.finish(&Request::Status) {
+                    Ok(Response::Status(status)) => json!({ "ok": true, "status": status }),
+                    other => respond_json(other),
+                },
+                Command::Gamepad { buttons, axes } => {
+                    let input = Input {
+                        connected: true,
+                        buttons,
+                        axes,
+                        source: EnumOrUnknown::new(Source::GAMEPAD),
+                        special_fields: SpecialFields::default(),
+                    }
+                    .write_to_bytes()
+                    .unwrap();
+                    if self.run.is_none() {
+                        match self.open(&Request::InputListener) {
+                            Ok((run, Response::Ok)) => self.run = Some(run),
+                            other => return respond_json(other.map(|(_, response)| response)),
+                        }
+                    }
+                    match send(self.run.as_mut().unwrap(), &Request::Input(input)) {
+                        Ok(()) => json!({ "ok": true }),
+                        Err(e) => {
+                            self.run = None;
+                            failure(e)
+                        }
+                    }
+                }
+                Command::Subscribe { since } => {
+                    if self.subscribed {
+                        return failure("Already subscribed.");
+                    }
+                    let stream = local_client(&self.state, &self.session).and_then(|mut stream| {
+                        send(&mut stream, &Request::Subscribe { since: since.unwrap_or(u64::MAX) })?;
+                        Ok(stream)
+                    });
+                    let mut stream = match stream {
+                        Ok(stream) => stream,
+                        Err(e) => return failure(e),
+                    };
+                    self.subscribed = true;
+                    let writer = Arc::clone(&self.writer);
+                    thread::spawn(move || {
+                        while let Ok(Response::Event(event)) = receive(&mut stream) {
+                            // keepalives only matter on the daemon socket, but they do tell us when the tool left
+                            let line = event.map_or(json!({ "event": "keepalive" }), event_json);
+                            if write_line(&writer, &line).is_err() {
+                                return;
+                            }
+                        }
+                    });
+                    json!({ "ok": true })
+                }
+            }
+        }
+    }
+
+    fn respond_json(response: io::Result<Response>) -> Value {
+        match response {
+            Ok(Response::Ok) | Ok(Response::Queued) => json!({ "ok": true }),
+            Ok(Response::Error(e)) => failure(e),
+            Ok(other) => failure(format!("unexpected {:?}", other)),
+            Err(e) => failure(e),
+        }
+    }
+
+    fn serve_tool(state: Arc<DaemonState>, stream: TcpStream, token: Option<String>, code_dir: PathBuf) {
+        let reader = stream.try_clone();
+        if reader.is_err() {
+            return;
+        }
+        let mut reader = BufReader::new(reader.unwrap());
+        let mut client = Client {
+            state,
+            writer: Arc::new(Mutex::new(stream)),
+            session: DEFAULT_SESSION.to_string(),
+            code_dir,
+            run: None,
+            subscribed: false,
+        };
+        let mut authorized = token.is_none();
+        loop {
+            let mut line = String::new();
+            match (&mut reader).take(MAX_COMMAND_SIZE).read_line(&mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+            if !line.ends_with('\n') && line.len() as u64 == MAX_COMMAND_SIZE {
+                let _ = write_line(&client.writer, &failure("Command too long."));
+                break;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            let command = serde_json::from_str::<Command>(&line);
+            if command.is_err() {
+                let reply = failure(format!("Bad command: {}", command.err().unwrap()));
+                if write_line(&client.writer, &reply).is_err() {
+                    break;
+                }
+                continue;
+            }
+            let command = command.unwrap();
+            if !authorized {
+                match command {
+                    Command::Hello { token: Some(ref given), .. } if Some(given) == token.as_ref() => {
+                        authorized = true;
+                    }
+                    _ => {
+                        println!("[Api] Refused a client without the token.");
+                        let _ = write_line(&client.writer, &failure("A hello with the token has to come first."));
+                        break;
+                    }
+                }
+            }
+            let reply = client.handle(command);
+            if write_line(&client.writer, &reply).is_err() {
+                break;
+            }
+        }
+        // like any other client, leaving ends the run
+        client.stop_run();
+    }
+
+    /// Serves tools on `listener`, one line of JSON per command and per reply or event.
+    pub fn serve(listener: TcpListener, config: ApiConfig, state: Arc<DaemonState>) {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let state = Arc::clone(&state);
+                    let token = config.token.clone();
+                    let code_dir = config.code_dir.clone();
+                    thread::spawn(move || serve_tool(state, stream, token, code_dir));
+                }
+                Err(e) => {
+                    println!("[Api] accept function failed: {:?}", e);
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::mock_runtime::mock_runtime::{MockRuntime, Script};
+        use std::{
+            fs,
+            time::{Duration, Instant},
+        };
+
+        fn start_api(token: Option<&str>, code_dir: &Path) -> SocketAddr {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            let state = Arc::new(DaemonState::new("unused.sock"));
+            let config = ApiConfig {
+                bind: addr,
+                token: token.map(str::to_string),
+                code_dir: code_dir.to_path_buf(),
+            };
+            thread::spawn(move || serve(listener, config, state));
+            addr
+        }
+
+        struct Tool {
+            writer: TcpStream,
+            reader: BufReader<TcpStream>,
+        }
+
+        impl Tool {
+            fn connect(addr: SocketAddr) -> Tool {
+                let writer = TcpStream::connect(addr).unwrap();
+                writer.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+                let reader = BufReader::new(writer.try_clone().unwrap());
+                Tool { writer, reader }
+            }
+
+            fn next(&mut self) -> Value {
+                let mut line = String::new();
+                self.reader.read_line(&mut line).unwrap();
+                serde_json::from_str(&line).unwrap_or(Value::Null)
+            }
+
+            // sends a command and returns its reply, setting aside the events that came first
+            fn command(&mut self, command: Value, events: &mut Vec<Value>) -> Value {
+                self.writer.write_all(format!("{}\n", command).as_bytes()).unwrap();
+                loop {
+                    let line = self.next();
+                    if line.get("event").is_none() {
+                        return line;
+                    }
+                    events.push(line);
+                }
+            }
+        }
+
+        #[test]
+        fn config_needs_a_token_off_localhost() {
+            let base = Path::new("/home/student");
+            let ini = Ini::load_from_str("[api]\nenabled = true\n").unwrap();
+            assert_eq!(
+                ApiConfig::from_ini(&ini, base).unwrap(),
+                Some(ApiConfig {
+                    bind: DEFAULT_API_ADDR.parse().unwrap(),
+                    token: None,
+                    code_dir: base.to_path_buf(),
+                })
+            );
+            let elsewhere = Ini::load_from_str("[api]\nenabled = true\ncode_dir = robot/code\n").unwrap();
+            assert_eq!(
+                ApiConfig::from_ini(&elsewhere, base).unwrap().unwrap().code_dir,
+                Path::new("/home/student/robot/code")
+            );
+            let off = Ini::load_from_str("[api]\nbind = 0.0.0.0:9000\n").unwrap();
+            assert_eq!(ApiConfig::from_ini(&off, base).unwrap(), None);
+            let open = Ini::load_from_str("[api]\nenabled = true\nbind = 0.0.0.0:9000\n").unwrap();
+            assert!(ApiConfig::from_ini(&open, base).unwrap_err().contains("token"));
+            let locked = Ini::load_from_str("[api]\nenabled = true\nbind = 0.0.0.0:9000\ntoken = hunter2\n").unwrap();
+            assert_eq!(
+                ApiConfig::from_ini(&locked, base).unwrap().unwrap().token.as_deref(),
+                Some("hunter2")
+            );
+            let bad = Ini::load_from_str("[api]\nenabled = true\nbind = robot\n").unwrap();
+            assert!(ApiConfig::from_ini(&bad, base).is_err());
+        }
+
+        #[test]
+        fn uploads_stay_under_the_code_dir() {
+            let root = std::env::temp_dir().join(format!("daybreak-api-code-{}", std::process::id()));
+            let code_dir = root.join("code");
+            fs::create_dir_all(&code_dir).unwrap();
+            fs::write(code_dir.join("student_code.py"), "print('hi')\n").unwrap();
+            fs::write(root.join("secret.txt"), "hunter2\n").unwrap();
+            let addr = start_api(None, &code_dir);
+            let mut events = vec![];
+            let mut tool = Tool::connect(addr);
+
+            for path in ["../secret.txt".to_string(), root.join("secret.txt").display().to_string()] {
+                let reply = tool.command(json!({ "cmd": "upload", "path": path }), &mut events);
+                assert_eq!(reply["ok"], false);
+                assert!(reply["error"].as_str().unwrap().contains("isn't under"), "{}", reply);
+            }
+            // code that is under it gets as far as noticing there's no robot
+            let reply = tool.command(json!({ "cmd": "upload", "path": "student_code.py" }), &mut events);
+            assert_eq!(reply, json!({ "ok": false, "error": "No robot is connected." }));
+            let _ = fs::remove_dir_all(&root);
+        }
+
+        #[test]
+        fn tools_need_the_token_first() {
+            let addr = start_api(Some("hunter2"), &std::env::temp_dir());
+            let mut events = vec![];
+
+            let mut stranger = Tool::connect(addr);
+            let reply = stranger.command(json!({ "cmd": "devices" }), &mut events);
+            assert_eq!(reply["ok"], false);
+            let mut line = String::new();
+            assert_eq!(stranger.reader.read_line(&mut line).unwrap(), 0, "still open");
+
+            let mut guesser = Tool::connect(addr);
+            let reply = guesser.command(json!({ "cmd": "hello", "token": "hunter3" }), &mut events);
+            assert_eq!(reply["ok"], false);
+
+            let mut friend = Tool::connect(addr);
+            let reply = friend.command(json!({ "cmd": "hello", "token": "hunter2" }), &mut events);
+            assert_eq!(reply["ok"], true);
+            let reply = friend.command(json!({ "cmd": "devices" }), &mut events);
+            assert_eq!(reply, json!({ "ok": true, "devices": [] }));
+            let reply = friend.command(json!({ "cmd": "run", "mode": "sideways" }), &mut events);
+            assert_eq!(reply["ok"], false);
+        }
+
+        #[test]
+        fn json_commands_drive_the_mock_runtime() {
+            let mock = MockRuntime::bind("127.0.0.1:0", Script::default()).unwrap();
+            let port = mock.port();
+            let recorded = mock.recorded();
+            mock.spawn();
+            let addr = start_api(None, &std::env::temp_dir());
+            let session = format!("api-{}", std::process::id());
+            let mut tool = Tool::connect(addr);
+            let mut events = vec![];
+
+            let reply = tool.command(json!({ "cmd": "hello", "session": session }), &mut events);
+            assert_eq!(reply["ok"], true);
+            assert_eq!(tool.command(json!({ "cmd": "subscribe" }), &mut events)["ok"], true);
+            let reply = tool.command(
+                json!({ "cmd": "connect", "ip": "127.0.0.1", "options": { "port": port, "retries": "0" } }),
+                &mut events,
+            );
+            assert_eq!(reply, json!({ "ok": true }));
+
+            let deadline = Instant::now() + Duration::from_secs(5);
+            let devices = loop {
+                let reply = tool.command(json!({ "cmd": "devices" }), &mut events);
+                if reply["devices"].as_array().is_some_and(|devices| !devices.is_empty()) {
+                    break reply["devices"].clone();
+                }
+                assert!(Instant::now() < deadline, "no devices");
+                thread::sleep(Duration::from_millis(50));
+            };
+            let names: Vec<&str> = devices.as_array().unwrap().iter().map(|device| device["name"].as_str().unwrap()).collect();
+            let expected: Vec<String> = Script::default().devices.into_iter().map(|device| device.name).collect();
+            assert_eq!(names, expected);
+
+            assert_eq!(tool.command(json!({ "cmd": "run", "mode": "teleop" }), &mut events)["ok"], true);
+            let reply = tool.command(json!({ "cmd": "status" }), &mut events);
+            assert_eq!(reply["status"]["robot_type"], "potato");
+            assert_eq!(reply["status"]["run_mode"], "teleop");
+            let reply = tool.command(json!({ "cmd": "gamepad", "buttons": 5, "axes": [0.5, 0.0, 0.0, -0.5] }), &mut events);
+            assert_eq!(reply["ok"], true);
+            while recorded.lock().unwrap().inputs.is_empty() {
+                assert!(Instant::now() < deadline + Duration::from_secs(5), "no inputs reached Runtime");
+                thread::sleep(Duration::from_millis(20));
+            }
+            assert_eq!(recorded.lock().unwrap().inputs[0].inputs[0].buttons, 5);
+            assert_eq!(tool.command(json!({ "cmd": "run", "mode": "stop" }), &mut events)["ok"], true);
+
+            // the subscription saw the connection and both run modes go by
+            while !events.contains(&json!({ "event": "run_mode", "mode": "stop" })) {
+                assert!(Instant::now() < deadline + Duration::from_secs(10), "only saw {:?}", events);
+                events.push(tool.next());
+            }
+            assert!(events.contains(&json!({ "event": "connection", "state": { "connected": { "ip": "127.0.0.1" } } })));
+            assert!(events.contains(&json!({ "event": "run_mode", "mode": "teleop" })));
+            assert!(events.iter().any(|event| event["event"] == "devices"));
+        }
+    }
+}
@@ -1,609 +1,961 @@
 pub mod daemonhandler {
-    use crate::{
-        daemon::daemonhandler,
-        robot::robotmanager::{
-            run_mode::{Mode, RunMode},
-            Robot,
-        },
+    use crate::ipc::ipc::{
+        hello, receive, send, ConnectionState, DaemonError, DaemonStatus, Event, Request, Response, RobotType, RunCommand,
+        StartSide, UploadProgress, PROTOCOL_VERSION,
+    };
+    use crate::control_api::control_api::{self, ApiConfig};
+    use crate::error::error::DaybreakError;
+    use crate::robot::robotmanager::{
+        config_path, daemon_pid_path, daemon_socket_path, is_valid_session_name, robot_socket_path, state_dir,
+        GameAlert, LatencyStats, LinkState, Robot, RobotReply, RobotRequest, RuntimeStatus,
     };
-    use protobuf::{EnumOrUnknown, SpecialFields};
+    use crate::run_log::run_log::{decode_log_lines, encode_log_lines};
+    use crate::ssh::ssh::{known_hosts_path, HostKey, HostTrust, SshLogin};
     use ssh2::Session;
     use std::{
-        borrow::BorrowMut,
-        collections::LinkedList,
+        collections::HashMap,
         fs,
-        io::{Read, Write},
-        os::unix::net::{UnixListener, UnixStream},
-        path::Path,
-        process::exit,
-        sync::{Arc, Mutex},
+        io::{self, ErrorKind, Read, Write},
+        net::TcpListener,
+        os::{
+            fd::{AsRawFd, FromRawFd},
+            unix::net::{UnixListener, UnixStream},
+        },
+        path::{Path, PathBuf},
+        ptr,
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            mpsc::{self, Receiver, RecvTimeoutError, Sender},
+            Arc, Mutex, MutexGuard, PoisonError, Weak,
+        },
         thread,
+        time::{Duration, Instant},
     };
-    pub enum MsgDaemonType {
-        Upload = 1,
-        Connect = 2,
-        Run = 3,
-        QueryDevices = 4,
-        Download = 5,
-        InputListener = 6,
-        Kill = 255,
-    }
-    pub fn query_message_daemon_type(message: &Vec<u8>) -> Option<MsgDaemonType> {
-        let message_type = message[0];
-        return match message_type {
-            1 => Some(MsgDaemonType::Upload),
-            2 => Some(MsgDaemonType::Connect),
-            3 => Some(MsgDaemonType::Run),
-            4 => Some(MsgDaemonType::QueryDevices),
-            5 => Some(MsgDaemonType::Download),
-            6 => Some(MsgDaemonType::InputListener),
-            255 => Some(MsgDaemonType::Kill),
-            _ => None,
+    // a quiet subscription still hears from us this often, so a vanished client is noticed
+    const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(1);
+    // how long a robot thread gets to idle Runtime before we stop waiting on it
+    const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+    // how long the accept loop can go without noticing a shutdown
+    const SHUTDOWN_CHECK_INTERVAL: Duration = Duration::from_millis(200);
+    // uploads report progress every this many bytes
+    const UPLOAD_PROGRESS_STEP: u64 = 64 * 1024;
+    const SSH_PORT: u16 = 22;
+    // how long a just-created PID file can stay empty before it counts as left behind
+    const PID_WRITE_GRACE: Duration = Duration::from_millis(500);
+
+    // a worker that panicked mid-request mustn't take every later client down with it
+    fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+        mutex.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // sends a query to the robot thread and reads back its reply
+    fn request_from_robot(robot_socket: &mut UnixStream, request: &RobotRequest) -> Option<RobotReply> {
+        send(robot_socket, request).ok()?;
+        receive(robot_socket).ok()
+    }
+
+    // hands the robot thread something that needs no answer, false when there's no robot thread to take it
+    fn tell_robot(robot_socket: &Mutex<Option<UnixStream>>, request: &RobotRequest) -> bool {
+        match *lock(robot_socket) {
+            Some(ref mut robot_socket) => send(robot_socket, request).is_ok(),
+            None => false,
+        }
+    }
+
+    // pushes the session's events to a subscriber: what the session has now and the log lines after
+    // `since`, then whatever is published from here on
+    fn stream_events(socket: Arc<Mutex<UnixStream>>, session: Arc<Mutex<RobotSession>>, mut since: u64) {
+        let (sender, published) = mpsc::channel();
+        let (robot_socket, mut outgoing) = {
+            let mut session = lock(&session);
+            session.subscribers.push(sender);
+            let mut snapshot = vec![Some(Event::Connection(session.connection.clone()))];
+            snapshot.extend(session.run_mode.map(|mode| Some(Event::RunMode(mode))));
+            if session.has_robot() {
+                snapshot.extend(session.link.map(|link| Some(Event::Link(link))));
+                snapshot.extend(session.devices.clone().map(|devices| Some(Event::Devices(devices))));
+                snapshot.extend(session.runtime_status.map(|status| Some(Event::RuntimeStatus(status))));
+                snapshot.extend(session.latency.map(|latency| Some(Event::Latency(latency))));
+                snapshot.extend(session.game_alert.map(|alert| Some(Event::GameAlert(alert))));
+            }
+            (Arc::clone(&session.robot_socket), snapshot)
         };
+        // catch up on the lines the robot thread still has, a batch at a time
+        loop {
+            let batch = match lock(&robot_socket).as_mut() {
+                Some(robot_socket) => request_from_robot(robot_socket, &RobotRequest::Logs { since }),
+                None => None,
+            };
+            let Some(RobotReply::Logs(batch)) = batch else {
+                break;
+            };
+            let Some((head, lines)) = decode_log_lines(&batch) else {
+                break;
+            };
+            if since == u64::MAX {
+                since = head;
+                break;
+            }
+            if head < since {
+                // the robot thread was replaced and counts from 1 again
+                since = 0;
+                continue;
+            }
+            match lines.last() {
+                Some(last) => {
+                    since = last.seq;
+                    outgoing.push(Some(Event::Logs(batch)));
+                    if since >= head {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+        if since == u64::MAX {
+            since = 0;
+        }
+        loop {
+            for event in outgoing.drain(..) {
+                if send(&mut *lock(&socket), &Response::Event(event)).is_err() {
+                    println!("[Daemon @Subscribe] Subscriber left.");
+                    return;
+                }
+            }
+            let event = match published.recv_timeout(KEEPALIVE_INTERVAL) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => {
+                    outgoing.push(None);
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => return,
+            };
+            match event {
+                // lines the catch-up already sent come round again
+                Event::Logs(batch) => {
+                    let Some((head, lines)) = decode_log_lines(&batch) else {
+                        continue;
+                    };
+                    let fresh: Vec<_> = lines.into_iter().filter(|line| line.seq > since).collect();
+                    if let Some(last) = fresh.last() {
+                        since = last.seq;
+                        outgoing.push(Some(Event::Logs(encode_log_lines(head, &fresh))));
+                    }
+                }
+                // a new robot thread numbers its lines from 1
+                Event::Connection(ConnectionState::Connecting { .. } | ConnectionState::Replaying { .. }) => {
+                    since = 0;
+                    outgoing.push(Some(event));
+                }
+                event => outgoing.push(Some(event)),
+            }
+        }
     }
 
-    // create an event queue static variable
-    pub fn main_d() {
-        let temp_dir = std::env::temp_dir().into_os_string().into_string().unwrap();
-        let listener = UnixListener::bind(format!("{}/daybreak.sock", temp_dir));
-        if listener.is_err() {
-            println!("Failed to bind to socket.");
-            exit(1);
+    // hands an event to every subscriber of the session, forgetting the ones that left
+    fn publish(session: &Mutex<RobotSession>, event: Event) {
+        let mut session = lock(session);
+        match event {
+            Event::Connection(ref connection) => session.connection = connection.clone(),
+            Event::RunMode(mode) => session.run_mode = Some(mode),
+            Event::Devices(ref devices) => session.devices = Some(devices.clone()),
+            Event::RuntimeStatus(status) => session.runtime_status = Some(status),
+            Event::Link(link) => session.link = Some(link),
+            Event::Latency(latency) => session.latency = Some(latency),
+            Event::GameAlert(alert) => session.game_alert = Some(alert),
+            _ => {}
         }
-        let listener = listener.unwrap();
+        session
+            .subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
 
-        println!("[Daemon] Listening on {}/daybreak.sock", temp_dir);
+    // a client that hung up doesn't need an answer
+    fn respond(socket: &Mutex<UnixStream>, response: Response) {
+        let _ = send(&mut *lock(socket), &response);
+    }
 
-        let mut robot: Arc<Option<Arc<Robot>>> = Arc::new(None);
-        let mut robot_socket: Arc<Mutex<Option<UnixStream>>> = Arc::new(Mutex::new(None));
-        let mut ip_addr: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
-        let mut robot_type: Arc<Mutex<Option<u8>>> = Arc::new(Mutex::new(None));
-        fn input_listener(
-            socket: Arc<Mutex<UnixStream>>,
-            robot_socket: Arc<Mutex<Option<UnixStream>>>,
-        ) {
-            loop {
-                let mut buffer = [0; 1];
-                let read = socket.lock().unwrap().read(&mut buffer);
-                if read.is_err() {
-                    println!("[Daemon @Run] Failed to read from socket.");
-                    break;
+    // where a session's robot thread gets its frames from
+    enum Source {
+        Runtime { ip: String },
+        Replay,
+    }
+
+    // Everything the daemon tracks for one named robot.
+    #[derive(Default)]
+    struct RobotSession {
+        robot: Option<Arc<Robot>>,
+        robot_socket: Arc<Mutex<Option<UnixStream>>>,
+        source: Option<Source>,
+        robot_type: Option<RobotType>,
+        // the latest the robot thread has reported, kept for queries and new subscribers
+        devices: Option<Vec<u8>>,
+        devices_received: Option<Instant>,
+        runtime_status: Option<RuntimeStatus>,
+        link: Option<LinkState>,
+        latency: Option<LatencyStats>,
+        game_alert: Option<GameAlert>,
+        // start position chosen for this session, re-sent whenever we (re)connect
+        start_pos: Option<StartSide>,
+        // kept so new subscribers start out knowing them
+        connection: ConnectionState,
+        run_mode: Option<RunCommand>,
+        subscribers: Vec<Sender<Event>>,
+        input_listeners: u32,
+    }
+
+    impl RobotSession {
+        // the robot's address, None for replays and disconnected sessions
+        fn ip(&self) -> Option<String> {
+            match &self.source {
+                Some(Source::Runtime { ip }) => Some(ip.clone()),
+                Some(Source::Replay) | None => None,
+            }
+        }
+
+        fn has_robot(&self) -> bool {
+            self.robot.is_some() && lock(&self.robot_socket).is_some()
+        }
+
+        // what the robot thread reported, or None once it's gone
+        fn reported<T: Clone>(&self, report: &Option<T>) -> Option<T> {
+            report.clone().filter(|_| self.has_robot())
+        }
+
+        fn forget_reports(&mut self) {
+            self.devices = None;
+            self.devices_received = None;
+            self.runtime_status = None;
+            self.link = None;
+            self.latency = None;
+            self.game_alert = None;
+        }
+    }
+
+    // keeps the session up to date with what its robot thread reports, passing changes on to subscribers,
+    // until that robot thread is gone or replaced
+    fn forward_robot_events(session: Arc<Mutex<RobotSession>>, robot: Weak<Robot>, events: Receiver<Event>) {
+        for event in events {
+            {
+                let mut session = lock(&session);
+                let current = session.robot.as_ref().is_some_and(|current| ptr::eq(Arc::as_ptr(current), robot.as_ptr()));
+                if !current {
+                    return;
+                }
+                match event {
+                    // Runtime sends devices constantly, so only changes go out
+                    Event::Devices(ref devices) => {
+                        session.devices_received = Some(Instant::now());
+                        if session.devices.as_ref() == Some(devices) {
+                            continue;
+                        }
+                    }
+                    Event::RuntimeStatus(status) if session.runtime_status == Some(status) => continue,
+                    _ => {}
                 }
-                if buffer[0] == 4 {
-                    // socket.lock().unwrap().set_nonblocking(false).unwrap();
-                    // let _dawn_read = robot_socket_clone.lock().unwrap().as_ref().unwrap().set_nonblocking(false);
+            }
+            publish(&session, event);
+        }
+    }
+
+    // a robot for the session whose reports reach its subscribers
+    fn new_robot(state: &DaemonState, name: &str, session: &Arc<Mutex<RobotSession>>) -> Arc<Robot> {
+        let (events, reports) = mpsc::channel();
+        // looked up for every robot, so a state dir fixed while the daemon runs gets used
+        let state_dir = match state_dir() {
+            Ok(dir) => Some(dir),
+            Err(e) => {
+                println!("[Daemon] Nothing will be recorded or persisted by default: {}", e);
+                None
+            }
+        };
+        let robot = Arc::new(Robot {
+            session: name.to_string(),
+            config: state.config.clone(),
+            state: state_dir,
+            events,
+        });
+        let session = Arc::clone(session);
+        let weak = Arc::downgrade(&robot);
+        thread::spawn(move || forward_robot_events(session, weak, reports));
+        robot
+    }
+
+    // what an upload or download needs, copied out so the session isn't locked during the transfer
+    struct SshTarget {
+        ip: String,
+        port: u16,
+        robot_type: RobotType,
+        config: PathBuf,
+    }
+
+    /// Robot sessions shared by every client worker. Each session has its own lock, held only briefly.
+    pub struct DaemonState {
+        socket_path: String,
+        sessions: Mutex<HashMap<String, Arc<Mutex<RobotSession>>>>,
+        started: Instant,
+        clients: AtomicUsize,
+        shutting_down: AtomicBool,
+        // robots always serve ssh on 22, tests point uploads at a server of their own
+        ssh_port: u16,
+        // the daybreak.ini found when the daemon started, since it leaves that directory
+        config: PathBuf,
+    }
+
+    impl DaemonState {
+        pub fn new(socket_path: &str) -> DaemonState {
+            DaemonState {
+                socket_path: socket_path.to_string(),
+                sessions: Mutex::new(HashMap::new()),
+                started: Instant::now(),
+                clients: AtomicUsize::new(0),
+                shutting_down: AtomicBool::new(false),
+                ssh_port: SSH_PORT,
+                config: config_path(),
+            }
+        }
+
+        pub fn with_config(self, config: PathBuf) -> DaemonState {
+            DaemonState { config, ..self }
+        }
+
+        #[cfg(test)]
+        pub fn with_ssh_port(self, ssh_port: u16) -> DaemonState {
+            DaemonState { ssh_port, ..self }
+        }
+
+        fn session(&self, name: &str) -> Arc<Mutex<RobotSession>> {
+            let mut sessions = lock(&self.sessions);
+            Arc::clone(sessions.entry(name.to_string()).or_default())
+        }
+    }
+
+    // stops the session's robot thread, which idles Runtime and closes its link on the way out,
+    // and forgets the robot. False when there was nothing to disconnect.
+    fn disconnect(session: &Mutex<RobotSession>) -> bool {
+        let robot_socket = {
+            let mut session = lock(session);
+            session.robot = None;
+            session.source = None;
+            session.robot_type = None;
+            session.forget_reports();
+            session.run_mode = None;
+            Arc::clone(&session.robot_socket)
+        };
+        let old = lock(&robot_socket).take();
+        let was_connected = old.is_some();
+        if let Some(mut old) = old {
+            let _ = old.set_read_timeout(Some(DISCONNECT_TIMEOUT));
+            if request_from_robot(&mut old, &RobotRequest::Disconnect) != Some(RobotReply::Disconnected) {
+                println!("[Daemon @Disconnect] The robot thread didn't answer, dropping it anyway.");
+            }
+        }
+        publish(session, Event::Connection(ConnectionState::Disconnected));
+        was_connected
+    }
+
+    // the robot thread binds its socket after connecting, so give it a few tries
+    fn attach_robot_socket(session: &str, start_pos: Option<StartSide>) -> Option<UnixStream> {
+        let socket_path = robot_socket_path(session).ok()?;
+        let mut robot_socket_temp = UnixStream::connect(&socket_path);
+        // run multiple attempts
+        for _ in 0..5 {
+            if robot_socket_temp.is_ok() {
+                break;
+            }
+            robot_socket_temp = UnixStream::connect(&socket_path);
+            // wait for 1 second
+            thread::sleep(std::time::Duration::from_secs(1));
+        }
+        if robot_socket_temp.is_err() {
+            println!("[Daemon] Failed to connect to robot socket.");
+            return None;
+        }
+        let mut robot_socket_temp = robot_socket_temp.unwrap();
+        if let Some(side) = start_pos {
+            println!("[Daemon] Re-sending start position to robot.");
+            let _ = send(&mut robot_socket_temp, &RobotRequest::SetStartPos(side));
+        }
+        Some(robot_socket_temp)
+    }
+
+    // answers the client's Hello and returns the session it picked
+    fn greet(socket: &mut UnixStream) -> Option<String> {
+        let hello = receive::<Request>(socket);
+        let (version, session) = match hello {
+            Ok(Request::Hello { version, session }) => (version, session),
+            Ok(other) => {
+                println!("[Daemon] Expected a handshake, got {:?}", other);
+                let _ = send(
+                    socket,
+                    &Response::Error(DaemonError::Unexpected("a request before the handshake".to_string())),
+                );
+                return None;
+            }
+            // a liveness probe from another daemon hangs up without a word
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return None,
+            Err(e) => {
+                println!("[Daemon] Failed to read handshake: {}", e);
+                return None;
+            }
+        };
+        if version != PROTOCOL_VERSION {
+            println!("[Daemon] Client speaks protocol version {}, refusing.", version);
+            let _ = send(
+                socket,
+                &Response::Error(DaemonError::VersionMismatch {
+                    daemon: PROTOCOL_VERSION,
+                    client: version,
+                }),
+            );
+            return None;
+        }
+        if !is_valid_session_name(&session) {
+            println!("[Daemon] Invalid session name.");
+            let _ = send(socket, &Response::Error(DaemonError::InvalidSession(session)));
+            return None;
+        }
+        send(socket, &Response::Hello { version: PROTOCOL_VERSION }).ok()?;
+        Some(session)
+    }
+
+    // opens an ssh session to the robot without trusting or logging in to it yet
+    fn ssh_handshake(target: &SshTarget) -> Result<(Session, HostKey), DaemonError> {
+        let address = format!("{}:{}", target.ip, target.port);
+        let tcp = std::net::TcpStream::connect((target.ip.as_str(), target.port))
+            .map_err(|e| DaemonError::SshConnect(format!("{}: {}", address, e)))?;
+        let mut sess = Session::new().map_err(|e| DaemonError::SshConnect(e.to_string()))?;
+        sess.set_tcp_stream(tcp);
+        sess.handshake()
+            .map_err(|e| DaemonError::SshConnect(format!("handshake with {} failed: {}", address, e)))?;
+        let host_key = HostKey::of(&sess, &target.ip, target.port)
+            .ok_or_else(|| DaemonError::SshConnect(format!("{} sent no host key", address)))?;
+        Ok((sess, host_key))
+    }
+
+    // opens an ssh session to a trusted robot with the login daybreak.ini gives it
+    fn ssh_login(target: &SshTarget) -> Result<Session, DaemonError> {
+        let (sess, host_key) = ssh_handshake(target)?;
+        let (host, fingerprint) = (host_key.host(), host_key.fingerprint.clone());
+        match host_key.check(&known_hosts_path()).map_err(DaemonError::LocalFile)? {
+            HostTrust::Trusted => {}
+            HostTrust::Unknown => return Err(DaemonError::UntrustedHost { host, fingerprint }),
+            HostTrust::Mismatch => return Err(DaemonError::HostKeyMismatch { host, fingerprint }),
+        }
+        SshLogin::load(&target.config, &target.ip, target.robot_type)
+            .authenticate(&sess)
+            .map_err(DaemonError::SshAuth)?;
+        Ok(sess)
+    }
+
+    // adds the robot's host key to known_hosts, or says why it won't
+    fn trust(target: &SshTarget, expected: Option<&str>, replace: bool) -> Result<Response, DaemonError> {
+        let (_sess, host_key) = ssh_handshake(target)?;
+        let mismatch = || DaemonError::HostKeyMismatch {
+            host: host_key.host(),
+            fingerprint: host_key.fingerprint.clone(),
+        };
+        let bare = |fingerprint: &str| fingerprint.trim().trim_start_matches("SHA256:").to_string();
+        if expected.is_some_and(|expected| bare(expected) != bare(&host_key.fingerprint)) {
+            return Err(mismatch());
+        }
+        let path = known_hosts_path();
+        let added = match host_key.check(&path).map_err(DaemonError::LocalFile)? {
+            HostTrust::Trusted => false,
+            HostTrust::Mismatch if !replace => return Err(mismatch()),
+            HostTrust::Unknown | HostTrust::Mismatch => {
+                host_key.trust(&path).map_err(DaemonError::LocalFile)?;
+                true
+            }
+        };
+        Ok(Response::Trusted {
+            fingerprint: host_key.fingerprint,
+            added,
+        })
+    }
+
+    // where the robot keeps student code
+    fn remote_code_path(sess: &Session) -> Result<String, DaemonError> {
+        let remote_error = |e: &dyn std::fmt::Display| DaemonError::RemoteFile(format!("couldn't find the home directory: {}", e));
+        let mut home_dir = sess.channel_session().map_err(|e| remote_error(&e))?;
+        home_dir.exec("echo $HOME").map_err(|e| remote_error(&e))?;
+        let mut home_dir_str = String::new();
+        home_dir.read_to_string(&mut home_dir_str).map_err(|e| remote_error(&e))?;
+        home_dir_str = home_dir_str.trim().to_string();
+        println!("[Daemon] Remote home directory: {}", home_dir_str);
+        Ok(format!("{}/runtime/executor/studentcode.py", home_dir_str))
+    }
+
+    fn upload(
+        target: &SshTarget,
+        file_path: &Path,
+        mut progress: impl FnMut(UploadProgress),
+    ) -> Result<(), DaemonError> {
+        let sess = ssh_login(target)?;
+        let path = remote_code_path(&sess)?;
+        let file = std::fs::File::open(file_path);
+        if file.is_err() {
+            println!("[Daemon @Upload] Failed to open local file.");
+            return Err(DaemonError::LocalFile(file.err().unwrap().to_string()));
+        }
+        let mut file = file.unwrap();
+        let total = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        progress(UploadProgress::Started { total });
+        let remote_file = sess.scp_send(Path::new(&path), 0o644, total, None);
+        if remote_file.is_err() {
+            println!("[Daemon @Upload] Failed to send file.");
+            return Err(DaemonError::RemoteFile(remote_file.err().unwrap().to_string()));
+        }
+        let mut remote_file = remote_file.unwrap();
+        let mut buffer = [0; 1024];
+        let mut sent = 0;
+        loop {
+            let read = file.read(&mut buffer);
+            if read.is_err() {
+                println!("[Daemon @Upload] Failed to read from file.");
+                return Err(DaemonError::LocalFile(read.err().unwrap().to_string()));
+            }
+            let read = read.unwrap();
+            if read == 0 {
+                break;
+            }
+            if let Err(e) = remote_file.write_all(&buffer[..read]) {
+                return Err(DaemonError::RemoteFile(e.to_string()));
+            }
+            sent += read as u64;
+            if sent % UPLOAD_PROGRESS_STEP < read as u64 || sent == total {
+                progress(UploadProgress::Sent { sent, total });
+            }
+        }
+        let _ = remote_file.send_eof();
+        let _ = remote_file.wait_eof();
+        let _ = remote_file.wait_close();
+        Ok(())
+    }
+
+    fn download(target: &SshTarget, file_path: &Path) -> Result<(), DaemonError> {
+        let sess = ssh_login(target)?;
+        let path = remote_code_path(&sess)?;
+        let remote_file = sess.scp_recv(Path::new(&path));
+        if remote_file.is_err() {
+            println!("[Daemon @Download] Failed to get file.");
+            return Err(DaemonError::RemoteFile(remote_file.err().unwrap().to_string()));
+        }
+        let mut remote_file = remote_file.unwrap();
+        let mut buffer = Vec::new();
+        if let Err(e) = remote_file.0.read_to_end(&mut buffer) {
+            println!("[Daemon @Download] Failed to read from file.");
+            return Err(DaemonError::RemoteFile(e.to_string()));
+        }
+        println!("Path: {:?}", file_path);
+        if let Err(e) = std::fs::write(file_path, &buffer) {
+            println!("[Daemon @Download] Failed to write local file.");
+            return Err(DaemonError::LocalFile(e.to_string()));
+        }
+        Ok(())
+    }
+
+    fn input_listener(
+        socket: Arc<Mutex<UnixStream>>,
+        robot_socket: Arc<Mutex<Option<UnixStream>>>,
+        session: &Mutex<RobotSession>,
+    ) {
+        lock(session).input_listeners += 1;
+        loop {
+            let request = receive::<Request>(&mut *lock(&socket));
+            match request {
+                Ok(Request::Input(input)) => {
+                    tell_robot(&robot_socket, &RobotRequest::Input(input));
+                }
+                Ok(Request::StopRun) => {
                     println!("[Daemon @Run] Received message from client. Ending loop.");
                     break;
                 }
+                Ok(other) => {
+                    println!("[Daemon @Run] Ignoring {:?} during a run.", other);
+                }
+                Err(_) => {
+                    println!("[Daemon @Run] Failed to read from socket.");
+                    break;
+                }
+            }
+        }
 
-                if buffer[0] == 5 {
-                    let mut length_bytes = [0u8; 2];
-                    let _length_read = socket.lock().unwrap().read_exact(&mut length_bytes);
-                    if _length_read.is_err() {
-                        println!("[Daemon @Run] Failed to read length bytes.");
-                        continue;
-                    }
+        println!("[Daemon @Run] Input loop ended.");
+        // signal the robot to stop
+        tell_robot(&robot_socket, &RobotRequest::Run(RunCommand::Stop));
+        lock(session).input_listeners -= 1;
+        publish(session, Event::RunMode(RunCommand::Stop));
+    }
 
-                    // Convert length bytes to u16 (little endian)
-                    let msg_length = u16::from_le_bytes(length_bytes) as usize;
+    // where uploads and downloads go, None when the session has no robot
+    fn ssh_target(session: &RobotSession, state: &DaemonState) -> Option<SshTarget> {
+        Some(SshTarget {
+            ip: session.ip()?,
+            port: state.ssh_port,
+            robot_type: session.robot_type?,
+            config: state.config.clone(),
+        })
+    }
 
-                    // Read payload of specified length
-                    let mut payload = vec![0u8; msg_length];
-                    let _payload_read = socket.lock().unwrap().read_exact(&mut payload);
-                    if _payload_read.is_err() {
-                        continue;
-                    }
+    // counts a client for as long as its worker is around
+    struct Counted<'a>(&'a AtomicUsize);
 
-                    robot_socket
-                        .lock()
-                        .unwrap()
-                        .as_ref()
-                        .unwrap()
-                        .write(&[5])
-                        .unwrap();
-                    robot_socket
-                        .lock()
-                        .unwrap()
-                        .as_ref()
-                        .unwrap()
-                        .write(&length_bytes)
-                        .unwrap();
-                    robot_socket
-                        .lock()
-                        .unwrap()
-                        .as_ref()
-                        .unwrap()
-                        .write(&payload)
-                        .unwrap();
-                    robot_socket
-                        .lock()
-                        .unwrap()
-                        .as_ref()
-                        .unwrap()
-                        .flush()
-                        .unwrap();
-                }
-            }
-
-            println!("[Daemon @Run] Input loop ended.");
-            // signal the robot to stop
-            robot_socket
-                .lock()
-                .unwrap()
-                .as_ref()
-                .unwrap()
-                .write(&[2])
-                .unwrap();
-            robot_socket
-                .lock()
-                .unwrap()
-                .as_ref()
-                .unwrap()
-                .flush()
-                .unwrap();
+    impl Drop for Counted<'_> {
+        fn drop(&mut self) {
+            self.0.fetch_sub(1, Ordering::SeqCst);
         }
-        loop {
-            match listener.accept() {
-                Ok((socket, addr)) => {
-                    let socket = Arc::new(Mutex::new(socket));
-                    let robot_socket_clone = Arc::clone(&robot_socket);
-                    // handle the connection
-                    // println!("[Daemon] Accepted connection from {:?}", addr);
-                    // read the message
-                    let mut buffer = [0; 1];
-                    let _dawn_read = socket.lock().unwrap().read(&mut buffer);
-                    if _dawn_read.is_err() {
-                        println!("[Daemon] Failed to read from socket.");
-                        exit(1);
+    }
+
+    fn status(state: &DaemonState, name: &str, session: &Mutex<RobotSession>) -> DaemonStatus {
+        let session = lock(session);
+        DaemonStatus {
+            pid: std::process::id(),
+            uptime_ms: state.started.elapsed().as_millis() as u64,
+            clients: state.clients.load(Ordering::SeqCst).saturating_sub(1) as u32,
+            session: name.to_string(),
+            connection: session.connection.clone(),
+            robot_ip: session.ip(),
+            robot_type: session.robot_type,
+            link: session.reported(&session.link),
+            run_mode: session.run_mode,
+            start_pos: session.start_pos,
+            input_listeners: session.input_listeners,
+            device_age_ms: session
+                .reported(&session.devices_received)
+                .map(|received| received.elapsed().as_millis() as u64),
+        }
+    }
+
+    /// Answers one client: the handshake, then its request.
+    fn serve_client(state: Arc<DaemonState>, mut socket: UnixStream) {
+        state.clients.fetch_add(1, Ordering::SeqCst);
+        let _counted = Counted(&state.clients);
+        // every connection opens with a handshake that also picks the session
+        let name = greet(&mut socket);
+        if name.is_none() {
+            return;
+        }
+        let name = name.unwrap();
+        let request = receive::<Request>(&mut socket);
+        if request.is_err() {
+            println!("[Daemon] Failed to read from socket.");
+            return;
+        }
+        let socket = Arc::new(Mutex::new(socket));
+        let session = state.session(&name);
+        let robot_socket_clone = Arc::clone(&lock(&session).robot_socket);
+
+        match request.unwrap() {
+            Request::Shutdown => {
+                println!("[Daemon] Received kill message. Gracefully exiting.");
+                // leave every robot idle rather than running with nobody to stop it
+                let sessions: Vec<_> = lock(&state.sessions).values().cloned().collect();
+                for session in sessions {
+                    disconnect(&session);
+                }
+                if let Ok(pid_path) = daemon_pid_path() {
+                    remove_runtime_files(&pid_path, &state.socket_path);
+                }
+                println!("[Daemon] Deleted socket file.");
+
+                respond(&socket, Response::Ok);
+                state.shutting_down.store(true, Ordering::SeqCst);
+            }
+            Request::Hello { .. } => {
+                println!("[Daemon] Client said hello twice.");
+                respond(
+                    &socket,
+                    Response::Error(DaemonError::Unexpected("a second handshake".to_string())),
+                );
+            }
+            request @ (Request::Input(_) | Request::StopRun) => {
+                println!("[Daemon] {:?} outside of a run.", request);
+                respond(
+                    &socket,
+                    Response::Error(DaemonError::Unexpected("input outside of a run".to_string())),
+                );
+            }
+            Request::Subscribe { since } => {
+                println!("[Daemon @Subscribe] New subscriber for session {:?}", name);
+                stream_events(socket, session, since);
+            }
+            Request::Disconnect => {
+                println!("[Daemon @Disconnect] Disconnecting session {:?}", name);
+                if disconnect(&session) {
+                    respond(&socket, Response::Ok);
+                } else {
+                    respond(&socket, Response::Error(DaemonError::NoRobot));
+                }
+            }
+            Request::Replay { path } => {
+                println!("[Daemon @Replay] Replaying {:?} into session {:?}", path, name);
+                // one robot thread per session, so the old one has to let go first
+                disconnect(&session);
+                let robot = new_robot(&state, &name, &session);
+                {
+                    // a replay can't take uploads or downloads
+                    let mut session = lock(&session);
+                    session.source = Some(Source::Replay);
+                    session.robot_type = None;
+                    session.runtime_status = None;
+                    session.run_mode = None;
+                    session.robot = Some(Arc::clone(&robot));
+                }
+                if let Err(e) = robot.replay(&path) {
+                    println!("[Daemon @Replay] Failed to replay {}: {}", path, e);
+                    publish(&session, Event::Connection(ConnectionState::Disconnected));
+                    respond(&socket, Response::Error(DaemonError::Replay(e.to_string())));
+                    return;
+                }
+                let robot_socket_temp = attach_robot_socket(&name, None);
+                let response = if robot_socket_temp.is_some() {
+                    publish(&session, Event::Connection(ConnectionState::Replaying { path }));
+                    Response::Ok
+                } else {
+                    publish(&session, Event::Connection(ConnectionState::Disconnected));
+                    Response::Error(DaemonError::Replay(
+                        "the robot thread never came up".to_string(),
+                    ))
+                };
+                *lock(&robot_socket_clone) = robot_socket_temp;
+                respond(&socket, response);
+            }
+            Request::Connect {
+                ip,
+                robot_type,
+                options,
+            } => {
+                println!("[Daemon] Received connect message.");
+                let ip = ip.trim();
+                println!("[Daemon] Received IP: {:?} for session {:?}", ip, name);
+                respond(&socket, Response::Started);
+                // one robot thread per session, so the old one has to let go first
+                disconnect(&session);
+                let robot = new_robot(&state, &name, &session);
+                let start_pos = {
+                    let mut session = lock(&session);
+                    session.source = Some(Source::Runtime { ip: ip.to_string() });
+                    session.runtime_status = None;
+                    session.run_mode = None;
+                    session.robot = Some(Arc::clone(&robot));
+                    session.start_pos
+                };
+                publish(&session, Event::Connection(ConnectionState::Connecting { ip: ip.to_string() }));
+
+                println!("[Daemon] Connecting with {:?}", options);
+                if let Err(e) = robot.connect(ip, options) {
+                    println!("[Daemon] Failed to connect to robot.");
+                    publish(&session, Event::Connection(ConnectionState::Failed(e)));
+                    respond(&socket, Response::Error(DaemonError::Connect(e)));
+                    return;
+                }
+                println!("[Daemon] Successfully connected to robot. Connecting to robot socket.");
+                println!("Robot Type: {:?}", robot_type);
+                lock(&session).robot_type = Some(robot_type);
+                let robot_socket_temp = attach_robot_socket(&name, start_pos);
+                if robot_socket_temp.is_none() {
+                    publish(&session, Event::Connection(ConnectionState::Disconnected));
+                    respond(&socket, Response::Error(DaemonError::NoRobot));
+                    return;
+                }
+                *lock(&robot_socket_clone) = robot_socket_temp;
+                publish(&session, Event::Connection(ConnectionState::Connected { ip: ip.to_string() }));
+                respond(&socket, Response::Ok);
+            }
+            Request::Download { cwd, path } => {
+                println!("[Daemon] Download event caught!");
+                let target = ssh_target(&lock(&session), &state);
+                if target.is_none() {
+                    println!("[Daemon @Download] No available Robot.");
+                    respond(&socket, Response::Error(DaemonError::NoRobot));
+                    return;
+                }
+                println!("[Daemon @Download] Received file path: {:?}", path);
+                println!("[Daemon @Download] CWD: {:?}", cwd);
+
+                // combine the cwd and the file path to get the full path
+                let full_path = format!("{}/{}", cwd, path);
+                println!("[Daemon @Download] Full path: {:?}", full_path);
+                respond(&socket, Response::Started);
+                match download(&target.unwrap(), Path::new(&full_path)) {
+                    Ok(()) => {
+                        respond(&socket, Response::Ok);
+                        println!("[Daemon @Download] File has been downloaded.");
                     }
+                    Err(e) => {
+                        println!("[Daemon @Download] {}", e);
+                        respond(&socket, Response::Error(e));
+                    }
+                }
+            }
+            Request::Trust { fingerprint, replace } => {
+                let Some(target) = ssh_target(&lock(&session), &state) else {
+                    println!("[Daemon @Trust] No available Robot.");
+                    respond(&socket, Response::Error(DaemonError::NoRobot));
+                    return;
+                };
+                match trust(&target, fingerprint.as_deref(), replace) {
+                    Ok(response) => {
+                        println!("[Daemon @Trust] {:?}", response);
+                        respond(&socket, response);
+                    }
+                    Err(e) => {
+                        println!("[Daemon @Trust] {}", e);
+                        respond(&socket, Response::Error(e));
+                    }
+                }
+            }
+            Request::Upload { cwd, path } => {
+                let target = ssh_target(&lock(&session), &state);
+                if target.is_none() {
+                    println!("[Daemon @Upload] No available Robot.");
+                    respond(&socket, Response::Error(DaemonError::NoRobot));
+                    return;
+                }
 
-                    let message_type = query_message_daemon_type(&buffer.to_vec());
-                    if message_type.is_none() {
-                        println!("[Daemon] Unknown message type: {:?}", buffer[0]);
-                        continue;
+                // combine the cwd and the file path to get the full path
+                let full_path = format!("{}/{}", cwd, path);
+                println!("[Daemon @Upload] Full path: {:?}", full_path);
+                let file_path = Path::new(&full_path);
+                if !file_path.exists() {
+                    println!("[Daemon @Upload] File does not exist.");
+                    respond(&socket, Response::Error(DaemonError::FileNotFound(full_path)));
+                    return;
+                }
+                respond(&socket, Response::Started);
+                let progress = |step| publish(&session, Event::Upload(step));
+                match upload(&target.unwrap(), file_path, progress) {
+                    Ok(()) => {
+                        publish(&session, Event::Upload(UploadProgress::Done));
+                        respond(&socket, Response::Ok);
+                        println!("[Daemon @Upload] File has been uploaded.");
+                    }
+                    Err(e) => {
+                        println!("[Daemon @Upload] {}", e);
+                        publish(&session, Event::Upload(UploadProgress::Failed(e.clone())));
+                        respond(&socket, Response::Error(e));
                     }
+                }
+            }
+            Request::Run(command) => {
+                if !lock(&session).has_robot() {
+                    println!("[Daemon] No Robot Available.");
+                    respond(&socket, Response::Error(DaemonError::NoRobot));
+                    return;
+                }
+                tell_robot(&robot_socket_clone, &RobotRequest::Run(command));
 
-                    match message_type.unwrap() {
-                        MsgDaemonType::Kill => {
-                            println!("[Daemon] Received kill message. Gracefully exiting.");
-                            // delete the socket file
-                            let _ = std::fs::remove_file(format!("{}/daybreak.sock", temp_dir));
-                            println!("[Daemon] Deleted socket file.");
+                println!("[Daemon] Sent run message to robot.");
+                publish(&session, Event::RunMode(command));
+                // now hold the socket until the robot is done running
+                respond(&socket, Response::Ok);
+                println!("[Daemon @Run] Waiting for robot to finish running.");
+                input_listener(socket, robot_socket_clone, &session);
+            }
+            Request::InputListener => {
+                println!("[Daemon @InputListener] Received Input Listening Request...");
+                if lock(&robot_socket_clone).is_none() {
+                    println!("[Daemon @InputListener] Request failed.");
+                    respond(&socket, Response::Error(DaemonError::NoRobot));
+                    return;
+                }
+                respond(&socket, Response::Ok);
+                println!("[Daemon @InputListener] Waiting for robot to finish running.");
+                input_listener(socket, robot_socket_clone, &session);
+            }
+            Request::QueryDevices => {
+                // now with the robot, ask for the devices.
+                let devices = {
+                    let session = lock(&session);
+                    session.reported(&session.devices)
+                };
+                respond(&socket, Response::Devices(devices));
+            }
+            Request::QueryStatus => {
+                let status = {
+                    let session = lock(&session);
+                    session.reported(&session.runtime_status)
+                };
+                respond(&socket, Response::RuntimeStatus(status));
+            }
+            Request::SetStartPos(side) => {
+                let has_robot = {
+                    let mut session = lock(&session);
+                    session.start_pos = Some(side);
+                    session.has_robot()
+                };
+                if !has_robot {
+                    println!("[Daemon @StartPos] No robot available, start position will be sent on connect.");
+                    respond(&socket, Response::Queued);
+                    return;
+                }
+                // a robot thread that went away in the meantime gets it on the next connect, like any other
+                if tell_robot(&robot_socket_clone, &RobotRequest::SetStartPos(side)) {
+                    respond(&socket, Response::Ok);
+                } else {
+                    println!("[Daemon @StartPos] The robot thread is gone, start position will be sent on connect.");
+                    respond(&socket, Response::Queued);
+                }
+            }
+            Request::Status => {
+                let status = status(&state, &name, &session);
+                respond(&socket, Response::Status(status));
+            }
+            Request::QueryLatency => {
+                let latency = {
+                    let session = lock(&session);
+                    session.reported(&session.latency)
+                };
+                respond(&socket, Response::Latency(latency));
+            }
+            Request::QueryGameState => {
+                let alert = {
+                    let session = lock(&session);
+                    session.reported(&session.game_alert)
+                };
+                respond(&socket, Response::GameAlert(alert));
+            }
+            Request::QueryLink => {
+                let link = {
+                    let session = lock(&session);
+                    session.reported(&session.link)
+                };
+                respond(&socket, Response::Link(link));
+            }
+        }
+    }
 
-                            let _ = socket.lock().unwrap().write(&[200]);
-                            let _ = socket.lock().unwrap().flush();
-                            exit(0);
-                        }
-                        MsgDaemonType::Connect => {
-                            let mut buf_robo = [0; 1];
-                            let _ = socket.lock().unwrap().read(&mut buf_robo);
-                            let mut buf = [0; 15];
-                            println!("[Daemon] Received connect message.");
-                            let _ = socket.lock().unwrap().read(&mut buf);
-                            let ip = String::from_utf8(buf.to_vec()).unwrap();
-                            let ip = ip.trim_matches(char::from(0));
-                            let ip = ip.trim();
-                            println!("[Daemon] Received IP: {:?}", ip);
-                            let _ = socket.lock().unwrap().write(&[1]);
-                            let _ = socket.lock().unwrap().flush();
-                            ip_addr = Arc::new(Mutex::new(Some(ip.to_string())));
-                            robot = Arc::new(Some(Arc::new(Robot {
-                                // event_queue: LinkedList::new()
-                            })));
-
-                            let state = robot.as_ref().clone().unwrap().connect(&ip);
-                            if state == 200 {
-                                println!("[Daemon] Successfully connected to robot. Connecting to robot socket.");
-                                let mut robot_socket_temp = UnixStream::connect(format!(
-                                    "{}/daybreak.robot.sock",
-                                    temp_dir
-                                ));
-                                println!("Robot Type: {}", buf_robo[0]);
-                                robot_type = Arc::new(Mutex::new(Some(buf_robo[0])));
-                                // run multiple attempts
-                                for _ in 0..5 {
-                                    if robot_socket_temp.is_ok() {
-                                        break;
-                                    }
-                                    robot_socket_temp = UnixStream::connect(format!(
-                                        "{}/daybreak.robot.sock",
-                                        temp_dir
-                                    ));
-                                    // wait for 1 second
-                                    thread::sleep(std::time::Duration::from_secs(1));
-                                }
-                                if robot_socket_temp.is_err() {
-                                    println!("[Daemon] Failed to connect to robot socket.");
-                                    continue;
-                                }
-                                robot_socket =
-                                    Arc::new(Mutex::new(Some(robot_socket_temp.unwrap())));
-                            } else {
-                                println!("[Daemon] Failed to connect to robot.");
-                            }
-                            let _ = socket.lock().unwrap().write(&[state]);
-                            let _ = socket.lock().unwrap().flush();
-                        }
-                        MsgDaemonType::Download => {
-                            println!("[Daemon] Download event caught!");
-                            let mut buffer = [0; 1024];
-                            let _dawn_read = socket.lock().unwrap().read(&mut buffer);
-                            if _dawn_read.is_err() {
-                                println!("[Daemon] Failed to read from socket.");
-                                continue;
-                            }
-
-                            if robot_type.lock().unwrap().is_none() {
-                                println!("[Daemon @Download] Unknown robot type.");
-                                let _ = socket.lock().unwrap().write(&[50]);
-                                let _ = socket.lock().unwrap().flush();
-                                continue;
-                            }
-
-                            if robot_socket.lock().is_err() {
-                                println!("[Daemon @Download] No available Robot.");
-                                let _ = socket.lock().unwrap().write(&[50]);
-                                let _ = socket.lock().unwrap().flush();
-                                continue;
-                            }
-
-                            // read the cwd
-                            let payload_parts = String::from_utf8(buffer.to_vec());
-                            if payload_parts.is_err() {
-                                continue;
-                            }
-                            let payload_parts = payload_parts.unwrap();
-                            let payload_parts = payload_parts.trim_matches(char::from(0));
-                            let payload_parts = payload_parts.trim();
-                            let payload_parts =
-                                payload_parts.split(char::from(0)).collect::<Vec<&str>>();
-                            if payload_parts.len() <= 1 {
-                                continue;
-                            }
-                            let cwd = payload_parts[0];
-                            let file_path = payload_parts[1];
-                            println!("[Daemon @Download] Received file path: {:?}", file_path);
-                            println!("[Daemon @Download] CWD: {:?}", cwd);
-
-                            // combine the cwd and the file path to get the full path
-                            let full_path = format!("{}/{}", cwd, file_path);
-                            let full_path = full_path.as_str();
-                            println!("[Daemon @Download] Full path: {:?}", full_path);
-                            let file_path = std::path::Path::new(full_path);
-                            // if the file exists and was requested, send a 200
-                            let _ = socket.lock().unwrap().write(&[200]);
-                            let _ = socket.lock().unwrap().flush();
-                            // connect over ssh
-                            let tcp = std::net::TcpStream::connect(format!(
-                                "{}:22",
-                                ip_addr.lock().unwrap().as_ref().unwrap()
-                            ));
-                            if tcp.is_err() {
-                                println!("[Daemon @Upload] Failed to connect to IP address.");
-                                let _ = socket.lock().unwrap().write(&[101]);
-                                let _ = socket.lock().unwrap().flush();
-                                continue;
-                            }
-                            let tcp = tcp.unwrap();
-                            let mut sess = Session::new().unwrap();
-                            sess.set_tcp_stream(tcp);
-                            sess.handshake().unwrap();
-                            let password_pair = if robot_type.lock().unwrap().unwrap() == 2 {
-                                vec!["pi", "raspberry"]
-                            } else {
-                                vec!["ubuntu", "potato"]
-                            };
-                            let worked = sess.userauth_password(password_pair[0], password_pair[1]);
-                            println!("{:?}", password_pair);
-                            if worked.is_err() {
-                                println!("[Daemon @Download] Failed to authenticate.");
-                                let _ = socket.lock().unwrap().write(&[101]);
-                                let _ = socket.lock().unwrap().flush();
-                                continue;
-                            }
-                            let mut home_dir = sess.channel_session().unwrap();
-                            home_dir.exec("echo $HOME").unwrap();
-                            let mut home_dir_str = String::new();
-                            home_dir.read_to_string(&mut home_dir_str).unwrap();
-                            home_dir_str = home_dir_str.trim().to_string();
-                            println!("[Daemon @Upload] Remote home directory: {}", home_dir_str);
-                            let path = format!("{}/runtime/executor/studentcode.py", home_dir_str)
-                                .to_string();
-                            let remote_file = sess.scp_recv(Path::new(&path));
-
-                            if remote_file.is_err() {
-                                println!("[Daemon @Download] Failed to get file.");
-                                let _ = socket.lock().unwrap().write(&[100]);
-                                let _ = socket.lock().unwrap().flush();
-                                continue;
-                            }
-                            let mut remote_file = remote_file.unwrap();
-                            println!("Path: {:?}", file_path);
-                            let file = std::fs::File::create(file_path);
-                            if file.is_err() {
-                                println!("[Daemon @Upload] Failed to open local file.");
-                                let _ = socket.lock().unwrap().write(&[103]);
-                                let _ = socket.lock().unwrap().flush();
-                                continue;
-                            }
-
-                            let mut file = file.unwrap();
-                            let mut buffer = Vec::new();
-
-                            let read = remote_file.0.read_to_end(&mut buffer);
-
-                            if read.is_err() {
-                                println!("[Daemon @Upload] Failed to read from file.");
-                                continue;
-                            }
-                            file.write_all(&buffer).unwrap();
-
-                            let _ = socket.lock().unwrap().write(&[200]);
-                            let _ = socket.lock().unwrap().flush();
-                            // completed upload.
-                            println!("[Daemon @Download] File has been downloaded.");
-                        }
-                        MsgDaemonType::Upload => {
-                            let mut buffer = [0; 1024];
-                            let _dawn_read = socket.lock().unwrap().read(&mut buffer);
-                            if _dawn_read.is_err() {
-                                println!("[Daemon] Failed to read from socket.");
-                                continue;
-                            }
-
-                            if robot_type.lock().unwrap().is_none() {
-                                println!("[Daemon @Upload] Unknown robot type.");
-                                let _ = socket.lock().unwrap().write(&[50]);
-                                let _ = socket.lock().unwrap().flush();
-                                continue;
-                            }
-
-                            if robot_socket.lock().is_err() {
-                                println!("[Daemon @Upload] No available Robot.");
-                                let _ = socket.lock().unwrap().write(&[50]);
-                                let _ = socket.lock().unwrap().flush();
-                                continue;
-                            }
-
-                            // read the cwd
-                            let payload_parts = String::from_utf8(buffer.to_vec()).unwrap();
-                            let payload_parts = payload_parts.trim_matches(char::from(0));
-                            let payload_parts = payload_parts.trim();
-                            let payload_parts =
-                                payload_parts.split(char::from(0)).collect::<Vec<&str>>();
-                            if payload_parts.len() < 1 {
-                                println!("[Daemon @Upload] Bad file");
-                                let _ = socket.lock().unwrap().write(&[50]);
-                                let _ = socket.lock().unwrap().flush();
-                                continue;
-                            }
-                            println!("[Daemon @Upload {:?}]", payload_parts);
-                            // combine the cwd and the file path to get the full path
-                            let full_path = payload_parts.join("/");
-                            let full_path = full_path.as_str();
-                            println!("[Daemon @Upload] Full path: {:?}", full_path);
-                            let file_path = std::path::Path::new(full_path);
-                            if !file_path.exists() {
-                                println!("[Daemon @Upload] File does not exist.");
-                                let _ = socket.lock().unwrap().write(&[100]);
-                                let _ = socket.lock().unwrap().flush();
-                                continue;
-                            }
-                            // if the file exists and was sent, send a 200
-                            let _ = socket.lock().unwrap().write(&[200]);
-                            let _ = socket.lock().unwrap().flush();
-                            // connect over ssh
-                            if ip_addr.lock().unwrap().is_none() {
-                                println!("[Daemon @Upload] Failed to connect to IP address.");
-                                let _ = socket.lock().unwrap().write(&[101]);
-                                let _ = socket.lock().unwrap().flush();
-                                continue;
-                            }
-                            let tcp = std::net::TcpStream::connect(format!(
-                                "{}:22",
-                                ip_addr.lock().unwrap().as_ref().unwrap()
-                            ));
-                            if tcp.is_err() {
-                                println!("[Daemon @Upload] Failed to connect to IP address.");
-                                let _ = socket.lock().unwrap().write(&[101]);
-                                let _ = socket.lock().unwrap().flush();
-                                continue;
-                            }
-                            let tcp = tcp.unwrap();
-                            let mut sess = Session::new().unwrap();
-                            sess.set_tcp_stream(tcp);
-                            sess.handshake().unwrap();
-                            let password_pair = if robot_type.lock().unwrap().unwrap() == 2 {
-                                vec!["pi", "raspberry"]
-                            } else {
-                                vec!["ubuntu", "potato"]
-                            };
-                            let worked = sess.userauth_password(password_pair[0], password_pair[1]);
-                            if worked.is_err() {
-                                println!("[Daemon @Upload] Failed to authenticate.");
-                                let _ = socket.lock().unwrap().write(&[101]);
-                                let _ = socket.lock().unwrap().flush();
-                                continue;
-                            }
-                            // show out the remote path of the file
-                            let mut home_dir = sess.channel_session().unwrap();
-                            home_dir.exec("echo $HOME").unwrap();
-                            let mut home_dir_str = String::new();
-                            home_dir.read_to_string(&mut home_dir_str).unwrap();
-                            home_dir_str = home_dir_str.trim().to_string();
-                            println!("[Daemon @Upload] Remote home directory: {}", home_dir_str);
-                            let path = format!("{}/runtime/executor/studentcode.py", home_dir_str)
-                                .to_string();
-                            let remote_file = sess.scp_send(
-                                Path::new(&path),
-                                0o644,
-                                file_path.metadata().unwrap().len(),
-                                None,
-                            );
-                            if remote_file.is_err() {
-                                println!("[Daemon @Upload] Failed to send file.");
-                                let _ = socket.lock().unwrap().write(&[100]);
-                                let _ = socket.lock().unwrap().flush();
-                                continue;
-                            }
-                            let mut remote_file = remote_file.unwrap();
-                            let file = std::fs::File::open(file_path);
-                            if file.is_err() {
-                                println!("[Daemon @Upload] Failed to open local file.");
-                                let _ = socket.lock().unwrap().write(&[103]);
-                                let _ = socket.lock().unwrap().flush();
-                                continue;
-                            }
-
-                            let mut file = file.unwrap();
-                            let mut buffer = [0; 1024];
-                            let mut failed = false;
-                            loop {
-                                let read = file.read(&mut buffer);
-                                if read.is_err() {
-                                    println!("[Daemon @Upload] Failed to read from file.");
-                                    failed = true;
-                                    continue;
-                                }
-                                let read = read.unwrap();
-                                if read == 0 {
-                                    break;
-                                }
-                                let _ = remote_file.write(&buffer[..read]);
-                            }
-
-                            if failed {
-                                continue;
-                            }
-                            let _ = remote_file.send_eof();
-                            let _ = remote_file.wait_eof();
-                            let _ = remote_file.wait_close();
-
-                            let _ = socket.lock().unwrap().write(&[200]);
-                            let _ = socket.lock().unwrap().flush();
-                            // completed upload.
-                            println!("[Daemon @Upload] File has been uploaded.");
-                        }
-                        MsgDaemonType::Run => {
-                            let mut buffer = [0; 1];
-                            let _dawn_read = socket.lock().unwrap().read(&mut buffer);
-                            if _dawn_read.is_err() {
-                                println!("[Daemon] Failed to read from socket.");
-                                continue;
-                            }
-                            let buffer = buffer.to_vec();
-                            let run_type: u8 = buffer[0];
-                            if robot.is_none() || robot_socket_clone.lock().unwrap().is_none() {
-                                println!("[Daemon] No Robot Available.");
-                                let _ = socket.lock().unwrap().write(&[100]);
-                                let _ = socket.lock().unwrap().flush();
-                                continue;
-                            }
-                            if let Some(ref mut robot_socket) = *robot_socket.lock().unwrap() {
-                                robot_socket.write(&[run_type]).unwrap();
-                                robot_socket.flush().unwrap();
-                            }
-
-                            println!("[Daemon] Sent run message to robot.");
-                            // now hold the socket until the robot is done running
-
-                            println!("[Daemon @Run] Starting log loop...");
-                            let _ = fs::remove_file(format!("{}/robot.run.txt", temp_dir));
-
-                            let _ = socket.lock().unwrap().write(&[1]);
-                            let _ = socket.lock().unwrap().flush();
-                            thread::spawn(move || {
-                                println!("[Daemon @Run] Waiting for robot to finish running.");
-                                input_listener(socket, robot_socket_clone);
-                            });
-                        }
-                        MsgDaemonType::InputListener => {
-                            println!("[Daemon @InputListener] Received Input Listening Request...");
-                            if robot_socket_clone.lock().unwrap().is_none() {
-                                println!("[Daemon @InputListener] Request failed.");
-                                let _ = socket.lock().unwrap().write(&[1]);
-                                let _ = socket.lock().unwrap().flush();
-                                continue;
-                            }
-                            let _ = socket.lock().unwrap().write(&[2]);
-                            let _ = socket.lock().unwrap().flush();
-                            thread::spawn(move || {
-                                println!(
-                                    "[Daemon @InputListener] Waiting for robot to finish running."
-                                );
-                                input_listener(socket, robot_socket_clone);
-                            });
-                        }
+    /// A client connection that skips the socket, for front ends living inside the daemon like the control API.
+    pub fn local_client(state: &Arc<DaemonState>, session: &str) -> std::io::Result<UnixStream> {
+        let (mut client, daemon) = UnixStream::pair()?;
+        let state = Arc::clone(state);
+        thread::spawn(move || serve_client(state, daemon));
+        hello(&mut client, session)?;
+        Ok(client)
+    }
 
-                        MsgDaemonType::QueryDevices => {
-                            // println!("[Daemon @QueryDevices] Received Query Question...");
-                            if robot.is_none() || robot_socket_clone.lock().unwrap().is_none() {
-                                let _ = socket.lock().unwrap().write(&[0]);
-                                let _ = socket.lock().unwrap().flush();
-                                // println!("[Daemon @QueryDevices] No robot available.");
-                                continue;
-                            }
-
-                            // now with the robot, ask for the devices.
-                            let mut buffer = [0; 3];
-                            // println!("[Daemon @QueryDevices] Fetching devices...");
-                            robot_socket_clone
-                                .lock()
-                                .unwrap()
-                                .as_ref()
-                                .unwrap()
-                                .write(&[4])
-                                .unwrap();
-                            robot_socket_clone
-                                .lock()
-                                .unwrap()
-                                .as_ref()
-                                .unwrap()
-                                .flush()
-                                .unwrap();
-                            robot_socket_clone
-                                .lock()
-                                .unwrap()
-                                .as_ref()
-                                .unwrap()
-                                .read_exact(&mut buffer)
-                                .unwrap();
-
-                            if buffer[0] != 1 {
-                                println!("[Daemon @QueryDevices] Failed to fetch devices.");
-                                socket.lock().unwrap().write(&[0]).unwrap();
-                                socket.lock().unwrap().flush().unwrap();
-                                continue;
-                            }
-
-                            // with the 3 byte header, get the length of the message let length_arr: Vec<u8> = vec![(length & 0x00ff) as u8, (length & 0xf00) as u8];
-                            let length = (buffer[1] as usize) | ((buffer[2] as usize) << 8);
-                            let mut buffer = vec![0; length as usize];
-                            robot_socket_clone
-                                .lock()
-                                .unwrap()
-                                .as_ref()
-                                .unwrap()
-                                .read(&mut buffer)
-                                .unwrap();
-                            // println!("[Daemon @QueryDevices] Fetched all devices!");
-                            let _ = socket.lock().unwrap().write(&[1]);
-                            let _ = socket
-                                .lock()
-                                .unwrap()
-                                .write(&[(buffer.len() & 0x00ff) as u8]);
-                            let _ = socket
-                                .lock()
-                                .unwrap()
-                                .write(&[((buffer.len() & 0xff00) >> 8) as u8]);
-                            let _ = socket.lock().unwrap().write(&buffer);
-                            let _ = socket.lock().unwrap().flush();
-                            // println!("[Daemon @QueryDevices] Sent Devices Info");
-                        }
-                        _ => {
-                            println!("[Daemon] Unknown message type: {:?}", buffer[0]);
-                        }
-                    }
+    // waits up to `timeout` for a client to be waiting on the listener
+    fn client_waiting(listener: &UnixListener, timeout: Duration) -> bool {
+        let mut waiting = libc::pollfd {
+            fd: listener.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: one pollfd that lives for the whole call
+        unsafe { libc::poll(&mut waiting, 1, timeout.as_millis() as i32) > 0 }
+    }
+
+    /// Accepts clients until one asks for a shutdown, each on its own worker so a slow upload or a stuck client holds up nobody else.
+    pub fn serve(listener: UnixListener, state: Arc<DaemonState>) {
+        while !state.shutting_down.load(Ordering::SeqCst) {
+            if !client_waiting(&listener, SHUTDOWN_CHECK_INTERVAL) {
+                continue;
+            }
+            match listener.accept() {
+                Ok((socket, _addr)) => {
+                    let state = Arc::clone(&state);
+                    thread::spawn(move || serve_client(state, socket));
                 }
                 Err(e) => {
                     println!("accept function failed: {:?}", e);
@@ -611,4 +963,697 @@ pub mod daemonhandler {
             }
         }
     }
+
+    // whether a process exists, even one we aren't allowed to signal
+    fn process_alive(pid: i32) -> bool {
+        // SAFETY: signal 0 only checks that the process is there
+        pid > 0
+            && (unsafe { libc::kill(pid, 0) } == 0
+                || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM))
+    }
+
+    // the PID in the lock file. A daemon starting alongside us creates the file a moment before it
+    // writes to it, so an empty one gets that moment before it counts as left behind.
+    fn held_pid(pid_path: &str) -> Option<i32> {
+        let deadline = Instant::now() + PID_WRITE_GRACE;
+        loop {
+            let held = fs::read_to_string(pid_path).ok()?;
+            if let Ok(pid) = held.trim().parse::<i32>() {
+                return Some(pid);
+            }
+            if !held.trim().is_empty() || Instant::now() >= deadline {
+                return None;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    /// Takes the PID file and clears out whatever a daemon that's gone left behind.
+    /// Fails with the reason when another daemon still holds them.
+    pub fn lock_runtime_files(pid_path: &str, socket_path: &str) -> Result<(), DaybreakError> {
+        let answering = || UnixStream::connect(socket_path).is_ok();
+        let mut pid_file = fs::OpenOptions::new().write(true).create_new(true).open(pid_path);
+        if pid_file.as_ref().is_err_and(|e| e.kind() == ErrorKind::AlreadyExists) {
+            let pid = held_pid(pid_path);
+            match pid {
+                Some(pid) if answering() => {
+                    return Err(DaybreakError::DaemonRunning(format!("A daemon is already running (PID {}).", pid)));
+                }
+                Some(pid) if process_alive(pid) => {
+                    return Err(DaybreakError::DaemonRunning(format!(
+                        "PID {} holds {} but isn't answering on {}. Stop it, or start with --start-force.",
+                        pid, pid_path, socket_path
+                    )));
+                }
+                _ => {
+                    println!("[Daemon] Removing the lock file of a daemon that's gone.");
+                    let _ = fs::remove_file(pid_path);
+                }
+            }
+            pid_file = fs::OpenOptions::new().write(true).create_new(true).open(pid_path);
+        }
+        let mut pid_file = pid_file?;
+        if let Err(e) = write!(pid_file, "{}", std::process::id()) {
+            let _ = fs::remove_file(pid_path);
+            return Err(e.into());
+        }
+
+        if Path::new(socket_path).exists() {
+            if answering() {
+                // a daemon from before the lock file, most likely
+                let _ = fs::remove_file(pid_path);
+                return Err(DaybreakError::DaemonRunning(format!("A daemon is already answering on {}.", socket_path)));
+            }
+            println!("[Daemon] Removing the stale socket {}.", socket_path);
+            let _ = fs::remove_file(socket_path);
+        }
+        Ok(())
+    }
+
+    /// How long --detach and client auto-start wait for a new daemon to answer.
+    pub const DAEMON_START_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Forks into the background with stdin closed and stdout/stderr appended to `log_path`.
+    /// Returns the daemon's PID in the parent and None in the daemon itself.
+    pub fn detach(log_path: &str) -> io::Result<Option<i32>> {
+        let log = fs::OpenOptions::new().create(true).append(true).open(log_path)?;
+        let null = fs::File::open("/dev/null")?;
+        // SAFETY: called before any other thread is spawned
+        match unsafe { libc::fork() } {
+            -1 => Err(io::Error::last_os_error()),
+            0 => {
+                // SAFETY: plain syscalls on descriptors we own
+                unsafe {
+                    libc::setsid();
+                    libc::dup2(null.as_raw_fd(), 0);
+                    libc::dup2(log.as_raw_fd(), 1);
+                    libc::dup2(log.as_raw_fd(), 2);
+                    // so the daemon doesn't keep the directory it was started from busy
+                    libc::chdir(c"/".as_ptr());
+                }
+                Ok(None)
+            }
+            pid => Ok(Some(pid)),
+        }
+    }
+
+    // the daemon with this PID holds the lock file and answers on the socket
+    fn daemon_ready(pid: i32, pid_path: &str, socket_path: &str) -> bool {
+        fs::read_to_string(pid_path).is_ok_and(|held| held.trim() == pid.to_string())
+            && UnixStream::connect(socket_path).is_ok()
+    }
+
+    /// Waits for a detached daemon to come up. False if it exited first or took longer than `timeout`.
+    pub fn wait_for_daemon(pid: i32, pid_path: &str, socket_path: &str, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            if daemon_ready(pid, pid_path, socket_path) {
+                return true;
+            }
+            // SAFETY: pid is our own child, and WNOHANG keeps this from blocking
+            if unsafe { libc::waitpid(pid, std::ptr::null_mut(), libc::WNOHANG) } == pid {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        false
+    }
+
+    // the socket systemd opened for us when started through daybreak.socket
+    fn activated_listener() -> Option<UnixListener> {
+        let pid = std::env::var("LISTEN_PID").ok()?.parse::<u32>().ok()?;
+        let fds = std::env::var("LISTEN_FDS").ok()?.parse::<i32>().ok()?;
+        if pid != std::process::id() || fds < 1 {
+            return None;
+        }
+        // SAFETY: systemd passes its first socket as fd 3 and nothing else owns it
+        Some(unsafe { UnixListener::from_raw_fd(3) })
+    }
+
+    /// Removes the daemon socket and PID file, as long as the PID file is still ours.
+    pub fn remove_runtime_files(pid_path: &str, socket_path: &str) {
+        let pid = fs::read_to_string(pid_path).ok();
+        if pid.as_deref().map(str::trim) != Some(std::process::id().to_string().as_str()) {
+            return;
+        }
+        let _ = fs::remove_file(socket_path);
+        let _ = fs::remove_file(pid_path);
+    }
+
+    /// Runs the daemon until a client asks it to shut down.
+    pub fn main_d(config: PathBuf) -> Result<(), DaybreakError> {
+        let socket_path = daemon_socket_path()?;
+        let pid_path = daemon_pid_path()?;
+        // systemd owns the socket when it started us, so there's nothing to lock or clean up
+        let listener = match activated_listener() {
+            Some(listener) => {
+                println!("[Daemon] Listening on the socket systemd passed in.");
+                listener
+            }
+            None => {
+                lock_runtime_files(&pid_path, &socket_path)?;
+                let listener = UnixListener::bind(&socket_path);
+                if let Err(e) = listener {
+                    remove_runtime_files(&pid_path, &socket_path);
+                    return Err(e.into());
+                }
+                println!("[Daemon] Listening on {}", socket_path);
+                listener?
+            }
+        };
+        println!("[Daemon] Reading settings from {}", config.display());
+        let state = Arc::new(DaemonState::new(&socket_path).with_config(config.clone()));
+        match ApiConfig::load(&config) {
+            Ok(Some(config)) => match TcpListener::bind(config.bind) {
+                Ok(api) => {
+                    println!("[Api] Listening on {}", config.bind);
+                    let state = Arc::clone(&state);
+                    thread::spawn(move || control_api::serve(api, config, state));
+                }
+                Err(e) => println!("[Api] Failed to bind to {}: {}", config.bind, e),
+            },
+            Ok(None) => {}
+            Err(e) => println!("[Api] {}", e),
+        }
+        serve(listener, state);
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::mock_runtime::mock_runtime::{MockRuntime, Script};
+        use crate::test_support::test_support::{eventually, serve_state, spawn_daemon, start_daemon};
+        use crate::robot::robotmanager::{device::DevData, run_mode::Mode, ConnectOptions, LinkState};
+        use protobuf::Message;
+        use std::{net::TcpListener, time::Instant};
+
+        fn open(path: &str, session: &str) -> UnixStream {
+            let mut stream = UnixStream::connect(path).unwrap();
+            stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+            hello(&mut stream, session).unwrap();
+            stream
+        }
+
+        fn ask(path: &str, session: &str, request: &Request) -> Response {
+            let mut stream = open(path, session);
+            send(&mut stream, request).unwrap();
+            receive(&mut stream).unwrap()
+        }
+
+        fn connect(path: &str, session: &str, robot_type: RobotType, options: ConnectOptions) {
+            let mut stream = open(path, session);
+            send(
+                &mut stream,
+                &Request::Connect {
+                    ip: "127.0.0.1".to_string(),
+                    robot_type,
+                    options,
+                },
+            )
+            .unwrap();
+            assert_eq!(receive::<Response>(&mut stream).unwrap(), Response::Started);
+            assert_eq!(receive::<Response>(&mut stream).unwrap(), Response::Ok);
+        }
+
+        #[test]
+        fn uploads_dont_block_device_polling() {
+            let mock = MockRuntime::bind("127.0.0.1:0", Script::default()).unwrap();
+            let port = mock.port();
+            mock.spawn();
+            // an ssh server that accepts and then never says a word, so the upload hangs
+            let stall = TcpListener::bind("127.0.0.1:0").unwrap();
+            let ssh_port = stall.local_addr().unwrap().port();
+            thread::spawn(move || {
+                let mut held = vec![];
+                for stream in stall.incoming() {
+                    held.push(stream);
+                }
+            });
+
+            let (path, _) = serve_state("concurrent", |path| DaemonState::new(path).with_ssh_port(ssh_port));
+            let session = format!("concurrent-{}", std::process::id());
+            connect(
+                &path,
+                &session,
+                RobotType::Potato,
+                ConnectOptions {
+                    port,
+                    ..ConnectOptions::default()
+                },
+            );
+
+            let code = std::env::temp_dir().join(format!("concurrent-{}.py", std::process::id()));
+            std::fs::write(&code, "print('hi')\n").unwrap();
+            let mut upload = open(&path, &session);
+            send(
+                &mut upload,
+                &Request::Upload {
+                    cwd: code.parent().unwrap().to_str().unwrap().to_string(),
+                    path: code.file_name().unwrap().to_str().unwrap().to_string(),
+                },
+            )
+            .unwrap();
+            assert_eq!(receive::<Response>(&mut upload).unwrap(), Response::Started);
+
+            // the upload is stuck in its ssh handshake, and devices still come through
+            let deadline = Instant::now() + Duration::from_secs(5);
+            let devices = loop {
+                if let Response::Devices(Some(devices)) = ask(&path, &session, &Request::QueryDevices) {
+                    break devices;
+                }
+                assert!(Instant::now() < deadline, "no devices while uploading");
+                thread::sleep(Duration::from_millis(50));
+            };
+            let devices = DevData::parse_from_bytes(&devices).unwrap();
+            assert_eq!(devices.devices, Script::default().devices);
+            assert!(matches!(
+                ask(&path, &session, &Request::QueryStatus),
+                Response::RuntimeStatus(_)
+            ));
+
+            let _ = std::fs::remove_file(&code);
+            let _ = std::fs::remove_file(&path);
+        }
+
+        #[test]
+        fn subscribers_hear_what_changes() {
+            let mock = MockRuntime::bind("127.0.0.1:0", Script::default()).unwrap();
+            let port = mock.port();
+            mock.spawn();
+            let path = start_daemon("subscribe");
+            let session = format!("subscribe-{}", std::process::id());
+
+            let mut events = open(&path, &session);
+            send(&mut events, &Request::Subscribe { since: 0 }).unwrap();
+            assert_eq!(
+                receive::<Response>(&mut events).unwrap(),
+                Response::Event(Some(Event::Connection(ConnectionState::Disconnected)))
+            );
+
+            connect(
+                &path,
+                &session,
+                RobotType::Potato,
+                ConnectOptions {
+                    port,
+                    ..ConnectOptions::default()
+                },
+            );
+            let mut run = open(&path, &session);
+            send(&mut run, &Request::Run(RunCommand::Teleop)).unwrap();
+            assert_eq!(receive::<Response>(&mut run).unwrap(), Response::Ok);
+
+            let mut seen = vec![];
+            let mut devices = None;
+            let mut latency = None;
+            let mut lines = vec![];
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while devices.is_none()
+                || latency.is_none()
+                || lines.is_empty()
+                || !seen.contains(&Event::RunMode(RunCommand::Teleop))
+                || !seen.contains(&Event::Link(LinkState::Connected))
+            {
+                assert!(Instant::now() < deadline, "only heard {:?}", seen);
+                match receive::<Response>(&mut events).unwrap() {
+                    Response::Event(Some(Event::Devices(bytes))) => devices = Some(bytes),
+                    Response::Event(Some(Event::Latency(stats))) => latency = Some(stats),
+                    Response::Event(Some(Event::Logs(batch))) => lines.extend(decode_log_lines(&batch).unwrap().1),
+                    Response::Event(Some(event)) => seen.push(event),
+                    Response::Event(None) => {}
+                    other => panic!("unexpected {:?}", other),
+                }
+            }
+            assert!(seen.contains(&Event::Connection(ConnectionState::Connecting {
+                ip: "127.0.0.1".to_string()
+            })));
+            assert!(seen.contains(&Event::Connection(ConnectionState::Connected {
+                ip: "127.0.0.1".to_string()
+            })));
+            let devices = DevData::parse_from_bytes(&devices.unwrap()).unwrap();
+            assert_eq!(devices.devices, Script::default().devices);
+            assert!(lines.iter().all(|line| line.session == session));
+
+            // a late subscriber starts out knowing what the session has, and hears each line once
+            let mut late = open(&path, &session);
+            send(&mut late, &Request::Subscribe { since: 0 }).unwrap();
+            let mut snapshot = vec![];
+            let mut seqs = vec![];
+            while !snapshot.contains(&Event::Link(LinkState::Connected))
+                || !snapshot.iter().any(|event| matches!(event, Event::Devices(_)))
+            {
+                assert!(Instant::now() < deadline, "the late subscriber only heard {:?}", snapshot);
+                match receive::<Response>(&mut late).unwrap() {
+                    Response::Event(Some(Event::Logs(batch))) => {
+                        seqs.extend(decode_log_lines(&batch).unwrap().1.iter().map(|line| line.seq))
+                    }
+                    Response::Event(Some(event)) => snapshot.push(event),
+                    _ => {}
+                }
+            }
+            assert!(seqs.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", seqs);
+            drop(late);
+
+            // hanging up the run stops the robot, and subscribers hear about it
+            drop(run);
+            loop {
+                assert!(Instant::now() < deadline, "never heard the robot stop");
+                if receive::<Response>(&mut events).unwrap()
+                    == Response::Event(Some(Event::RunMode(RunCommand::Stop)))
+                {
+                    break;
+                }
+            }
+            let _ = std::fs::remove_file(&path);
+        }
+
+        #[test]
+        fn status_reports_the_session() {
+            let mock = MockRuntime::bind("127.0.0.1:0", Script::default()).unwrap();
+            let port = mock.port();
+            mock.spawn();
+            let path = start_daemon("status");
+            let session = format!("status-{}", std::process::id());
+
+            let status = match ask(&path, &session, &Request::Status) {
+                Response::Status(status) => status,
+                other => panic!("unexpected {:?}", other),
+            };
+            assert_eq!(status.pid, std::process::id());
+            assert_eq!(status.session, session);
+            assert_eq!(status.connection, ConnectionState::Disconnected);
+            assert_eq!((status.clients, status.input_listeners), (0, 0));
+            assert_eq!((status.link, status.device_age_ms, status.run_mode), (None, None, None));
+            // a side picked before connecting is what clients see, and what the robot gets
+            assert_eq!(ask(&path, &session, &Request::SetStartPos(StartSide::Right)), Response::Queued);
+
+            connect(
+                &path,
+                &session,
+                RobotType::Raspberry,
+                ConnectOptions {
+                    port,
+                    ..ConnectOptions::default()
+                },
+            );
+            let mut run = open(&path, &session);
+            send(&mut run, &Request::Run(RunCommand::Teleop)).unwrap();
+            assert_eq!(receive::<Response>(&mut run).unwrap(), Response::Ok);
+
+            let deadline = Instant::now() + Duration::from_secs(10);
+            let status = loop {
+                if let Response::Status(status) = ask(&path, &session, &Request::Status) {
+                    if status.device_age_ms.is_some() && status.input_listeners == 1 {
+                        break status;
+                    }
+                }
+                assert!(Instant::now() < deadline, "devices never arrived");
+                thread::sleep(Duration::from_millis(50));
+            };
+            assert_eq!(
+                status.connection,
+                ConnectionState::Connected {
+                    ip: "127.0.0.1".to_string()
+                }
+            );
+            assert_eq!(status.robot_ip.as_deref(), Some("127.0.0.1"));
+            assert_eq!(status.robot_type, Some(RobotType::Raspberry));
+            assert_eq!(status.link, Some(LinkState::Connected));
+            assert_eq!(status.run_mode, Some(RunCommand::Teleop));
+            assert_eq!(status.start_pos, Some(StartSide::Right));
+            assert!(status.clients >= 1, "the run is still open");
+            assert!(status.device_age_ms.unwrap() < 5000);
+            let _ = std::fs::remove_file(&path);
+        }
+
+        #[test]
+        fn disconnect_idles_runtime_and_lets_go() {
+            let mock = MockRuntime::bind("127.0.0.1:0", Script::default()).unwrap();
+            let port = mock.port();
+            let recorded = mock.recorded();
+            mock.spawn();
+            let path = start_daemon("disconnect");
+            let session = format!("disconnect-{}", std::process::id());
+            let options = ConnectOptions {
+                port,
+                ..ConnectOptions::default()
+            };
+
+            assert_eq!(
+                ask(&path, &session, &Request::Disconnect),
+                Response::Error(DaemonError::NoRobot)
+            );
+            connect(&path, &session, RobotType::Potato, options);
+            eventually("the attached thread lets go of its path", || {
+                (!Path::new(&robot_socket_path(&session).unwrap()).exists()).then_some(())
+            });
+            let mut run = open(&path, &session);
+            send(&mut run, &Request::Run(RunCommand::Auto)).unwrap();
+            assert_eq!(receive::<Response>(&mut run).unwrap(), Response::Ok);
+
+            assert_eq!(ask(&path, &session, &Request::Disconnect), Response::Ok);
+            eventually("Runtime is idle", || {
+                (recorded.lock().unwrap().run_modes.last() == Some(&Mode::IDLE)).then_some(())
+            });
+            assert_eq!(ask(&path, &session, &Request::QueryDevices), Response::Devices(None));
+            let status = match ask(&path, &session, &Request::Status) {
+                Response::Status(status) => status,
+                other => panic!("unexpected {:?}", other),
+            };
+            assert_eq!(status.connection, ConnectionState::Disconnected);
+            assert_eq!((status.robot_ip, status.robot_type, status.run_mode), (None, None, None));
+
+            // connecting again, twice over without a disconnect, always ends with a robot that answers
+            connect(&path, &session, RobotType::Potato, options);
+            connect(&path, &session, RobotType::Potato, options);
+            eventually("the new robot thread answers", || {
+                (ask(&path, &session, &Request::QueryDevices) != Response::Devices(None)).then_some(())
+            });
+            assert_eq!(recorded.lock().unwrap().connections, 3);
+            let _ = std::fs::remove_file(&path);
+        }
+
+        #[test]
+        fn clients_that_die_mid_request_leave_the_daemon_standing() {
+            let mock = MockRuntime::bind("127.0.0.1:0", Script::default()).unwrap();
+            let port = mock.port();
+            let recorded = mock.recorded();
+            mock.spawn();
+            let (path, daemon) = spawn_daemon("dying");
+            let session = format!("dying-{}", std::process::id());
+            let options = ConnectOptions {
+                port,
+                ..ConnectOptions::default()
+            };
+
+            // killed before saying hello, and halfway through a frame's length
+            drop(UnixStream::connect(&path).unwrap());
+            let mut stream = UnixStream::connect(&path).unwrap();
+            stream.write_all(&[7, 0]).unwrap();
+            drop(stream);
+            let mut stream = open(&path, &session);
+            let mut frame = vec![];
+            send(&mut frame, &Request::Run(RunCommand::Teleop)).unwrap();
+            stream.write_all(&frame[..frame.len() - 1]).unwrap();
+            drop(stream);
+
+            // killed before hearing whether the robot connected
+            let mut stream = open(&path, &session);
+            send(
+                &mut stream,
+                &Request::Connect {
+                    ip: "127.0.0.1".to_string(),
+                    robot_type: RobotType::Potato,
+                    options,
+                },
+            )
+            .unwrap();
+            drop(stream);
+            eventually("the robot connects anyway", || {
+                (ask(&path, &session, &Request::QueryDevices) != Response::Devices(None)).then_some(())
+            });
+
+            // killed while running, partway through an input
+            let mut run = open(&path, &session);
+            send(&mut run, &Request::Run(RunCommand::Teleop)).unwrap();
+            assert_eq!(receive::<Response>(&mut run).unwrap(), Response::Ok);
+            let mut input = vec![];
+            send(&mut input, &Request::Input(vec![1; 32])).unwrap();
+            run.write_all(&input[..input.len() / 2]).unwrap();
+            drop(run);
+            eventually("the robot stops", || {
+                (recorded.lock().unwrap().run_modes.last() == Some(&Mode::IDLE)).then_some(())
+            });
+
+            // and a subscriber that goes away without a word
+            let mut events = open(&path, &session);
+            send(&mut events, &Request::Subscribe { since: 0 }).unwrap();
+            receive::<Response>(&mut events).unwrap();
+            drop(events);
+
+            assert!(matches!(ask(&path, &session, &Request::Status), Response::Status(_)));
+            assert!(matches!(
+                ask(&path, &session, &Request::QueryDevices),
+                Response::Devices(Some(_))
+            ));
+
+            // shutting down returns from serve rather than exiting the process
+            assert_eq!(ask(&path, &session, &Request::Shutdown), Response::Ok);
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while !daemon.is_finished() {
+                assert!(Instant::now() < deadline, "the daemon kept serving");
+                thread::sleep(Duration::from_millis(20));
+            }
+            daemon.join().unwrap();
+            let _ = std::fs::remove_file(&path);
+        }
+
+        #[test]
+        fn ssh_connection_failures_arent_blamed_on_the_login() {
+            let closed = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = closed.local_addr().unwrap().port();
+            drop(closed);
+            let target = |port| SshTarget {
+                ip: "127.0.0.1".to_string(),
+                port,
+                robot_type: RobotType::Potato,
+                config: config_path(),
+            };
+            assert!(matches!(ssh_login(&target(port)), Err(DaemonError::SshConnect(_))));
+
+            // something that answers but isn't an ssh server
+            let imposter = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = imposter.local_addr().unwrap().port();
+            thread::spawn(move || {
+                let (mut stream, _) = imposter.accept().unwrap();
+                let _ = stream.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n");
+            });
+            match ssh_login(&target(port)) {
+                Err(DaemonError::SshConnect(reason)) => assert!(reason.contains("handshake"), "{}", reason),
+                other => panic!("unexpected {:?}", other.err()),
+            }
+
+            // and refusing a robot says which key it showed
+            let refused = DaemonError::HostKeyMismatch {
+                host: "[192.168.0.140]:2222".to_string(),
+                fingerprint: "SHA256:abc".to_string(),
+            }
+            .to_string();
+            assert!(refused.contains("[192.168.0.140]:2222's ssh host key is SHA256:abc"), "{}", refused);
+        }
+
+        fn runtime_files(name: &str) -> (String, String) {
+            let dir = std::env::temp_dir().display().to_string();
+            let id = std::process::id();
+            (
+                format!("{}/daybreak-test-{}-{}.pid", dir, name, id),
+                format!("{}/daybreak-test-{}-{}.sock", dir, name, id),
+            )
+        }
+
+        #[test]
+        fn leftovers_of_a_dead_daemon_are_cleared() {
+            let (pid_path, socket_path) = runtime_files("stale");
+            let mut gone = std::process::Command::new("true").spawn().unwrap();
+            gone.wait().unwrap();
+            fs::write(&pid_path, gone.id().to_string()).unwrap();
+            let _ = fs::remove_file(&socket_path);
+            // a listener that's gone leaves its file behind
+            drop(UnixListener::bind(&socket_path).unwrap());
+
+            lock_runtime_files(&pid_path, &socket_path).unwrap();
+            assert_eq!(fs::read_to_string(&pid_path).unwrap(), std::process::id().to_string());
+            assert!(!Path::new(&socket_path).exists());
+            remove_runtime_files(&pid_path, &socket_path);
+            assert!(!Path::new(&pid_path).exists());
+        }
+
+        #[test]
+        fn a_live_daemon_keeps_its_files() {
+            let (pid_path, socket_path) = runtime_files("live");
+            let _ = fs::remove_file(&socket_path);
+            let _listener = UnixListener::bind(&socket_path).unwrap();
+            let _ = fs::remove_file(&pid_path);
+            // one that answers without a lock file, like a daemon from before there was one
+            let error = lock_runtime_files(&pid_path, &socket_path).unwrap_err();
+            assert!(error.to_string().contains("already answering"), "{}", error);
+            assert!(!Path::new(&pid_path).exists());
+
+            fs::write(&pid_path, "1").unwrap();
+            let error = lock_runtime_files(&pid_path, &socket_path).unwrap_err();
+            assert!(error.to_string().contains("PID 1"), "{}", error);
+            // and someone else's files aren't ours to clean up
+            remove_runtime_files(&pid_path, &socket_path);
+            assert!(Path::new(&socket_path).exists());
+            let _ = fs::remove_file(&pid_path);
+            let _ = fs::remove_file(&socket_path);
+        }
+
+        #[test]
+        fn a_daemon_starting_alongside_keeps_its_lock() {
+            let (pid_path, socket_path) = runtime_files("race");
+            let _ = fs::remove_file(&socket_path);
+            // created but not yet written to, as another daemon would have it for a moment
+            fs::write(&pid_path, "").unwrap();
+            let writer = {
+                let pid_path = pid_path.clone();
+                thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(100));
+                    fs::write(&pid_path, "1").unwrap();
+                })
+            };
+            let error = lock_runtime_files(&pid_path, &socket_path).unwrap_err();
+            assert!(error.to_string().contains("PID 1"), "{}", error);
+            writer.join().unwrap();
+            let _ = fs::remove_file(&pid_path);
+        }
+
+        #[test]
+        fn detached_daemons_are_waited_for() {
+            let (pid_path, socket_path) = runtime_files("detach");
+            let _ = fs::remove_file(&pid_path);
+            let _ = fs::remove_file(&socket_path);
+            // one that dies on the way up is given up on straight away
+            let mut dying = std::process::Command::new("true").spawn().unwrap();
+            let started = Instant::now();
+            assert!(!wait_for_daemon(dying.id() as i32, &pid_path, &socket_path, Duration::from_secs(5)));
+            assert!(started.elapsed() < Duration::from_secs(5));
+            // already reaped by the wait above
+            let _ = dying.wait();
+
+            let mut up = std::process::Command::new("sleep").arg("10").spawn().unwrap();
+            let pid = up.id() as i32;
+            let _listener = UnixListener::bind(&socket_path).unwrap();
+            // something answering isn't enough while the lock file names another daemon
+            fs::write(&pid_path, "1").unwrap();
+            assert!(!wait_for_daemon(pid, &pid_path, &socket_path, Duration::from_millis(200)));
+            fs::write(&pid_path, pid.to_string()).unwrap();
+            assert!(wait_for_daemon(pid, &pid_path, &socket_path, Duration::from_secs(1)));
+
+            up.kill().unwrap();
+            up.wait().unwrap();
+            let _ = fs::remove_file(&pid_path);
+            let _ = fs::remove_file(&socket_path);
+        }
+
+        #[test]
+        fn a_silent_client_blocks_nobody() {
+            let path = start_daemon("silent");
+            // says nothing at all, not even hello
+            let _silent = UnixStream::connect(&path).unwrap();
+            let mut halfway = UnixStream::connect(&path).unwrap();
+            hello(&mut halfway, "silent").unwrap();
+
+            assert_eq!(
+                ask(&path, "silent", &Request::QueryDevices),
+                Response::Devices(None)
+            );
+            assert_eq!(
+                ask(&path, "silent", &Request::SetStartPos(StartSide::Left)),
+                Response::Queued
+            );
+            let _ = std::fs::remove_file(&path);
+        }
+    }
 }
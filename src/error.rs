@@ -0,0 +1,86 @@
+#[allow(clippy::module_inception)]
+pub mod error {
+    use std::{fmt, io};
+
+    use crate::ipc::ipc::{DaemonError, Response};
+
+    /// Everything that can go wrong in the library, for the CLI or another program to report as it likes.
+    #[derive(Debug)]
+    pub enum DaybreakError {
+        /// The daemon couldn't be reached, or the connection to it broke.
+        Io(io::Error),
+        /// The daemon refused the request or failed to carry it out.
+        Daemon(DaemonError),
+        /// The daemon answered with something that doesn't fit the request.
+        Protocol(String),
+        /// Data from the robot didn't parse.
+        Decode(String),
+        /// Another daemon holds the socket or PID file.
+        DaemonRunning(String),
+        /// Drawing to or reading from the terminal failed.
+        Terminal(io::Error),
+        /// Gamepad support couldn't start.
+        Gamepad(String),
+    }
+
+    impl DaybreakError {
+        /// For a response that isn't one of those the request can get.
+        pub fn unexpected(response: Response) -> DaybreakError {
+            match response {
+                Response::Error(e) => DaybreakError::Daemon(e),
+                other => DaybreakError::Protocol(format!("unexpected {:?}", other)),
+            }
+        }
+    }
+
+    impl fmt::Display for DaybreakError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                DaybreakError::Io(e) => write!(f, "Failed to talk to the daemon: {}", e),
+                DaybreakError::Daemon(e) => write!(f, "{}", e),
+                DaybreakError::Protocol(what) => write!(f, "The daemon sent {}.", what),
+                DaybreakError::Decode(reason) => write!(f, "Failed to read the robot's data: {}", reason),
+                DaybreakError::DaemonRunning(reason) => write!(f, "{}", reason),
+                DaybreakError::Terminal(e) => write!(f, "Terminal error: {}", e),
+                DaybreakError::Gamepad(reason) => write!(f, "Failed to open gamepads: {}", reason),
+            }
+        }
+    }
+
+    impl std::error::Error for DaybreakError {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            match self {
+                DaybreakError::Io(e) | DaybreakError::Terminal(e) => Some(e),
+                _ => None,
+            }
+        }
+    }
+
+    impl From<io::Error> for DaybreakError {
+        fn from(error: io::Error) -> Self {
+            DaybreakError::Io(error)
+        }
+    }
+
+    impl From<DaemonError> for DaybreakError {
+        fn from(error: DaemonError) -> Self {
+            DaybreakError::Daemon(error)
+        }
+    }
+
+    // for code that still deals in io::Result, like the control API's in-process clients
+    impl From<DaybreakError> for io::Error {
+        fn from(error: DaybreakError) -> Self {
+            match error {
+                DaybreakError::Io(e) => e,
+                other => io::Error::other(other.to_string()),
+            }
+        }
+    }
+
+    impl From<protobuf::Error> for DaybreakError {
+        fn from(error: protobuf::Error) -> Self {
+            DaybreakError::Decode(error.to_string())
+        }
+    }
+}
@@ -0,0 +1,441 @@
+#[allow(clippy::module_inception)]
+pub mod ipc {
+    use std::{
+        fmt,
+        io::{self, ErrorKind, Read, Write},
+    };
+
+    use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+    use crate::error::error::DaybreakError;
+    use crate::robot::robotmanager::{
+        start_pos::Pos, ConnectError, ConnectOptions, GameAlert, LatencyStats, LinkState, RuntimeStatus, MAX_REPLY_SIZE,
+    };
+
+    /// Bumped whenever `Request` or `Response` changes shape. Clients and the daemon must match exactly.
+    pub const PROTOCOL_VERSION: u32 = 11;
+
+    /// Which robot we're talking to, which decides the SSH login for uploads and downloads.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum RobotType {
+        Potato,
+        Raspberry,
+    }
+
+    impl RobotType {
+        pub fn from_name(name: &str) -> Option<RobotType> {
+            match name.trim().to_lowercase().as_str() {
+                "potato" => Some(RobotType::Potato),
+                "raspberry" => Some(RobotType::Raspberry),
+                _ => None,
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum RunCommand {
+        Teleop,
+        Auto,
+        Stop,
+    }
+
+    /// The side the robot starts autonomous on.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum StartSide {
+        Left,
+        Right,
+    }
+
+    impl From<Pos> for StartSide {
+        fn from(pos: Pos) -> Self {
+            match pos {
+                Pos::LEFT => StartSide::Left,
+                Pos::RIGHT => StartSide::Right,
+            }
+        }
+    }
+
+    impl From<StartSide> for Pos {
+        fn from(side: StartSide) -> Self {
+            match side {
+                StartSide::Left => Pos::LEFT,
+                StartSide::Right => Pos::RIGHT,
+            }
+        }
+    }
+
+    /// Everything a client can ask of the daemon. Every connection opens with `Hello`.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub enum Request {
+        /// Agrees on the protocol version and picks the robot session the connection targets.
+        Hello { version: u32, session: String },
+        /// Answered with `Started`, then `Ok` or the reason Runtime couldn't be reached.
+        Connect {
+            ip: String,
+            robot_type: RobotType,
+            options: ConnectOptions,
+        },
+        /// Sends `path`, relative to the client's `cwd`, to the robot as its student code.
+        Upload { cwd: String, path: String },
+        /// Fetches the robot's student code into `path`, relative to the client's `cwd`.
+        Download { cwd: String, path: String },
+        /// Changes the run mode. The connection then carries `Input`s until `StopRun` or hang-up.
+        Run(RunCommand),
+        /// Forwards `Input`s like `Run` without changing the run mode.
+        InputListener,
+        /// A protobuf `Input` from a gamepad or the keyboard.
+        Input(Vec<u8>),
+        StopRun,
+        QueryDevices,
+        QueryStatus,
+        QueryLatency,
+        QueryGameState,
+        QueryLink,
+        SetStartPos(StartSide),
+        /// Replays a session recording into the selected session.
+        Replay { path: String },
+        /// Pushes `Event`s until the client hangs up, with log lines after `since`. `u64::MAX` starts from now.
+        Subscribe { since: u64 },
+        /// Answered with `Status` for the daemon and the selected session.
+        Status,
+        /// Idles Runtime and drops the session's robot, keeping the daemon running.
+        Disconnect,
+        /// Adds the robot's ssh host key to Daybreak's known_hosts, answered with `Trusted`.
+        /// With `fingerprint`, only if the robot's key has it. `replace` swaps out a key trusted before.
+        Trust { fingerprint: Option<String>, replace: bool },
+        Shutdown,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub enum Response {
+        Hello { version: u32 },
+        /// The request is underway. Another response follows when it's done.
+        Started,
+        Ok,
+        /// No robot is connected, so the start position will be sent once one is.
+        Queued,
+        /// A protobuf `DevData`, `None` without a robot or before it has sent any.
+        Devices(Option<Vec<u8>>),
+        /// `None` without a robot.
+        RuntimeStatus(Option<RuntimeStatus>),
+        Latency(Option<LatencyStats>),
+        GameAlert(Option<GameAlert>),
+        Link(Option<LinkState>),
+        /// Something changed on a subscription, or `None` to show the stream is still alive.
+        Event(Option<Event>),
+        Status(DaemonStatus),
+        /// The robot's host key is trusted, `added` when it wasn't before.
+        Trusted { fingerprint: String, added: bool },
+        Error(DaemonError),
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct DaemonStatus {
+        pub pid: u32,
+        pub uptime_ms: u64,
+        /// Clients connected besides the one asking.
+        pub clients: u32,
+        pub session: String,
+        pub connection: ConnectionState,
+        pub robot_ip: Option<String>,
+        pub robot_type: Option<RobotType>,
+        pub link: Option<LinkState>,
+        /// The run mode clients last asked for.
+        pub run_mode: Option<RunCommand>,
+        /// The side chosen for autonomous, sent to the robot on every connect.
+        pub start_pos: Option<StartSide>,
+        /// Open run or input streams forwarding gamepad and keyboard input.
+        pub input_listeners: u32,
+        /// How long ago Runtime last sent devices, `None` before it has.
+        pub device_age_ms: Option<u64>,
+    }
+
+    /// What a subscription pushes, starting with what the session has now. Each one replaces what the client knew before.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub enum Event {
+        /// A protobuf `DevData`, sent when the devices or their values change.
+        Devices(Vec<u8>),
+        /// A batch from `encode_log_lines`.
+        Logs(Vec<u8>),
+        RunMode(RunCommand),
+        Connection(ConnectionState),
+        /// Sent when it changes.
+        RuntimeStatus(RuntimeStatus),
+        Upload(UploadProgress),
+        /// The TCP link to Runtime came up, dropped or was given up on.
+        Link(LinkState),
+        /// Sent with every echoed ping.
+        Latency(LatencyStats),
+        /// A field event, sent once as it happens.
+        GameAlert(GameAlert),
+    }
+
+    #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum ConnectionState {
+        #[default]
+        Disconnected,
+        Connecting { ip: String },
+        Connected { ip: String },
+        Replaying { path: String },
+        Failed(ConnectError),
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum UploadProgress {
+        Started { total: u64 },
+        Sent { sent: u64, total: u64 },
+        Done,
+        Failed(DaemonError),
+    }
+
+    /// Why the daemon couldn't do what was asked.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub enum DaemonError {
+        VersionMismatch { daemon: u32, client: u32 },
+        InvalidSession(String),
+        /// The request doesn't make sense at this point in the connection.
+        Unexpected(String),
+        NoRobot,
+        FileNotFound(String),
+        LocalFile(String),
+        SshConnect(String),
+        SshAuth(String),
+        /// The robot's host key isn't in known_hosts yet.
+        UntrustedHost { host: String, fingerprint: String },
+        /// The robot's host key isn't the one known_hosts has for it.
+        HostKeyMismatch { host: String, fingerprint: String },
+        RemoteFile(String),
+        Connect(ConnectError),
+        Replay(String),
+    }
+
+    impl fmt::Display for DaemonError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                DaemonError::VersionMismatch { daemon, client } => write!(
+                    f,
+                    "The daemon speaks protocol version {} but this client speaks {}. Restart the daemon.",
+                    daemon, client
+                ),
+                DaemonError::InvalidSession(name) => write!(f, "Invalid session name: {:?}", name),
+                DaemonError::Unexpected(request) => write!(f, "The daemon didn't expect {}.", request),
+                DaemonError::NoRobot => write!(f, "No robot is connected."),
+                DaemonError::FileNotFound(path) => write!(f, "File does not exist: {}", path),
+                DaemonError::LocalFile(reason) => write!(f, "Failed to use the local file: {}", reason),
+                DaemonError::SshConnect(reason) => write!(f, "Failed to connect to the robot over ssh: {}", reason),
+                DaemonError::SshAuth(reason) => write!(f, "The robot refused the ssh login: {}", reason),
+                DaemonError::UntrustedHost { host, fingerprint } => write!(
+                    f,
+                    "{} isn't a trusted robot yet. Its ssh host key is {}; if that's the robot's, run `daybreak trust {}`.",
+                    host, fingerprint, fingerprint
+                ),
+                DaemonError::HostKeyMismatch { host, fingerprint } => write!(
+                    f,
+                    "{}'s ssh host key is {}, not the key Daybreak trusts for it. It may be another machine on the network; \
+                     if the robot was reinstalled, run `daybreak trust {} --replace`.",
+                    host, fingerprint, fingerprint
+                ),
+                DaemonError::RemoteFile(reason) => write!(f, "Failed to use the file on the robot: {}", reason),
+                DaemonError::Connect(error) => write!(f, "{}", error),
+                DaemonError::Replay(reason) => write!(f, "Failed to start the replay: {}", reason),
+            }
+        }
+    }
+
+    /// Writes one message as `[length u32 LE][bincode]`.
+    pub fn send<T: Serialize>(stream: &mut impl Write, message: &T) -> io::Result<()> {
+        let payload = bincode::serialize(message).map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?;
+        if payload.len() > MAX_REPLY_SIZE {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "message too large"));
+        }
+        let mut bytes = (payload.len() as u32).to_le_bytes().to_vec();
+        bytes.extend_from_slice(&payload);
+        stream.write_all(&bytes)?;
+        stream.flush()
+    }
+
+    pub fn receive<T: DeserializeOwned>(stream: &mut impl Read) -> io::Result<T> {
+        let mut length = [0; 4];
+        stream.read_exact(&mut length)?;
+        let length = u32::from_le_bytes(length) as usize;
+        if length > MAX_REPLY_SIZE {
+            return Err(io::Error::new(ErrorKind::InvalidData, "message too large"));
+        }
+        let mut payload = vec![0; length];
+        stream.read_exact(&mut payload)?;
+        bincode::deserialize(&payload).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// Takes the first whole message written by `send` off the front of `buffer`, None until one has arrived.
+    pub fn take_message<T: DeserializeOwned>(buffer: &mut Vec<u8>) -> Option<io::Result<T>> {
+        let length = u32::from_le_bytes(buffer.get(..4)?.try_into().unwrap()) as usize;
+        if length > MAX_REPLY_SIZE {
+            // there's no finding the next message after this one
+            buffer.clear();
+            return Some(Err(io::Error::new(ErrorKind::InvalidData, "message too large")));
+        }
+        if buffer.len() < 4 + length {
+            return None;
+        }
+        let message: Vec<u8> = buffer.drain(..4 + length).collect();
+        Some(bincode::deserialize(&message[4..]).map_err(|e| io::Error::new(ErrorKind::InvalidData, e)))
+    }
+
+    /// The client's half of the handshake.
+    pub fn hello(stream: &mut (impl Read + Write), session: &str) -> Result<(), DaybreakError> {
+        send(
+            stream,
+            &Request::Hello {
+                version: PROTOCOL_VERSION,
+                session: session.to_string(),
+            },
+        )?;
+        match receive(stream)? {
+            Response::Hello { version } if version == PROTOCOL_VERSION => Ok(()),
+            Response::Hello { version } => Err(DaybreakError::Daemon(DaemonError::VersionMismatch {
+                daemon: version,
+                client: PROTOCOL_VERSION,
+            })),
+            other => Err(DaybreakError::unexpected(other)),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::robot::robotmanager::gamestate::State;
+        use std::{os::unix::net::UnixStream, thread, time::Duration};
+
+        #[test]
+        fn messages_round_trip() {
+            let requests = vec![
+                Request::Hello {
+                    version: PROTOCOL_VERSION,
+                    session: "red".to_string(),
+                },
+                Request::Connect {
+                    ip: "192.168.0.140".to_string(),
+                    robot_type: RobotType::Raspberry,
+                    options: ConnectOptions {
+                        port: 18101,
+                        timeout: Duration::from_millis(250),
+                        retries: 0,
+                        backoff: Duration::from_millis(20),
+                    },
+                },
+                Request::Upload {
+                    cwd: "/home/student/a very long path".repeat(100),
+                    path: "code.py".to_string(),
+                },
+                Request::Run(RunCommand::Auto),
+                Request::Input(vec![1, 2, 3]),
+                Request::SetStartPos(StartSide::Right),
+                Request::Subscribe { since: u64::MAX },
+            ];
+            let mut bytes = vec![];
+            for request in &requests {
+                send(&mut bytes, request).unwrap();
+            }
+            let responses = vec![
+                Response::Error(DaemonError::Connect(ConnectError::Refused)),
+                Response::Event(Some(Event::Connection(ConnectionState::Failed(ConnectError::TimedOut)))),
+                Response::Event(Some(Event::Upload(UploadProgress::Sent { sent: 1024, total: 4096 }))),
+                Response::Event(None),
+                Response::Latency(Some(LatencyStats {
+                    samples: 20,
+                    min_ms: 6,
+                    avg_ms: 15.5,
+                    p95_ms: 24,
+                    skew_ms: -40,
+                })),
+                Response::GameAlert(Some(GameAlert {
+                    seq: 3,
+                    state: State::HYPOTHERMIA_START,
+                })),
+                Response::Link(None),
+                Response::Status(DaemonStatus {
+                    pid: 4242,
+                    uptime_ms: 61_000,
+                    clients: 2,
+                    session: "red".to_string(),
+                    connection: ConnectionState::Connected {
+                        ip: "192.168.0.140".to_string(),
+                    },
+                    robot_ip: Some("192.168.0.140".to_string()),
+                    robot_type: Some(RobotType::Potato),
+                    link: Some(LinkState::Reconnecting),
+                    run_mode: Some(RunCommand::Teleop),
+                    start_pos: Some(StartSide::Left),
+                    input_listeners: 1,
+                    device_age_ms: None,
+                }),
+            ];
+            for response in &responses {
+                send(&mut bytes, response).unwrap();
+            }
+
+            let mut reader = &bytes[..];
+            for request in requests {
+                assert_eq!(receive::<Request>(&mut reader).unwrap(), request);
+            }
+            for response in responses {
+                assert_eq!(receive::<Response>(&mut reader).unwrap(), response);
+            }
+            assert!(reader.is_empty());
+        }
+
+        #[test]
+        fn messages_come_off_a_buffer_once_whole() {
+            let mut bytes = vec![];
+            send(&mut bytes, &Request::SetStartPos(StartSide::Left)).unwrap();
+            send(&mut bytes, &Request::StopRun).unwrap();
+            let mut buffer = vec![];
+            let mut taken = vec![];
+            for byte in bytes {
+                buffer.push(byte);
+                taken.extend(take_message::<Request>(&mut buffer).map(Result::unwrap));
+            }
+            assert_eq!(taken, vec![Request::SetStartPos(StartSide::Left), Request::StopRun]);
+            assert!(buffer.is_empty());
+        }
+
+        #[test]
+        fn garbage_and_oversize_lengths_are_refused() {
+            let mut oversize = &((MAX_REPLY_SIZE + 1) as u32).to_le_bytes()[..];
+            assert_eq!(receive::<Request>(&mut oversize).unwrap_err().kind(), ErrorKind::InvalidData);
+            let mut buffer = ((MAX_REPLY_SIZE + 1) as u32).to_le_bytes().to_vec();
+            assert!(take_message::<Request>(&mut buffer).unwrap().is_err());
+            assert!(buffer.is_empty());
+            let mut garbage = &[2, 0, 0, 0, 0xff, 0xff][..];
+            assert_eq!(receive::<Request>(&mut garbage).unwrap_err().kind(), ErrorKind::InvalidData);
+        }
+
+        #[test]
+        fn handshake_refuses_other_versions() {
+            let (mut client, mut daemon) = UnixStream::pair().unwrap();
+            let older = thread::spawn(move || {
+                let request: Request = receive(&mut daemon).unwrap();
+                assert_eq!(
+                    request,
+                    Request::Hello {
+                        version: PROTOCOL_VERSION,
+                        session: "blue".to_string()
+                    }
+                );
+                send(&mut daemon, &Response::Hello { version: PROTOCOL_VERSION + 1 }).unwrap();
+            });
+            let error = hello(&mut client, "blue").unwrap_err();
+            assert!(
+                matches!(error, DaybreakError::Daemon(DaemonError::VersionMismatch { .. })),
+                "{}",
+                error
+            );
+            assert!(error.to_string().contains("protocol version"), "{}", error);
+            older.join().unwrap();
+        }
+    }
+}
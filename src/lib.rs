@@ -1,6 +1,16 @@
+pub mod client;
+pub mod control_api;
 pub mod daemon;
+pub mod error;
+pub mod ipc;
+pub mod mock_runtime;
 pub mod robot;
+pub mod run_log;
+pub mod runtime_security;
 pub mod sfx_manager;
+pub mod ssh;
+#[cfg(test)]
+pub mod test_support;
 pub mod tui;
 pub mod tui_readdevices;
 pub mod tui_runrobot;
@@ -8,7 +18,7 @@ pub mod keymap {
     use gilrs::Button;
     use termion::event::Key;
     pub fn gamepad_mapped(button: &Button) -> u8 {
-        return match button {
+        match button {
             Button::DPadUp => 12,
             Button::DPadDown => 13,
             Button::DPadLeft => 14,
@@ -27,7 +37,7 @@ pub mod keymap {
             Button::West => 2,
             Button::North => 3,
             _ => 20, // Default case for unmapped buttons
-        };
+        }
     }
     pub fn key_map(key: &Key) -> u8 {
         match key {
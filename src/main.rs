@@ -1,40 +1,31 @@
-use crossterm::event;
 use daybreak::{
     daemon::daemonhandler,
-    keymap::gamepad_mapped,
-    keymap::key_map,
+    error::error::DaybreakError,
+    ipc::ipc::{ConnectionState, RobotType, RunCommand},
     robot::robotmanager::{
-        device::{param::Val, DevData},
-        input::{Input, Source as InputSource},
+        config_path, daemon_log_path, daemon_pid_path, daemon_socket_path, is_valid_session_name, read_recording, recording_path,
+        start_pos::Pos, state_dir, ConnectOptions, CONNECT_OPTION_KEYS, DEFAULT_SESSION,
     },
-    sfx_manager::SfxManager,
-    tui::tui::App,
-    tui_readdevices::{
-        self,
-        read_devices_tui::{self, read_devices},
+    tui_readdevices::read_devices_tui::{
+        self, daemon_client, describe_devices, ensure_daemon, select_session, selected_session,
     },
-    tui_runrobot::run_robot_tui::{self, input_executor, tui},
+    tui_runrobot::run_robot_tui::{input_executor, tui},
 };
-use gilrs::{Axis, Button, Event, GamepadId, Gilrs};
 use ini::Ini;
 use linked_hash_map::LinkedHashMap;
-use protobuf::{EnumOrUnknown, Message, SpecialFields};
-use ratatui::{
-    layout::{Constraint, Layout},
-    style::{Style, Stylize},
-    widgets::{Block, List, ListItem, ListState},
+use signal_hook::{
+    consts::{SIGINT, SIGTERM},
+    iterator::Signals,
 };
-use signal_hook::{consts::SIGINT, iterator::Signals};
 use std::{
-    collections::HashMap,
     env, fs,
-    io::{Read, Write},
-    os::unix::net::UnixStream,
-    sync::{atomic::AtomicBool, Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
     thread,
     time::Duration,
 };
-use Constraint::{Fill, Length, Min, Percentage};
 // 3 byte message
 
 fn exit(code: i32) {
@@ -42,42 +33,81 @@ fn exit(code: i32) {
 }
 
 fn on_shutdown() {
-    let mut signals = Signals::new([SIGINT]).unwrap();
+    let mut signals = Signals::new([SIGINT, SIGTERM]).unwrap();
     thread::spawn(move || {
         for sig in signals.forever() {
             println!("\n[Shutdown] Received signal {:?}", sig);
-            // delete the socket file
-            let _ = std::fs::remove_file(format!(
-                "{}/daybreak.sock",
-                std::env::temp_dir().into_os_string().into_string().unwrap()
-            ));
+            if let (Ok(pid_path), Ok(socket_path)) = (daemon_pid_path(), daemon_socket_path()) {
+                daemonhandler::remove_runtime_files(&pid_path, &socket_path);
+            }
             println!("[Shutdown] Deleted socket file.");
-            exit(1);
+            // SIGTERM is how systemd and `kill` stop a daemon that's doing fine
+            exit(if sig == SIGTERM { 0 } else { 1 });
         }
     });
 }
 
+// waits out an upload or download, which can take a while over a slow link
+fn transfer(tag: &str, result: Result<(), DaybreakError>) {
+    match result {
+        Ok(()) => println!("[{}] File has been {}ed.", tag, tag.to_lowercase()),
+        Err(e) => {
+            println!("[{}] {}", tag, e);
+            exit(1);
+        }
+    }
+}
+
 fn main() {
-    let temp_dir = std::env::temp_dir().into_os_string().into_string().unwrap();
     let mut commands: LinkedHashMap<&str, &str> = LinkedHashMap::new();
-    commands.insert("--connect [IP] [raspberry/potato]", "Connect to Runtime");
-    commands.insert("--start", "Start the Daybreak daemon.");
+    commands.insert(
+        "--connect [IP] [raspberry/potato] [--name NAME]",
+        "Connect to Runtime, optionally as a named robot session.\n\tAlso takes --port, --timeout [ms], --retries and --backoff [ms].",
+    );
+    commands.insert(
+        "--robot [NAME]",
+        "Targets any command at a named robot session.",
+    );
+    commands.insert(
+        "--start [--detach]",
+        "Start the Daybreak daemon.\n\t--detach runs it in the background, logging to daybreak.log next to its socket.\n\tOther commands start it this way on their own unless [daemon] autostart = false.",
+    );
     commands.insert(
         "--start-force",
-        "Start the Daybreak daemon and remove the socket file if it exists.",
+        "Start the Daybreak daemon, removing its socket and lock file even if another daemon seems to hold them.",
     );
     commands.insert("--help", "Display this help message.");
     commands.insert("upload [FILE PATH]", "Upload a file to the robot.");
+    commands.insert(
+        "trust [FINGERPRINT] [--replace]",
+        "Trusts the robot's ssh host key for uploads and downloads, optionally only if it has FINGERPRINT.\n\t--replace trusts a new key for a robot that was reinstalled.",
+    );
     commands.insert(
         "download [FILE PATH]",
         "Downloads the studentcode from the robot.",
     );
     commands.insert("shutdown", "Shutdown the Daybreak daemon.");
+    commands.insert(
+        "disconnect",
+        "Idles the robot and drops the connection, keeping the daemon running.",
+    );
     commands.insert("run [auto, teleop, stop] or <empty/--tui>", "Executes code on the robot.\n\tIf no extra paramater provided, automatically goes into TUI mode.");
     commands.insert(
         "input",
         "Sets the robot to be on generic input listener mode.",
     );
+    commands.insert(
+        "status [--json]",
+        "Shows the daemon's connection, run mode and clients, then the Runtime status (battery, Shepherd, latency).\n\t--json prints the daemon's part as JSON.",
+    );
+    commands.insert(
+        "startpos [left/right]",
+        "Sets the side the robot starts on for autonomous.",
+    );
+    commands.insert(
+        "replay [FILE PATH]",
+        "Plays a session recording (the latest by default) back in the run TUI.",
+    );
     commands.insert("ls", "Lists all connected devices.");
     commands.insert("    -a", "Attaches to device lister until shutdown.");
     commands.insert(
@@ -92,7 +122,7 @@ fn main() {
             println!("    {}\t{}", k, v);
         });
     }
-    let args: Vec<String> = if args.len() > 1 {
+    let mut args: Vec<String> = if args.len() > 1 {
         if args[0] == "target/debug/daybreak" {
             args[2..].to_vec()
         } else {
@@ -102,7 +132,26 @@ fn main() {
         vec![]
     };
 
-    if args.len() < 1 {
+    // --name on connect and --robot everywhere else both pick the session
+    if args.contains(&"--robot".to_string()) && args.contains(&"--name".to_string()) {
+        println!("[Session] --robot and --name both pick the session, please pass only one.");
+        exit(1);
+        return;
+    }
+    for flag in ["--robot", "--name"] {
+        if let Some(index) = args.iter().position(|arg| arg == flag) {
+            if index + 1 >= args.len() || !is_valid_session_name(&args[index + 1]) {
+                println!("[Session] Please pass a session name after {} (letters, numbers, - and _).", flag);
+                exit(1);
+                return;
+            }
+            let name = args.remove(index + 1);
+            args.remove(index);
+            select_session(&name);
+        }
+    }
+
+    if args.is_empty() {
         println!("Please pass a command.");
         show_help(&commands);
         exit(1);
@@ -111,48 +160,99 @@ fn main() {
     let mut command = args[0].as_str();
 
     if command == "--start-force" {
-        if std::fs::exists(format!("{}/daybreak.sock", temp_dir)).unwrap() {
-            println!("[Connection] Socket file already exists. Removing...");
-            let _daybreak_removal = std::fs::remove_file(format!("{}/daybreak.sock", temp_dir));
-            if _daybreak_removal.is_err() {
-                println!("[Connection] Failed to remove socket file.");
+        let paths = daemon_socket_path().and_then(|socket_path| Ok([socket_path, daemon_pid_path()?]));
+        let paths = match paths {
+            Ok(paths) => paths,
+            Err(e) => {
+                println!("[Connection] {}", e);
                 exit(1);
+                return;
+            }
+        };
+        for path in paths {
+            if std::fs::exists(&path).unwrap() {
+                println!("[Connection] {} already exists. Removing...", path);
+                if std::fs::remove_file(&path).is_err() {
+                    println!("[Connection] Failed to remove {}.", path);
+                    exit(1);
+                }
             }
         }
         command = "--start";
     }
 
+    // shutting down a daemon that isn't there shouldn't start one first
+    if !matches!(command, "--start" | "--help" | "shutdown") && commands.keys().any(|usage| usage.split(' ').next() == Some(command)) {
+        if let Err(e) = ensure_daemon() {
+            println!("[Daemon] Failed to start the daemon: {}", e);
+            exit(1);
+            return;
+        }
+    }
+
     match command {
         "--connect" => {
-            let stream = UnixStream::connect(format!("{}/daybreak.sock", temp_dir));
-            if stream.is_err() {
-                println!("[Connection] Failed to connect to stream.");
-                exit(1);
+            // --port/--timeout/--retries/--backoff win over daybreak.ini
+            let mut flags = vec![];
+            for key in CONNECT_OPTION_KEYS {
+                let flag = format!("--{}", key.replace('_', "-"));
+                if let Some(index) = args.iter().position(|arg| *arg == flag) {
+                    if index + 1 >= args.len() {
+                        println!("[Connection] Please pass a value after {}.", flag);
+                        exit(1);
+                        return;
+                    }
+                    flags.push((key, args.remove(index + 1)));
+                    args.remove(index);
+                }
             }
-            let mut ip: String = String::from("");
-            let mut robot_type: String = String::from("");
-            let ini = Ini::load_from_file("daybreak.ini");
+            let mut options = ConnectOptions::default();
+            let ip: String;
+            let robot_type: String;
+            let ini = Ini::load_from_file(config_path());
+            // named sessions read their own [connection NAME] section
+            let section_name = match selected_session() {
+                DEFAULT_SESSION => "connection".to_string(),
+                name => format!("connection {}", name),
+            };
 
-            if let Ok(ini) = ini {
-                let section = ini.section(Some("connection"));
-                if let Some(section) = section {
-                    ip = if let Some(ip_new) = section.get("ip") {
-                        ip_new.to_string()
-                    } else {
-                        "".to_string()
-                    };
-                    robot_type = section.get("robot_type").unwrap().to_string();
+            let section = ini
+                .as_ref()
+                .ok()
+                .and_then(|ini| ini.section(Some(section_name.as_str())));
+            // options hold however the IP is picked, and a named session without its own falls back to [connection]
+            let option_section = section.or_else(|| ini.as_ref().ok().and_then(|ini| ini.section(Some("connection"))));
+            if let Some(option_section) = option_section {
+                for key in CONNECT_OPTION_KEYS {
+                    if let Some(value) = option_section.get(key) {
+                        if let Err(e) = options.set(key, value) {
+                            println!("[Connection] {} in daybreak.ini.", e);
+                            exit(1);
+                            return;
+                        }
+                    }
+                }
+            }
+            if let Some((ip_new, robot_type_new)) =
+                section.and_then(|section| Some((section.get("ip")?, section.get("robot_type").unwrap_or("potato"))))
+            {
+                ip = ip_new.to_string();
+                robot_type = robot_type_new.to_string();
+                println!(
+                    "[Connection] Using ip: {} and robot_type: {} from daybreak.ini!",
+                    ip, robot_type
+                );
+            } else if args.len() < 2 {
+                if ini.is_ok() {
                     println!(
-                        "[Connection] Using ip: {} and robot_type: {} from daybreak.ini!",
-                        ip, robot_type
+                        "[Connection] Missing ip and robot_type in section [{}] in daybreak.ini.",
+                        section_name
                     );
                 } else {
-                    println!("[Connection] Missing ip and robot_type in section [connection] in daybreak.ini.");
-                    exit(1);
+                    println!("[Connection] Please pass an IP address to connect to and (optionally) the robot type.");
                 }
-            } else if args.len() < 2 {
-                println!("[Connection] Please pass an IP address to connect to and (optionally) the robot type.");
                 exit(1);
+                return;
             } else {
                 ip = args[1].as_str().to_string();
                 robot_type = if args.len() >= 3 {
@@ -162,53 +262,31 @@ fn main() {
                 };
             }
 
-            let mut stream = stream.unwrap();
-            let _ = stream.write(&[2]);
-            let robot_ip_as_bytes = ip.as_bytes();
-
-            let robot_type = if robot_type.to_lowercase() == "potato" {
-                1
-            } else if robot_type.to_lowercase() == "raspberry" {
-                2
-            } else {
-                0
-            };
-
-            if robot_type == 0 {
-                println!("[Connection] Invalid robot type. Valid Types: raspberry/potato");
-                exit(1);
+            for (key, value) in flags {
+                if let Err(e) = options.set(key, &value) {
+                    println!("[Connection] {}.", e);
+                    exit(1);
+                    return;
+                }
             }
-            let _ = stream.write(&[robot_type]);
-            let _ = stream.write(robot_ip_as_bytes);
-            let _ = stream.flush();
-            println!("[Connection] Sending connection request to daemon...");
 
-            let mut buffer = [0; 1];
-            let _dawn_read = stream.read(&mut buffer);
-            if _dawn_read.is_err() {
-                println!("[Connection] Failed to read from daemon.");
+            let robot_type = RobotType::from_name(&robot_type);
+            if robot_type.is_none() {
+                println!("[Connection] Invalid robot type. Valid Types: raspberry/potato");
                 exit(1);
+                return;
             }
-
-            if buffer[0] == 1 {
-                println!("[Connection] Daemon acknowledged request... Waiting for connection.");
-
-                let _dawn_read = stream.read(&mut buffer);
-                if _dawn_read.is_err() {
-                    println!("[Connection] Failed to read from daemon.");
-                    exit(1);
-                }
-
-                if buffer[0] == 200 {
+            let ip = ip.trim();
+            println!("[Connection] Asking the daemon to connect to {}...", ip);
+            match daemon_client().connect(ip, robot_type.unwrap(), options) {
+                Ok(()) => {
                     println!("[Connection] Successfully connected to Robot.");
                     exit(0);
-                } else {
-                    println!("[Connection] Failed to connect to Robot.");
+                }
+                Err(e) => {
+                    println!("[Connection] {}", e);
                     exit(1);
                 }
-            } else {
-                println!("[Connection] Failed to connect to daemon.");
-                exit(1);
             }
         }
         "--help" => {
@@ -219,21 +297,57 @@ fn main() {
             });
         }
         "--start" => {
-            if std::fs::exists(format!("{}/daybreak.sock", temp_dir)).unwrap() {
-                println!("[Daemon] Socket file already exists. Exiting...");
-                exit(1);
+            // found before detaching, since the daemon then leaves the directory it was started from
+            let config = config_path();
+            if args.contains(&"--detach".to_string()) {
+                let paths = daemon_log_path()
+                    .and_then(|log_path| Ok((log_path, daemon_pid_path()?, daemon_socket_path()?)));
+                let (log_path, pid_path, socket_path) = match paths {
+                    Ok(paths) => paths,
+                    Err(e) => {
+                        println!("[Daemon] {}", e);
+                        exit(1);
+                        return;
+                    }
+                };
+                match daemonhandler::detach(&log_path) {
+                    Ok(Some(pid)) => {
+                        if !daemonhandler::wait_for_daemon(
+                            pid,
+                            &pid_path,
+                            &socket_path,
+                            daemonhandler::DAEMON_START_TIMEOUT,
+                        ) {
+                            println!("[Daemon] The daemon didn't start, see {}.", log_path);
+                            exit(1);
+                            return;
+                        }
+                        println!("[Daemon] Running in the background (PID {}), logging to {}.", pid, log_path);
+                        return;
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        println!("[Daemon] Failed to detach: {}", e);
+                        exit(1);
+                        return;
+                    }
+                }
             }
+            // the daemon itself works out whether an existing socket is still alive
             println!("Starting Daybreak Daemon...");
             on_shutdown();
-            daemonhandler::main_d();
+            if let Err(e) = daemonhandler::main_d(config) {
+                println!("[Daemon] {}", e);
+                exit(1);
+            }
         }
         "ls" => {
             let attach = args.contains(&"-a".to_string()) || args.contains(&"--attach".to_string());
             let mut frequency: u64 = 1000;
             if args.len() > 1 {
                 let arg = args.iter().position(|s| s == "-t" || s == "--frequency");
-                if arg.is_some() {
-                    let argument = args.get(arg.unwrap() + 1);
+                if let Some(arg) = arg {
+                    let argument = args.get(arg + 1);
                     if argument.is_none() {
                         println!("Please pass your frequency in miliseconds.");
                         exit(1);
@@ -246,27 +360,180 @@ fn main() {
                         exit(1);
                     }
 
-                    frequency = freq_from_user.unwrap().clone();
+                    frequency = freq_from_user.unwrap();
                 }
             }
 
             if attach {
-                let duration = Duration::from_millis(frequency);
-                read_devices_tui::tui();
+                let _duration = Duration::from_millis(frequency);
+                if let Err(e) = read_devices_tui::tui() {
+                    println!("[List Devices] {}", e);
+                    exit(1);
+                }
             } else {
-                println!("{}", read_devices());
+                match daemon_client().devices() {
+                    Ok(Some(devices)) => println!("{}", describe_devices(&devices)),
+                    Ok(None) => println!("[List Devices] No robot available."),
+                    Err(e) => {
+                        println!("[List Devices] {}", e);
+                        exit(1);
+                    }
+                }
             }
         }
-        "download" => {
-            // connect to daemon
-            let stream = UnixStream::connect(format!("{}/daybreak.sock", temp_dir));
-            if stream.is_err() {
-                println!("[Download] Failed to connect to daemon.");
+        "startpos" => {
+            let pos = match args.get(1).map(|pos| pos.to_lowercase()).as_deref() {
+                Some("left") => Pos::LEFT,
+                Some("right") => Pos::RIGHT,
+                _ => {
+                    println!("[StartPos] Please pass a start position.");
+                    println!("Usage: daybreak startpos [left/right]");
+                    exit(1);
+                    return;
+                }
+            };
+            match daemon_client().set_start_pos(pos) {
+                Ok(true) => println!("[StartPos] Robot will start on the {:?} side.", pos),
+                Ok(false) => println!(
+                    "[StartPos] No robot connected. The {:?} side will be sent on connect.",
+                    pos
+                ),
+                Err(e) => {
+                    println!("[StartPos] {}", e);
+                    exit(1);
+                }
+            }
+        }
+        "status" => {
+            let client = daemon_client();
+            let daemon_status = match client.status() {
+                Ok(status) => status,
+                Err(e) => {
+                    println!("[Status] {}", e);
+                    exit(1);
+                    return;
+                }
+            };
+            if args.contains(&"--json".to_string()) {
+                println!("{}", serde_json::to_string_pretty(&daemon_status).unwrap());
+                // the same exit code as the plain output, so scripts can tell Runtime isn't reporting
+                if !matches!(client.runtime_status(), Ok(Some(_))) {
+                    exit(1);
+                }
+                return;
+            }
+            let uptime = daemon_status.uptime_ms / 1000;
+            println!("Daemon PID:         {}", daemon_status.pid);
+            println!(
+                "Uptime:             {}h {:02}m {:02}s",
+                uptime / 3600,
+                uptime / 60 % 60,
+                uptime % 60
+            );
+            println!("Other clients:      {}", daemon_status.clients);
+            println!("Session:            {}", daemon_status.session);
+            let connection = match daemon_status.connection {
+                ConnectionState::Disconnected => "disconnected".to_string(),
+                ConnectionState::Connecting { ip } => format!("connecting to {}", ip),
+                ConnectionState::Connected { ip } => format!("connected to {}", ip),
+                ConnectionState::Replaying { path } => format!("replaying {}", path),
+                ConnectionState::Failed(e) => format!("failed: {}", e),
+            };
+            println!("Connection:         {}", connection);
+            if let Some(robot_type) = daemon_status.robot_type {
+                println!("Robot type:         {:?}", robot_type);
+            }
+            if let Some(link) = daemon_status.link {
+                println!("Link:               {:?}", link);
+            }
+            match daemon_status.run_mode {
+                Some(mode) => println!("Requested mode:     {:?}", mode),
+                None => println!("Requested mode:     none yet"),
+            }
+            if let Some(side) = daemon_status.start_pos {
+                println!("Start position:     {:?}", side);
+            }
+            println!("Input listeners:    {}", daemon_status.input_listeners);
+            match daemon_status.device_age_ms {
+                Some(age) => println!("Devices updated:    {}ms ago", age),
+                None => println!("Devices updated:    never"),
+            }
+
+            // latency is measured by Dawn, so it's worth showing even when Runtime hasn't reported in
+            let runtime_status = client.runtime_status();
+            match &runtime_status {
+                Ok(Some(status)) => {
+                    match status.battery {
+                        Some(volts) => println!("Battery:            {:.2}V", volts),
+                        None => println!("Battery:            no BatteryBuddy reading"),
+                    }
+                    println!("Run mode:           {:?}", status.mode);
+                    println!("Shepherd connected: {}", status.shep_connected);
+                    println!("Dawn connected:     {}", status.dawn_connected);
+                }
+                Ok(None) => println!("[Status] No Runtime status available."),
+                Err(e) => println!("[Status] {}", e),
+            }
+
+            match client.latency().ok().flatten() {
+                Some(latency) => {
+                    println!(
+                        "Latency:            min {}ms / avg {:.1}ms / p95 {}ms ({} samples)",
+                        latency.min_ms, latency.avg_ms, latency.p95_ms, latency.samples
+                    );
+                    println!("Clock skew:         {}ms", latency.skew_ms);
+                    if latency.is_degraded() {
+                        println!("[Status] Warning: the link to the robot is degraded.");
+                    }
+                }
+                None => println!("Latency:            no measurements yet"),
+            }
+            if !matches!(runtime_status, Ok(Some(_))) {
+                exit(1);
+            }
+        }
+        "replay" => {
+            // without a file, replay whatever the session recorded last
+            let path = match args.get(1) {
+                Some(path) => path.to_string(),
+                None => match state_dir() {
+                    Ok(state) => recording_path(&state, selected_session()),
+                    Err(e) => {
+                        println!("[Replay] {}", e);
+                        exit(1);
+                        return;
+                    }
+                },
+            };
+            let path = fs::canonicalize(&path);
+            if path.is_err() {
+                println!("[Replay] Recording not found: {:?}", path.err().unwrap());
+                exit(1);
+                return;
+            }
+            let path = path.unwrap().into_os_string().into_string().unwrap();
+            let frames = read_recording(&path);
+            if frames.is_err() {
+                println!("[Replay] Failed to read {}: {}", path, frames.err().unwrap());
                 exit(1);
+                return;
             }
+            println!("[Replay] Loaded {} frames from {}", frames.unwrap().len(), path);
 
+            let client = daemon_client();
+            if let Err(e) = client.replay(&path) {
+                println!("[Replay] {}", e);
+                exit(1);
+                return;
+            }
+            if let Err(e) = tui(client, true) {
+                println!("[Replay] {}", e);
+                exit(1);
+            }
+        }
+        "download" => {
             // ini file parsing instead of args
-            let ini = Ini::load_from_file("daybreak.ini");
+            let ini = Ini::load_from_file(config_path());
             let mut file_path = "".to_string();
             if let Ok(ini) = ini {
                 let section = ini.section(Some("connection"));
@@ -297,91 +564,11 @@ fn main() {
             } else {
                 file_path = args[1].to_string();
             }
-            let mut stream = stream.unwrap();
-            // send the message '1' for the type of message, then send the file path to upload
-
-            let _ = stream.write(&[5]);
-
-            let _dawn_cwd = stream.write(env::current_dir().unwrap().to_str().unwrap().as_bytes());
-            let _ = stream.write(&[0]);
-            let file_path_bytes = file_path.as_bytes();
-            let _dawn_upload = stream.write(file_path_bytes);
-            let _dawn_flush = stream.flush();
-            if _dawn_flush.is_err() {
-                println!("[Download] Failed to flush stream.");
-                exit(1);
-            }
-
-            println!("[Download] Sent file path to daemon.");
-
-            // read the response
-            let mut buffer = [0; 1];
-            let _dawn_read = stream.read(&mut buffer);
-
-            if _dawn_read.is_err() {
-                println!("[Download] Failed to read from daemon.");
-                exit(1);
-            }
-
-            println!("{}", buffer[0]);
-            match buffer[0] {
-                200 => {
-                    println!("[Download] File is now downloading...");
-
-                    let mut buffer = [0; 1];
-                    let _dawn_read = stream.read(&mut buffer);
-                    if _dawn_read.is_err() {
-                        println!("[Download] Failed to read from daemon.");
-                        exit(1);
-                    }
-
-                    if buffer[0] == 200 {
-                        println!("[Download] File has been downloaded.");
-                    } else {
-                        match buffer[0] {
-                            100 => {
-                                println!("[Download] File does not exist.");
-                            }
-                            104 => {
-                                println!("[Download] Failed to read IP address.");
-                            }
-                            105 => {
-                                println!("[Download] Failed to download file.");
-                            }
-                            101 => {
-                                println!("[Download] Failed to authenticate with ssh.");
-                            }
-                            102 => {
-                                println!("[Download] Failed to connect to ssh.");
-                            }
-                            103 => {
-                                println!("[Download] Failed to read from local file. (Check permissions)");
-                            }
-                            _ => {
-                                println!("[Download] Unknown response from daemon. {}", buffer[0]);
-                            }
-                        }
-                    }
-                }
-                100 => {
-                    println!("[Download] File does not exist.");
-                }
-                50 => {
-                    println!("[Download] No available robot.");
-                }
-                _ => {
-                    println!("[Download] Unknown response from daemon.");
-                }
-            }
+            println!("[Download] Downloading into {}...", file_path);
+            transfer("Download", daemon_client().download(&file_path));
         }
         "upload" => {
-            // connect to daemon
-            let stream = UnixStream::connect(format!("{}/daybreak.sock", temp_dir));
-            if stream.is_err() {
-                println!("[Upload] Failed to connect to daemon.");
-                exit(1);
-            }
-            let ini = Ini::load_from_file("daybreak.ini");
+            let ini = Ini::load_from_file(config_path());
             let mut file_path = "".to_string();
             if let Ok(ini) = ini {
                 let section = ini.section(Some("connection"));
@@ -407,115 +594,61 @@ fn main() {
             } else {
                 file_path = args[1].to_string();
             }
-            let mut stream = stream.unwrap();
-            // send the message '1' for the type of message, then send the file path to upload
-            let file_path_bytes = file_path.as_bytes();
-            let _ = stream.write(&[1]);
-
-            // write the current working directory
-            let _dawn_cwd = stream.write(env::current_dir().unwrap().to_str().unwrap().as_bytes());
-            // write a 0 byte to separate the cwd and the file path
-            let _ = stream.write(&[0]);
-            let _dawn_upload = stream.write(file_path_bytes);
-            // now send it
-            let _dawn_flush = stream.flush();
-            if _dawn_flush.is_err() {
-                println!("[Upload] Failed to flush stream.");
-                exit(1);
-            }
-
-            println!("[Upload] Sent file path to daemon.");
-
-            // read the response
-            let mut buffer = [0; 1];
-            let _dawn_read = stream.read(&mut buffer);
-
-            if _dawn_read.is_err() {
-                println!("[Upload] Failed to read from daemon.");
-                exit(1);
-            }
-            match buffer[0] {
-                200 => {
-                    println!("[Upload] File is now uploading...");
-
-                    let mut buffer = [0; 1];
-                    let _dawn_read = stream.read(&mut buffer);
-                    if _dawn_read.is_err() {
-                        println!("[Upload] Failed to read from daemon.");
-                        exit(1);
-                    }
-
-                    if buffer[0] == 200 {
-                        println!("[Upload] File has been uploaded.");
-                    } else {
-                        match buffer[0] {
-                            100 => {
-                                println!("[Upload] File does not exist.");
-                            }
-                            104 => {
-                                println!("[Upload] Failed to read IP address.");
-                            }
-                            105 => {
-                                println!("[Upload] Failed to upload file.");
-                            }
-                            101 => {
-                                println!("[Upload] Failed to authenticate with ssh.");
-                            }
-                            102 => {
-                                println!("[Upload] Failed to connect to ssh.");
-                            }
-                            103 => {
-                                println!(
-                                    "[Upload] Failed to read from local file. (Check permissions)"
-                                );
-                            }
-                            _ => {
-                                println!("[Upload] Unknown response from daemon.");
-                            }
-                        }
-                    }
-                }
-                100 => {
-                    println!("[Upload] File does not exist.");
-                }
-                _ => {
-                    println!("[Upload] Unknown response from daemon.");
-                }
-            }
+            println!("[Upload] Uploading {}...", file_path);
+            transfer("Upload", daemon_client().upload(&file_path));
         }
         "run" => {
             if args.len() < 2 || args.contains(&"--tui".to_string()) {
-                let stream = UnixStream::connect(format!("{}/daybreak.sock", temp_dir));
-                if stream.is_err() {
-                    println!("[Run] Failed to connect to daemon.");
+                if let Err(e) = tui(daemon_client(), false) {
+                    println!("[Run] {}", e);
                     exit(1);
                 }
-                tui(Arc::new(Mutex::new(stream.unwrap())));
                 return;
             }
             let run_mode = args[1].as_str();
             let run_mode = match run_mode {
-                "auto" => 3,
-                "teleop" => 1,
-                "stop" => 2,
+                "auto" => RunCommand::Auto,
+                "teleop" => RunCommand::Teleop,
+                "stop" => RunCommand::Stop,
                 _ => {
                     println!("[Run] Unknown run mode: {:?}", run_mode);
                     exit(1);
-                    0
+                    return;
                 }
             };
-            let stream = UnixStream::connect(format!("{}/daybreak.sock", temp_dir));
-            if stream.is_err() {
-                println!("[Run] Failed to connect to daemon.");
-                exit(1);
+            let client = daemon_client();
+            // subscribe before starting so the first lines the robot prints aren't missed
+            let mut logs = None;
+            if run_mode != RunCommand::Stop {
+                let subscription = client.subscribe(u64::MAX);
+                if let Err(e) = subscription {
+                    println!("[Run] Failed to subscribe to the robot's logs: {}", e);
+                    exit(1);
+                    return;
+                }
+                let mut subscription = subscription.unwrap();
+                let first = subscription.logs();
+                if let Err(e) = first {
+                    println!("[Run] Failed to subscribe to the robot's logs: {}", e);
+                    exit(1);
+                    return;
+                }
+                for line in first.unwrap() {
+                    println!("{}", line.display());
+                }
+                logs = Some(subscription);
             }
-            let stream = Arc::new(Mutex::new(stream.unwrap()));
-            stream.lock().unwrap().write(&[3]).unwrap();
-            stream.lock().unwrap().write(&[run_mode]).unwrap();
-            let _ = stream.lock().unwrap().flush();
-            println!("[Run] Sent run message to daemon.");
-            println!("[Run] Waiting for response...");
-            if run_mode == 2 {
+            println!("[Run] Sending run message to daemon...");
+            let stream = match client.run(run_mode) {
+                Ok(stream) => stream,
+                Err(e) => {
+                    println!("[Run] {}", e);
+                    exit(1);
+                    return;
+                }
+            };
+            let stream = Arc::new(Mutex::new(stream));
+            if run_mode == RunCommand::Stop {
                 println!("[Run] Completed exit.");
                 exit(0);
             }
@@ -538,58 +671,48 @@ fn main() {
                 }
             });
 
-            thread::spawn(move || {
-                input_executor(
-                    stream_clone,
-                    true,
-                    Arc::new(AtomicBool::new(false)),
-                    terminal_string,
-                );
+            if let Err(e) = stream.lock().unwrap().set_nonblocking(true) {
+                println!("[Run] {}", e);
+                exit(1);
+                return;
+            }
+            // Ctrl-C or losing the daemon ends the run
+            let stopper = Arc::new(AtomicBool::new(false));
+            let log_stopper = Arc::clone(&stopper);
+            let mut logs = logs.unwrap();
+            thread::spawn(move || loop {
+                match logs.logs() {
+                    Ok(lines) => {
+                        for line in lines {
+                            println!("{}", line.display());
+                        }
+                    }
+                    Err(e) => {
+                        println!("[Run] Lost the log stream: {}", e);
+                        log_stopper.store(true, Ordering::Release);
+                        return;
+                    }
+                }
             });
-            stream.lock().unwrap().set_nonblocking(true).unwrap();
-            let mut buffer = vec![];
-            loop {
-                // read from the {TEMP DIR}/robot.run.txt and update the log if there is any new data
-                let file = fs::read_to_string(format!("{}/robot.run.txt", temp_dir));
-                if file.is_err() {
-                    continue;
-                }
-                let file = file.unwrap();
-                if file.len() == 0 {
-                    continue;
-                }
-                let file = file.as_bytes().to_vec();
-                // now compare this file with the buffer, if there is new data at the end, then update the buffer, then send the text to console
-                if file.len() > buffer.len() {
-                    let new_data = &file[buffer.len()..];
-                    let new_data = String::from_utf8(new_data.to_vec()).unwrap();
-                    print!("{}", new_data);
-                    buffer = file;
+
+            if let Err(e) = input_executor(stream_clone, true, Arc::clone(&stopper), terminal_string) {
+                // the robot still runs without a gamepad, so keep showing its logs until Ctrl-C
+                println!("[Run] {}", e);
+                while !stopper.load(Ordering::Acquire) {
+                    thread::sleep(Duration::from_millis(100));
                 }
             }
         }
         "input" => {
-            let stream = UnixStream::connect(format!("{}/daybreak.sock", temp_dir));
-            if stream.is_err() {
-                println!("[Run] Failed to connect to daemon.");
-                exit(1);
-            }
-            let stream = Arc::new(Mutex::new(stream.unwrap()));
-            stream.lock().unwrap().write(&[6]).unwrap();
-            stream.lock().unwrap().flush().unwrap();
-
-            let mut buffer = [0; 1];
-            let read_error = stream.lock().unwrap().read_exact(&mut buffer);
-
-            if read_error.is_err() {
-                println!("[Input] Failed to read daemon.");
-                exit(1);
-            }
-
-            if buffer[0] == 1 {
-                println!("[Input] Daemon refused to fulfill request.");
-                exit(1);
-            }
+            let stream = match daemon_client().listen() {
+                Ok(stream) => stream,
+                Err(e) => {
+                    println!("[Input] Daemon refused to fulfill request: {}", e);
+                    exit(1);
+                    return;
+                }
+            };
+            let stream = Arc::new(Mutex::new(stream));
 
             println!("[Input] Sent input request message to daemon.");
             println!("[Input] Waiting for response...");
@@ -612,37 +735,44 @@ fn main() {
                 }
             });
 
-            thread::spawn(move || {
-                input_executor(
-                    stream_clone,
-                    true,
-                    Arc::new(AtomicBool::new(false)),
-                    terminal_string,
-                );
-            });
-            stream.lock().unwrap().set_nonblocking(true).unwrap();
+            if let Err(e) = stream.lock().unwrap().set_nonblocking(true) {
+                println!("[Input] {}", e);
+                exit(1);
+                return;
+            }
             println!("[Input] Started input listener.");
-        }
-        "shutdown" => {
-            let stream = UnixStream::connect(format!("{}/daybreak.sock", temp_dir));
-            if stream.is_err() {
-                println!("[Shutdown] Failed to connect to daemon.");
+            if let Err(e) = input_executor(stream_clone, true, Arc::new(AtomicBool::new(false)), terminal_string) {
+                println!("[Input] {}", e);
                 exit(1);
             }
-            let mut stream = stream.unwrap();
-            let _ = stream.write(&[255]);
-            let _ = stream.flush();
-            println!("[Shutdown] Sent shutdown message to daemon.");
-            let mut buffer = [0; 1];
-            let _dawn_read = stream.read(&mut buffer);
-            if _dawn_read.is_err() {
-                println!("[Shutdown] Failed to read from daemon.");
+        }
+        "trust" => {
+            let replace = args.iter().any(|arg| arg == "--replace");
+            let fingerprint = args.iter().skip(1).find(|arg| !arg.starts_with("--"));
+            match daemon_client().trust(fingerprint.map(String::as_str), replace) {
+                Ok((fingerprint, true)) => println!("[Trust] Now trusting the robot's host key {}.", fingerprint),
+                Ok((fingerprint, false)) => println!("[Trust] The robot's host key {} was already trusted.", fingerprint),
+                Err(e) => {
+                    println!("[Trust] {}", e);
+                    exit(1);
+                }
+            }
+        }
+        "disconnect" => match daemon_client().disconnect() {
+            Ok(()) => println!("[Disconnect] Disconnected from the robot."),
+            Err(e) => {
+                println!("[Disconnect] {}", e);
                 exit(1);
             }
-            if buffer[0] == 200 {
-                println!("[Shutdown] Daemon has shutdown.");
-            } else {
-                println!("[Shutdown] Daemon failed to shutdown.");
+        },
+        "shutdown" => {
+            println!("[Shutdown] Sending shutdown message to daemon.");
+            match daemon_client().shutdown() {
+                Ok(()) => println!("[Shutdown] Daemon has shutdown."),
+                Err(e) => {
+                    println!("[Shutdown] {}", e);
+                    exit(1);
+                }
             }
         }
         _ => {
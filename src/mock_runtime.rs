@@ -0,0 +1,421 @@
+#[allow(clippy::module_inception)]
+pub mod mock_runtime {
+    use std::{
+        io::{self, ErrorKind, Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::{Arc, Mutex},
+        thread::{self, JoinHandle},
+        time::{Duration, Instant},
+    };
+
+    use protobuf::SpecialFields;
+
+    use crate::robot::robotmanager::{
+        device::{param::Val, DevData, Device, Param},
+        input::UserInputs,
+        now_millis,
+        run_mode::Mode,
+        start_pos::Pos,
+        text::Text,
+        timestamp::TimeStamps,
+        Frame, FrameDecoder,
+    };
+
+    /// What the mock Runtime sends every tick.
+    pub struct Script {
+        pub devices: Vec<Device>,
+        /// Printed one line per tick, but only while the robot is running like real student code.
+        pub logs: Vec<String>,
+        pub interval: Duration,
+    }
+
+    fn param(name: &str, val: Val) -> Param {
+        Param {
+            name: name.to_string(),
+            val: Some(val),
+            readonly: false,
+            special_fields: SpecialFields::default(),
+        }
+    }
+
+    fn device(name: &str, uid: u64, type_: u32, params: Vec<Param>) -> Device {
+        Device {
+            name: name.to_string(),
+            uid,
+            type_,
+            params,
+            special_fields: SpecialFields::default(),
+        }
+    }
+
+    impl Default for Script {
+        fn default() -> Self {
+            Script {
+                devices: vec![
+                    device(
+                        "LimitSwitch",
+                        1,
+                        0,
+                        vec![
+                            param("switch0", Val::Bval(false)),
+                            param("switch1", Val::Bval(true)),
+                            param("switch2", Val::Bval(false)),
+                        ],
+                    ),
+                    device(
+                        "KoalaBear",
+                        2,
+                        6,
+                        vec![
+                            param("velocity_a", Val::Fval(0.0)),
+                            param("velocity_b", Val::Fval(0.0)),
+                            param("enc_a", Val::Ival(0)),
+                            param("enc_b", Val::Ival(0)),
+                        ],
+                    ),
+                    device("BatteryBuddy", 3, 4, vec![param("v_batt", Val::Fval(11.1))]),
+                ],
+                logs: vec![
+                    "Hello from the mock Runtime!\n".to_string(),
+                    "Driving forward\n".to_string(),
+                    "Turning left\n".to_string(),
+                ],
+                interval: Duration::from_millis(100),
+            }
+        }
+    }
+
+    /// Everything Dawn has sent the mock, in order.
+    #[derive(Default, Debug, Clone)]
+    pub struct Recorded {
+        pub run_modes: Vec<Mode>,
+        pub inputs: Vec<UserInputs>,
+        pub start_positions: Vec<Pos>,
+        pub connections: u32,
+    }
+
+    pub struct MockRuntime {
+        listener: TcpListener,
+        script: Arc<Script>,
+        recorded: Arc<Mutex<Recorded>>,
+    }
+
+    impl MockRuntime {
+        pub fn bind(addr: &str, script: Script) -> io::Result<MockRuntime> {
+            Ok(MockRuntime {
+                listener: TcpListener::bind(addr)?,
+                script: Arc::new(script),
+                recorded: Arc::new(Mutex::new(Recorded::default())),
+            })
+        }
+
+        pub fn port(&self) -> u16 {
+            self.listener.local_addr().map(|addr| addr.port()).unwrap_or(0)
+        }
+
+        pub fn recorded(&self) -> Arc<Mutex<Recorded>> {
+            Arc::clone(&self.recorded)
+        }
+
+        /// Accepts Dawn connections forever, one thread each.
+        pub fn serve(self) {
+            println!("[MockRuntime] Listening on port {}", self.port());
+            for stream in self.listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        println!("[MockRuntime] Failed to accept connection: {}", e);
+                        continue;
+                    }
+                };
+                let script = Arc::clone(&self.script);
+                let recorded = Arc::clone(&self.recorded);
+                thread::spawn(move || {
+                    if let Err(e) = serve_connection(stream, &script, &recorded) {
+                        println!("[MockRuntime] Connection ended: {}", e);
+                    }
+                });
+            }
+        }
+
+        pub fn spawn(self) -> JoinHandle<()> {
+            thread::spawn(move || self.serve())
+        }
+    }
+
+    fn send(stream: &mut TcpStream, frame: Frame) -> io::Result<()> {
+        stream.write_all(&frame.encode()?)?;
+        stream.flush()
+    }
+
+    fn serve_connection(
+        mut stream: TcpStream,
+        script: &Script,
+        recorded: &Mutex<Recorded>,
+    ) -> io::Result<()> {
+        let mut identify = [0; 1];
+        stream.read_exact(&mut identify)?;
+        println!("[MockRuntime] Client identified as {}", identify[0]);
+        recorded.lock().unwrap().connections += 1;
+        stream.set_read_timeout(Some(Duration::from_millis(10)))?;
+
+        let mut mode = Mode::IDLE;
+        let mut decoder = FrameDecoder::new();
+        let mut read_buffer = [0u8; 4096];
+        let mut last_tick = Instant::now() - script.interval;
+        let mut next_log = 0;
+        loop {
+            if last_tick.elapsed() >= script.interval {
+                last_tick = Instant::now();
+                send(
+                    &mut stream,
+                    Frame::DeviceData(DevData {
+                        devices: script.devices.clone(),
+                        special_fields: SpecialFields::default(),
+                    }),
+                )?;
+                if mode != Mode::IDLE && !script.logs.is_empty() {
+                    send(
+                        &mut stream,
+                        Frame::Log(Text {
+                            payload: vec![script.logs[next_log % script.logs.len()].clone()],
+                            special_fields: SpecialFields::default(),
+                        }),
+                    )?;
+                    next_log += 1;
+                }
+            }
+
+            match stream.read(&mut read_buffer) {
+                Ok(0) => return Ok(()),
+                Ok(read) => decoder.push(&read_buffer[..read]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {}
+                Err(e) => return Err(e),
+            }
+
+            while let Some(frame) = decoder.next_frame() {
+                let frame = match frame {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        println!("[MockRuntime] {}", e);
+                        continue;
+                    }
+                };
+                match frame {
+                    Frame::RunMode(run_mode) => {
+                        mode = run_mode.mode.enum_value_or_default();
+                        println!("[MockRuntime] RunMode {:?}", mode);
+                        recorded.lock().unwrap().run_modes.push(mode);
+                    }
+                    Frame::Inputs(inputs) => {
+                        recorded.lock().unwrap().inputs.push(inputs);
+                    }
+                    Frame::StartPos(start_pos) => {
+                        let pos = start_pos.pos.enum_value_or_default();
+                        println!("[MockRuntime] StartPos {:?}", pos);
+                        recorded.lock().unwrap().start_positions.push(pos);
+                    }
+                    Frame::TimeStamps(ping) => {
+                        send(
+                            &mut stream,
+                            Frame::TimeStamps(TimeStamps {
+                                dawn_timestamp: ping.dawn_timestamp,
+                                runtime_timestamp: now_millis(),
+                                special_fields: SpecialFields::default(),
+                            }),
+                        )?;
+                    }
+                    other => println!("[MockRuntime] Ignoring {:?}", other.msg_type()),
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::robot::robotmanager::{
+            input::{Input, Source},
+            read_recording, recording_path, state_dir, robot_socket_path, ConnectOptions, Direction, Robot,
+            RobotReply, RobotRequest,
+        };
+        use crate::client::client::DaybreakClient;
+        use crate::ipc::ipc::{receive, send, Event, RobotType, RunCommand, StartSide};
+        use crate::run_log::run_log::decode_log_lines;
+        use crate::test_support::test_support::{eventually, start_daemon};
+        use protobuf::{EnumOrUnknown, Message};
+        use std::{
+            fs,
+            os::unix::net::UnixStream,
+            sync::mpsc::{self, Receiver},
+        };
+
+        // waits for the robot thread and plays the daemon's side of the robot socket
+        fn attach_daemon(session: &str) -> UnixStream {
+            for _ in 0..100 {
+                if let Ok(socket) = UnixStream::connect(robot_socket_path(session).unwrap()) {
+                    return socket;
+                }
+                thread::sleep(Duration::from_millis(20));
+            }
+            panic!("robot socket never came up");
+        }
+
+        fn request(daemon: &mut UnixStream, request: RobotRequest) -> RobotReply {
+            send(daemon, &request).unwrap();
+            receive(daemon).unwrap()
+        }
+
+        // the first thing the robot thread reports that `wanted` picks out
+        fn reported<T>(reports: &Receiver<Event>, what: &str, mut wanted: impl FnMut(Event) -> Option<T>) -> T {
+            eventually(what, || reports.try_iter().find_map(&mut wanted))
+        }
+
+        #[test]
+        fn robot_manager_against_mock_runtime() {
+            let mock = MockRuntime::bind("127.0.0.1:0", Script::default()).unwrap();
+            let port = mock.port();
+            let recorded = mock.recorded();
+            mock.spawn();
+
+            let session = format!("mock-{}", std::process::id());
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            stream.write_all(&[1]).unwrap();
+            stream.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+            let (events, reports) = mpsc::channel();
+            // a config and state dir of the test's own, so nothing on this machine is read or written
+            let state = format!("{}/daybreak-mock-state-{}", std::env::temp_dir().display(), std::process::id());
+            fs::create_dir_all(&state).unwrap();
+            let robot = Robot {
+                session: session.clone(),
+                config: std::env::temp_dir().join(format!("daybreak-no-config-{}.ini", std::process::id())),
+                state: Some(state.clone()),
+                events,
+            };
+            thread::spawn(move || robot.main_loop(stream, FrameDecoder::new(), Some("127.0.0.1".to_string()), ConnectOptions::default()));
+            let mut daemon = attach_daemon(&session);
+
+            let devices = reported(&reports, "devices", |event| match event {
+                Event::Devices(devices) => Some(devices),
+                _ => None,
+            });
+            let devices = DevData::parse_from_bytes(&devices).unwrap();
+            assert_eq!(devices.devices, Script::default().devices);
+
+            // start position, teleop and a gamepad input all reach the Runtime
+            send(&mut daemon, &RobotRequest::SetStartPos(StartSide::Right)).unwrap();
+            send(&mut daemon, &RobotRequest::Run(RunCommand::Teleop)).unwrap();
+            let input = Input {
+                connected: true,
+                buttons: 0b101,
+                axes: vec![0.5, 0.0, 0.0, -0.5],
+                source: EnumOrUnknown::new(Source::GAMEPAD),
+                special_fields: SpecialFields::default(),
+            }
+            .write_to_bytes()
+            .unwrap();
+            send(&mut daemon, &RobotRequest::Input(input)).unwrap();
+
+            eventually("the input to reach Runtime", || {
+                let recorded = recorded.lock().unwrap();
+                (!recorded.inputs.is_empty()).then_some(())
+            });
+            {
+                let recorded = recorded.lock().unwrap();
+                assert_eq!(recorded.start_positions, vec![Pos::RIGHT]);
+                assert_eq!(recorded.run_modes, vec![Mode::TELEOP]);
+                assert_eq!(recorded.inputs[0].inputs[0].buttons, 0b101);
+            }
+
+            // the status follows the mode we asked for, and Runtime answers our pings
+            let status = reported(&reports, "the teleop status", |event| match event {
+                Event::RuntimeStatus(status) => Some(status).filter(|status| status.mode == Mode::TELEOP),
+                _ => None,
+            });
+            assert_eq!((status.battery, status.dawn_connected, status.shep_connected), (Some(11.1), true, false));
+            let latency = reported(&reports, "a latency sample", |event| match event {
+                Event::Latency(latency) => Some(latency),
+                _ => None,
+            });
+            assert!(latency.samples > 0);
+
+            // scripted logs land in this session's log buffer, tagged with the mode they arrived in
+            let line = eventually("the scripted log line", || {
+                let RobotReply::Logs(batch) = request(&mut daemon, RobotRequest::Logs { since: 0 }) else {
+                    return None;
+                };
+                let (_, lines) = decode_log_lines(&batch)?;
+                lines.into_iter().find(|line| line.text == "Hello from the mock Runtime!")
+            });
+            assert_eq!(line.mode, Mode::TELEOP);
+            assert_eq!(line.session, session);
+
+            send(&mut daemon, &RobotRequest::Run(RunCommand::Stop)).unwrap();
+            eventually("the robot to stop", || {
+                let recorded = recorded.lock().unwrap();
+                (recorded.run_modes.last() == Some(&Mode::IDLE)).then_some(())
+            });
+            drop(daemon);
+            // and everything crossed the link into the session recording
+            let recorded = read_recording(&recording_path(&state, &session)).unwrap();
+            assert!(recorded
+                .iter()
+                .any(|frame| frame.direction == Direction::Outbound && frame.packet[0] == 5));
+            assert!(recorded.iter().any(|frame| frame.direction == Direction::Inbound));
+            let _ = fs::remove_dir_all(&state);
+        }
+
+        // the whole way down: client, daemon, robot thread and the mock in place of Runtime
+        #[test]
+        fn a_client_drives_the_mock_runtime_through_the_daemon() {
+            let mock = MockRuntime::bind("127.0.0.1:0", Script::default()).unwrap();
+            let port = mock.port();
+            let recorded = mock.recorded();
+            mock.spawn();
+            let client = DaybreakClient::new()
+                .with_socket(&start_daemon("mock"))
+                .with_session(&format!("mock-daemon-{}", std::process::id()));
+
+            // chosen before there's a robot, so the daemon hands it over on connect
+            assert!(!client.set_start_pos(Pos::LEFT).unwrap());
+            client
+                .connect("127.0.0.1", RobotType::Raspberry, ConnectOptions { port, ..ConnectOptions::default() })
+                .unwrap();
+            let mut run = client.run(RunCommand::Teleop).unwrap();
+            let input = Input {
+                connected: true,
+                buttons: 0b11,
+                axes: vec![0.0, 1.0, 0.0, 0.0],
+                source: EnumOrUnknown::new(Source::KEYBOARD),
+                special_fields: SpecialFields::default(),
+            };
+            run.send_input(&input).unwrap();
+            eventually("the input to reach Runtime", || {
+                let recorded = recorded.lock().unwrap();
+                let mut inputs = recorded.inputs.iter().flat_map(|inputs| inputs.inputs.iter());
+                inputs.any(|sent| sent.buttons == 0b11).then_some(())
+            });
+
+            let devices = eventually("devices from the daemon", || client.devices().unwrap());
+            assert_eq!(devices.devices, Script::default().devices);
+            let status = eventually("the teleop status", || {
+                let status = client.runtime_status().unwrap();
+                status.filter(|status| status.mode == Mode::TELEOP)
+            });
+            assert_eq!((status.battery, status.dawn_connected), (Some(11.1), true));
+            {
+                let recorded = recorded.lock().unwrap();
+                assert_eq!(recorded.connections, 1);
+                assert_eq!(recorded.start_positions, vec![Pos::LEFT]);
+                assert_eq!(recorded.run_modes, vec![Mode::TELEOP]);
+            }
+
+            run.stop().unwrap();
+            eventually("Runtime to idle", || {
+                (recorded.lock().unwrap().run_modes.last() == Some(&Mode::IDLE)).then_some(())
+            });
+            client.disconnect().unwrap();
+            let _ = fs::remove_file(recording_path(&state_dir().unwrap(), client.session()));
+        }
+    }
+}
@@ -1,330 +1,1180 @@
 pub mod robotmanager {
-    use std::{collections::LinkedList, fs, io::{Read, Write}, net::{SocketAddr, TcpStream}, os::unix::net::UnixListener, str::FromStr, sync::Arc, thread, time::Duration};
+    use std::{collections::VecDeque, fmt, fs, io::{self, ErrorKind, Read, Write}, net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream}, path::{Path, PathBuf}, os::unix::{fs::{DirBuilderExt, MetadataExt}, net::{UnixListener, UnixStream}}, str::FromStr, sync::{mpsc::{self, Receiver, Sender, TryRecvError}, Arc}, thread, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
     include!(concat!(env!("OUT_DIR"), "/protos/mod.rs"));
-    use device::{DevData, Param};
-    use input::{Input, Source, UserInputs};
+    use crate::error::error::DaybreakError;
+    use crate::ipc::ipc::{send, take_message, Event, RunCommand, StartSide};
+    use crate::run_log::run_log::{encode_log_lines, persisted_log_path, LogBuffer, LOG_HISTORY};
+    use crate::runtime_security::runtime_security::{handshake, SecurityConfig};
+    use device::DevData;
+    use gamestate::{GameState, State};
+    use input::{Input, UserInputs};
     use protobuf::{EnumOrUnknown, Message, SpecialFields};
+    use serde::{Deserialize, Serialize};
     use run_mode::{Mode, RunMode};
+    use security::SecurityMessage;
+    use start_pos::{Pos, StartPos};
     use text::Text;
-
-    use crate::daemon;
+    use timestamp::TimeStamps;
 
     const HEADER_SIZE: usize = 3;
+    const PING_INTERVAL: Duration = Duration::from_secs(1);
+    const LATENCY_HISTORY: usize = 60;
+    /// Round trips slower than this (at the 95th percentile) mean the link is degrading.
+    pub const LATENCY_WARN_MS: u32 = 100;
+    const DEFAULT_PORT: u16 = 8101;
+    const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+    const CONNECT_RETRIES: u32 = 3;
+    const RECONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+    /// Runtime streams device data constantly and echoes our pings, so this much silence means the link is dead.
+    const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(5);
+    const RECONNECT_BASE: Duration = Duration::from_millis(500);
+    const RECONNECT_MAX: Duration = Duration::from_secs(16);
+    const RECONNECT_ATTEMPTS: u32 = 10;
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub enum MsgType {
         RunMode = 0,
         StartPos = 1,
         Log = 2,
         DeviceData = 3,
+        // 4 is held back by Runtime for its old challenge messages
         Inputs = 5,
-        TimeStamps = 6
+        TimeStamps = 6,
+        GameState = 7,
+        // not Runtime's, only for the opt-in handshake in runtime_security
+        Security = 8
     }
 
-    pub enum EventType {
-        RobotStart = 1,
-        RobotStop = 2,
-        RobotAuto = 3,
-        RobotGiveDevices = 4,
-        KeyPress = 5
+    pub fn message_type_from_byte(message_type: u8) -> Option<MsgType> {
+        match message_type {
+            0 => Some(MsgType::RunMode),
+            1 => Some(MsgType::StartPos),
+            2 => Some(MsgType::Log),
+            3 => Some(MsgType::DeviceData),
+            5 => Some(MsgType::Inputs),
+            6 => Some(MsgType::TimeStamps),
+            7 => Some(MsgType::GameState),
+            8 => Some(MsgType::Security),
+            _ => None
+        }
     }
-    pub struct Robot {
+
+    /// Largest payload the u16 length in a Runtime frame header can describe.
+    pub const MAX_FRAME_PAYLOAD: usize = u16::MAX as usize;
+    /// Replies on the daemon and robot sockets aren't bound by Runtime's header, but nothing honest gets this big.
+    pub const MAX_REPLY_SIZE: usize = 64 << 20;
+
+    /// Fails with `InvalidInput` rather than wrap the length of a message too big for one frame.
+    pub fn encode_packet(msg_type: MsgType, message: &[u8]) -> io::Result<Vec<u8>> {
+        let message_length = message.len();
+        // a wrapped length would desync Runtime's decoder for everything after it
+        if message_length > MAX_FRAME_PAYLOAD {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "a {} byte {:?} message doesn't fit in a frame, which holds at most {} bytes",
+                    message_length, msg_type, MAX_FRAME_PAYLOAD
+                ),
+            ));
+        }
+        let mut packet = vec![0u8; HEADER_SIZE + message_length]; // Pre-allocate the packet buffer
+
+        // Set the message type at index 0
+        packet[0] = msg_type as u8;
+
+        // Set the message length in little-endian order at indices 1 and 2
+        let length_bytes = (message_length as u16).to_le_bytes();
+        packet[1..3].copy_from_slice(&length_bytes);
+
+        // Copy the message payload starting at index 3
+        packet[3..].copy_from_slice(message);
+
+        Ok(packet)
     }
 
-    impl Robot {
-        pub fn query_message_type(&self, message: &Vec<u8>) -> Option<MsgType> {
-            let message_type = message[0];
-            return match message_type {
-                0 => Some(MsgType::RunMode),
-                1 => Some(MsgType::StartPos),
-                2 => Some(MsgType::Log),
-                3 => Some(MsgType::DeviceData),
-                5 => Some(MsgType::Inputs),
-                6 => Some(MsgType::TimeStamps),
-                _ => None
+    /// A single packet on the Runtime TCP link, with its payload already parsed.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum Frame {
+        RunMode(RunMode),
+        StartPos(StartPos),
+        Log(Text),
+        DeviceData(DevData),
+        GameState(GameState),
+        Inputs(UserInputs),
+        TimeStamps(TimeStamps),
+        Security(SecurityMessage),
+    }
+
+    impl Frame {
+        pub fn msg_type(&self) -> MsgType {
+            match self {
+                Frame::RunMode(_) => MsgType::RunMode,
+                Frame::StartPos(_) => MsgType::StartPos,
+                Frame::Log(_) => MsgType::Log,
+                Frame::DeviceData(_) => MsgType::DeviceData,
+                Frame::GameState(_) => MsgType::GameState,
+                Frame::Inputs(_) => MsgType::Inputs,
+                Frame::TimeStamps(_) => MsgType::TimeStamps,
+                Frame::Security(_) => MsgType::Security,
             }
         }
-        pub fn query_event_type(&self, message: &Vec<u8>) -> Option<EventType> {
-            let message_type = message[0];
-            return match message_type {
-                1 => Some(EventType::RobotStart),
-                2 => Some(EventType::RobotStop),
-                3 => Some(EventType::RobotAuto),
-                4 => Some(EventType::RobotGiveDevices),
-                5 => Some(EventType::KeyPress),
-                _ => None
+
+        pub fn parse(msg_type: MsgType, payload: &[u8]) -> Result<Frame, protobuf::Error> {
+            Ok(match msg_type {
+                MsgType::RunMode => Frame::RunMode(RunMode::parse_from_bytes(payload)?),
+                MsgType::StartPos => Frame::StartPos(StartPos::parse_from_bytes(payload)?),
+                MsgType::Log => Frame::Log(Text::parse_from_bytes(payload)?),
+                MsgType::DeviceData => Frame::DeviceData(DevData::parse_from_bytes(payload)?),
+                MsgType::GameState => Frame::GameState(GameState::parse_from_bytes(payload)?),
+                MsgType::Inputs => Frame::Inputs(UserInputs::parse_from_bytes(payload)?),
+                MsgType::TimeStamps => Frame::TimeStamps(TimeStamps::parse_from_bytes(payload)?),
+                MsgType::Security => Frame::Security(SecurityMessage::parse_from_bytes(payload)?),
+            })
+        }
+
+        pub fn encode(&self) -> io::Result<Vec<u8>> {
+            if let Frame::Log(log) = self {
+                if log.compute_size() as usize > MAX_FRAME_PAYLOAD {
+                    // logs are only lines, so a burst too big for one frame can go out as several
+                    let chunks = split_log(log).into_iter().map(|chunk| Frame::Log(chunk).encode());
+                    return Ok(chunks.collect::<io::Result<Vec<_>>>()?.concat());
+                }
             }
+            let payload = match self {
+                Frame::RunMode(message) => message.write_to_bytes(),
+                Frame::StartPos(message) => message.write_to_bytes(),
+                Frame::Log(message) => message.write_to_bytes(),
+                Frame::DeviceData(message) => message.write_to_bytes(),
+                Frame::GameState(message) => message.write_to_bytes(),
+                Frame::Inputs(message) => message.write_to_bytes(),
+                Frame::TimeStamps(message) => message.write_to_bytes(),
+                Frame::Security(message) => message.write_to_bytes(),
+            };
+            encode_packet(self.msg_type(), &payload?)
         }
+    }
 
-        pub fn exit(code: i32) {
-            std::process::exit(code);
+    // packs log lines into Text messages that each fit in a frame, cutting lines that can't fit alone
+    fn split_log(log: &Text) -> Vec<Text> {
+        // room for each line's tag and length varint
+        const LINE_BUDGET: usize = MAX_FRAME_PAYLOAD - 8;
+        let mut pieces: Vec<&str> = vec![];
+        for line in &log.payload {
+            let mut rest = line.as_str();
+            while rest.len() > LINE_BUDGET {
+                let mut cut = LINE_BUDGET;
+                while !rest.is_char_boundary(cut) {
+                    cut -= 1;
+                }
+                pieces.push(&rest[..cut]);
+                rest = &rest[cut..];
+            }
+            pieces.push(rest);
         }
 
+        let mut chunks = vec![];
+        let mut chunk = Text::new();
+        let mut size = 0;
+        for piece in pieces {
+            let piece_size = piece.len() + 8;
+            if size + piece_size > MAX_FRAME_PAYLOAD && !chunk.payload.is_empty() {
+                chunks.push(std::mem::take(&mut chunk));
+                size = 0;
+            }
+            chunk.payload.push(piece.to_string());
+            size += piece_size;
+        }
+        chunks.push(chunk);
+        chunks
+    }
 
-        pub fn main_loop(&self, mut stream: TcpStream) {
-            let temp_dir = std::env::temp_dir().into_os_string().into_string().unwrap();
-            if fs::metadata(format!("{}/daybreak.robot.sock", temp_dir)).is_ok() {
-                let _ = fs::remove_file(format!("{}/daybreak.robot.sock", temp_dir));
+    #[derive(Debug)]
+    pub enum FrameError {
+        UnknownType(u8),
+        Malformed(MsgType, protobuf::Error),
+    }
+
+    impl fmt::Display for FrameError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                FrameError::UnknownType(message_type) => write!(f, "Unknown message type: {}", message_type),
+                FrameError::Malformed(msg_type, e) => write!(f, "Failed to parse {:?}: {}", msg_type, e),
             }
-            let listener = UnixListener::bind(format!("{}/daybreak.robot.sock", temp_dir));
-            if listener.is_err() {
-                println!("[Connection] Failed to bind to socket.");
-                println!("error: {:?}", listener.err());
-                return;
+        }
+    }
+
+    /// Incrementally splits a byte stream into frames, regardless of how the
+    /// stream was chunked when it was read.
+    #[derive(Default)]
+    pub struct FrameDecoder {
+        buffer: Vec<u8>,
+    }
+
+    impl FrameDecoder {
+        pub fn new() -> FrameDecoder {
+            FrameDecoder { buffer: vec![] }
+        }
+
+        pub fn push(&mut self, bytes: &[u8]) {
+            self.buffer.extend_from_slice(bytes);
+        }
+
+        /// Number of bytes waiting for the rest of their frame.
+        pub fn pending(&self) -> usize {
+            self.buffer.len()
+        }
+
+        /// Pops the next complete frame. Unknown or malformed frames are still
+        /// consumed so the stream stays in sync.
+        pub fn next_frame(&mut self) -> Option<Result<Frame, FrameError>> {
+            self.next_packet().map(|packet| parse_packet(&packet))
+        }
+
+        /// Pops the next complete frame's bytes as they came off the wire, header included.
+        pub fn next_packet(&mut self) -> Option<Vec<u8>> {
+            if self.buffer.len() < HEADER_SIZE {
+                return None;
+            }
+            let msg_length = u16::from_le_bytes([self.buffer[1], self.buffer[2]]) as usize;
+            if self.buffer.len() < HEADER_SIZE + msg_length {
+                return None;
             }
-            let listener = listener.unwrap();
+            Some(self.buffer.drain(..HEADER_SIZE + msg_length).collect())
+        }
+    }
 
-            println!("[Connection] Listening on {}/daybreak.robot.sock", temp_dir);
-            println!("[Connection] Waiting for Daemon to connect...");
-            let daemon_socket = listener.accept();
-            if daemon_socket.is_err() {
-                println!("[Connection] Failed to accept connection from Daemon.");
-                return;
+    /// Parses one whole packet as `FrameDecoder::next_packet` hands it out.
+    pub fn parse_packet(packet: &[u8]) -> Result<Frame, FrameError> {
+        let payload = &packet[HEADER_SIZE..];
+
+        let msg_type = message_type_from_byte(packet[0]);
+        if msg_type.is_none() {
+            return Err(FrameError::UnknownType(packet[0]));
+        }
+        let msg_type = msg_type.unwrap();
+        Frame::parse(msg_type, payload).map_err(|e| FrameError::Malformed(msg_type, e))
+    }
+
+    /// Battery voltage as a BatteryBuddy on the robot reads it.
+    pub fn battery_volts(devices: &DevData) -> Option<f32> {
+        let buddy = devices.devices.iter().find(|device| device.name == "BatteryBuddy")?;
+        match buddy.params.iter().find(|param| param.name == "v_batt")?.val {
+            Some(device::param::Val::Fval(volts)) => Some(volts),
+            _ => None,
+        }
+    }
+
+    /// What Daybreak can tell of Runtime from its end of the link. Runtime sends no status of its own,
+    /// so there's no version to show.
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+    pub struct RuntimeStatus {
+        /// Runtime has passed on a field event from Shepherd since the link came up.
+        pub shep_connected: bool,
+        pub dawn_connected: bool,
+        #[serde(with = "enum_number")]
+        pub mode: Mode,
+        /// Volts, once a BatteryBuddy has reported them.
+        pub battery: Option<f32>,
+    }
+
+    impl RuntimeStatus {
+        pub fn observed(link: LinkState, run_mode: Mode, battery: Option<f32>, shepherd_heard: bool) -> RuntimeStatus {
+            let connected = link == LinkState::Connected;
+            RuntimeStatus {
+                shep_connected: connected && shepherd_heard,
+                dawn_connected: connected,
+                mode: run_mode,
+                battery,
             }
-            let (mut daemon_socket, _) = daemon_socket.unwrap();
-            println!("[Connection] Accepted connection from Daemon.");
-            println!("[Connection] Started Main loop.");
-            daemon_socket.set_nonblocking(true).unwrap();
+        }
+    }
 
-            let mut is_running = false;
-            let mut recent_dev_data: Option<Vec<u8>> = None;
+    pub fn now_millis() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+    }
 
-            let mut leftover_bytes: Vec<u8> = vec![];
-            // clear the log file
-            loop {
-                let mut event_buffer: [u8; 1] = [0; 1];
-                let event_received = daemon_socket.read(&mut event_buffer);
-                if event_received.is_ok() {
-                    let event = event_buffer.to_vec();
-                    let event_type = self.query_event_type(&event);
-                    if event_type.is_none() {
-                        println!("[Event] Unknown event type: {:?}", event[0]);
-                        continue;
-                    }
-                    match event_type.unwrap() {
-                        EventType::RobotStart => {
-                            let message = self.send_run_mode(&RunMode {
-                                mode: EnumOrUnknown::from(Mode::TELEOP),
-                                special_fields: SpecialFields::default(),
-                            });
-                            stream.write(message.as_slice()).unwrap();
-                            stream.flush().unwrap();
-                            println!("[RunMode] Started Running.");
-                            is_running = true;
-                        },
-                        EventType::RobotStop => {
-                            let message = self.send_run_mode(&RunMode {
-                                mode: EnumOrUnknown::from(Mode::IDLE),
-                                special_fields: SpecialFields::default(),
-                            });
-                            stream.write(message.as_slice()).unwrap();
-                            stream.flush().unwrap();
-                            println!("[RunMode] Stopped Running.");
-                            is_running = false;
-                        },
-                        EventType::RobotAuto => {
-                            let message = self.send_run_mode(&RunMode {
-                                mode: EnumOrUnknown::from(Mode::AUTO),
-                                special_fields: SpecialFields::default(),
-                            });
-                            stream.write(message.as_slice()).unwrap();
-                            stream.flush().unwrap();
-                            println!("[RunMode] Started Auto.");
-                            is_running = true;
-                        },
-                        EventType::RobotGiveDevices => {
-                            if recent_dev_data.as_ref().is_none() {
-                                daemon_socket.write(&[0]).unwrap();
-                                daemon_socket.flush().unwrap();
-                                continue;
-                            }
-                            daemon_socket.write(&[1]).unwrap();
-                            // send the length as well so the daemon knows how much to read
-                            let length = recent_dev_data.as_ref().unwrap().len();
-                            daemon_socket.write(&[(length & 0x00FF) as u8]).unwrap();           // Low byte
-                            daemon_socket.write(&[((length & 0xFF00) >> 8) as u8]).unwrap();    // High byte shifted correctly
-                            daemon_socket.write(&recent_dev_data.as_ref().unwrap()).unwrap();
-                            daemon_socket.flush().unwrap();
-                        },
-                        EventType::KeyPress => {
-                            // Read 2 length bytes
-                            let mut length_bytes = [0u8; 2];
-                            let _length_read = daemon_socket.read_exact(&mut length_bytes);
-                            if _length_read.is_err() {
-                                continue;
-                            }
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+    pub struct LatencyStats {
+        pub samples: u32,
+        pub min_ms: u32,
+        pub avg_ms: f32,
+        pub p95_ms: u32,
+        /// How far the runtime clock is ahead of ours, assuming a symmetric link.
+        pub skew_ms: i32,
+    }
 
-                            // Convert length bytes to u16 (little endian)
-                            let msg_length = u16::from_le_bytes(length_bytes) as usize;
+    impl LatencyStats {
+        pub fn is_degraded(&self) -> bool {
+            self.p95_ms > LATENCY_WARN_MS
+        }
+    }
 
-                            // Read payload of specified length
-                            let mut payload = vec![0u8; msg_length];
-                            let _payload_read = daemon_socket.read_exact(&mut payload);
-                            if _payload_read.is_err() {
-                                continue;
-                            }
-                            let input = Input::parse_from_bytes(&payload);
-                            if input.is_err() {
-                                println!("[Robot @Inputs] Failed to parse input from Daemon.");
-                                continue;
+    /// Rolling history of TimeStamps round trips.
+    pub struct LatencyTracker {
+        // (round trip, skew) in milliseconds
+        samples: VecDeque<(u32, i32)>,
+        capacity: usize,
+    }
+
+    impl LatencyTracker {
+        pub fn new(capacity: usize) -> LatencyTracker {
+            LatencyTracker { samples: VecDeque::with_capacity(capacity), capacity }
+        }
+
+        /// Records an echoed ping that came back at `received_at` (ms since the epoch).
+        pub fn record(&mut self, echo: &TimeStamps, received_at: u64) {
+            let rtt = received_at.saturating_sub(echo.dawn_timestamp);
+            let skew = echo.runtime_timestamp as i64 - (echo.dawn_timestamp + rtt / 2) as i64;
+            if self.samples.len() == self.capacity {
+                self.samples.pop_front();
+            }
+            self.samples.push_back((rtt as u32, skew as i32));
+        }
+
+        pub fn stats(&self) -> Option<LatencyStats> {
+            if self.samples.is_empty() {
+                return None;
+            }
+            let mut rtts: Vec<u32> = self.samples.iter().map(|(rtt, _)| *rtt).collect();
+            rtts.sort_unstable();
+            let count = rtts.len();
+            let p95_index = (count * 95).div_ceil(100) - 1;
+            Some(LatencyStats {
+                samples: count as u32,
+                min_ms: rtts[0],
+                avg_ms: rtts.iter().sum::<u32>() as f32 / count as f32,
+                p95_ms: rtts[p95_index],
+                skew_ms: (self.samples.iter().map(|(_, skew)| *skew as i64).sum::<i64>() / count as i64) as i32,
+            })
+        }
+    }
+
+    /// The newest field event, numbered so clients can tell a repeat from a new one.
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+    pub struct GameAlert {
+        pub seq: u32,
+        #[serde(with = "enum_number")]
+        pub state: State,
+    }
+
+    // protobuf enums by their number, since they have no serde of their own
+    mod enum_number {
+        use protobuf::Enum;
+        use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<E: Enum, S: Serializer>(value: &E, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_i32(value.value())
+        }
+
+        pub fn deserialize<'de, E: Enum, D: Deserializer<'de>>(deserializer: D) -> Result<E, D::Error> {
+            let number = i32::deserialize(deserializer)?;
+            E::from_i32(number).ok_or_else(|| D::Error::custom(format!("unknown {} {}", E::NAME, number)))
+        }
+    }
+
+    /// Health of the TCP link to Runtime, as reported to daemon clients.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum LinkState {
+        Connected,
+        Reconnecting,
+        /// Reconnecting failed too many times in a row; Runtime is still dialled every `RECONNECT_MAX`.
+        /// A replay's link stays lost once the recording runs out.
+        Lost,
+    }
+
+    /// Exponential backoff between reconnect attempts.
+    pub struct Backoff {
+        base: Duration,
+        max: Duration,
+        attempts: u32,
+    }
+
+    impl Backoff {
+        pub fn new(base: Duration, max: Duration) -> Backoff {
+            Backoff { base, max, attempts: 0 }
+        }
+
+        pub fn attempts(&self) -> u32 {
+            self.attempts
+        }
+
+        pub fn next_delay(&mut self) -> Duration {
+            let delay = self.base.saturating_mul(1 << self.attempts.min(16)).min(self.max);
+            self.attempts += 1;
+            delay
+        }
+
+        pub fn reset(&mut self) {
+            self.attempts = 0;
+        }
+    }
+
+    /// How to reach Runtime. Tunnels and simulators rarely sit on the robot's own port.
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+    pub struct ConnectOptions {
+        pub port: u16,
+        pub timeout: Duration,
+        /// Extra attempts after the first connect fails.
+        pub retries: u32,
+        /// Wait before the first retry, doubling for each one after.
+        pub backoff: Duration,
+    }
+
+    /// Settings `ConnectOptions::set` understands, as daybreak.ini keys and, with `-` for `_`, `--connect` flags.
+    pub const CONNECT_OPTION_KEYS: [&str; 4] = ["port", "timeout", "retries", "backoff"];
+
+    impl Default for ConnectOptions {
+        fn default() -> Self {
+            ConnectOptions {
+                port: DEFAULT_PORT,
+                timeout: CONNECT_TIMEOUT,
+                retries: CONNECT_RETRIES,
+                backoff: RECONNECT_BASE,
+            }
+        }
+    }
+
+    impl ConnectOptions {
+        /// Sets one option from text. Timeouts and backoff are in milliseconds.
+        pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+            let value = value.trim();
+            let invalid = || format!("Invalid value for {}: {:?}", key, value);
+            match key {
+                "port" => self.port = value.parse().ok().filter(|port| *port != 0).ok_or_else(invalid)?,
+                "timeout" => {
+                    let millis: u64 = value.parse().ok().filter(|millis| *millis != 0).ok_or_else(invalid)?;
+                    self.timeout = Duration::from_millis(millis);
+                }
+                "retries" => self.retries = value.parse::<u8>().map_err(|_| invalid())? as u32,
+                "backoff" => self.backoff = Duration::from_millis(value.parse().map_err(|_| invalid())?),
+                _ => return Err(format!("Unknown connection option: {}", key)),
+            }
+            Ok(())
+        }
+    }
+
+    /// Why a connection to Runtime failed.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum ConnectError {
+        Failed,
+        Security,
+        Refused,
+        TimedOut,
+        Unreachable,
+        BadAddress,
+    }
+
+    impl ConnectError {
+        fn from_io(error: &std::io::Error) -> ConnectError {
+            match error.kind() {
+                ErrorKind::ConnectionRefused => ConnectError::Refused,
+                ErrorKind::TimedOut | ErrorKind::WouldBlock => ConnectError::TimedOut,
+                ErrorKind::HostUnreachable | ErrorKind::NetworkUnreachable => ConnectError::Unreachable,
+                _ => ConnectError::Failed,
+            }
+        }
+    }
+
+    impl fmt::Display for ConnectError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                ConnectError::Failed => write!(f, "Failed to connect to Robot."),
+                ConnectError::Security => write!(f, "Runtime security handshake failed. Check the [security] section in daybreak.ini."),
+                ConnectError::Refused => write!(f, "Connection refused. Is Runtime listening on that port?"),
+                ConnectError::TimedOut => write!(f, "Timed out connecting to Runtime. Try a longer --timeout."),
+                ConnectError::Unreachable => write!(f, "The robot is unreachable. Check the network and IP address."),
+                ConnectError::BadAddress => write!(f, "Invalid IP address or port."),
+            }
+        }
+    }
+
+    /// Fails with `NotConnected` while the link is down, and with `InvalidInput` for a message too big to send.
+    fn send_to_runtime(
+        stream: &mut Option<TcpStream>,
+        recorder: &mut Option<SessionRecorder>,
+        packet: io::Result<Vec<u8>>,
+    ) -> io::Result<()> {
+        let packet = packet?;
+        let runtime = stream.as_mut().ok_or(ErrorKind::NotConnected)?;
+        record_frames(recorder, Direction::Outbound, &packet);
+        // a failed write means the link is gone, so drop the stream and let the supervisor reconnect
+        if let Err(e) = runtime.write_all(&packet).and_then(|_| runtime.flush()) {
+            *stream = None;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    // a link that's down is reported through the link state, anything else deserves a line
+    fn report_send(what: &str, sent: io::Result<()>) {
+        match sent {
+            Err(e) if e.kind() != ErrorKind::NotConnected => println!("[Connection] Failed to send {} to Runtime: {}", what, e),
+            _ => {}
+        }
+    }
+
+    /// Which way a recorded frame travelled.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Direction {
+        /// Runtime to Dawn.
+        Inbound = 0,
+        /// Dawn to Runtime.
+        Outbound = 1,
+    }
+
+    const RECORDING_MAGIC: &[u8; 5] = b"DBRK\x01";
+    // [milliseconds since the recording started u32][direction u8] before each packet
+    const RECORD_PREFIX_SIZE: usize = 5;
+
+    /// One frame out of a session recording, still in its wire encoding.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct RecordedFrame {
+        pub at_ms: u32,
+        pub direction: Direction,
+        pub packet: Vec<u8>,
+    }
+
+    /// Writes every frame that crosses the Runtime link to a session file for `daybreak replay`.
+    pub struct SessionRecorder {
+        file: fs::File,
+        started: Instant,
+    }
+
+    impl SessionRecorder {
+        pub fn create(path: &str) -> std::io::Result<SessionRecorder> {
+            let mut file = fs::File::create(path)?;
+            file.write_all(RECORDING_MAGIC)?;
+            Ok(SessionRecorder {
+                file,
+                started: Instant::now(),
+            })
+        }
+
+        /// Records each complete frame in `packet`, which may hold several.
+        pub fn record(&mut self, direction: Direction, packet: &[u8]) -> std::io::Result<()> {
+            let at_ms = self.started.elapsed().as_millis().min(u32::MAX as u128) as u32;
+            let mut record = Vec::with_capacity(packet.len() + RECORD_PREFIX_SIZE);
+            let mut offset = 0;
+            while packet.len() - offset >= HEADER_SIZE {
+                let length = u16::from_le_bytes([packet[offset + 1], packet[offset + 2]]) as usize;
+                let end = (offset + HEADER_SIZE + length).min(packet.len());
+                record.extend_from_slice(&at_ms.to_le_bytes());
+                record.push(direction as u8);
+                record.extend_from_slice(&packet[offset..end]);
+                offset = end;
+            }
+            self.file.write_all(&record)
+        }
+    }
+
+    // a failing recorder shouldn't take the link down with it, so it just stops recording
+    fn record_frames(recorder: &mut Option<SessionRecorder>, direction: Direction, packet: &[u8]) {
+        if let Some(active) = recorder {
+            if let Err(e) = active.record(direction, packet) {
+                println!("[Recorder] Failed to write to the recording, stopping: {:?}", e);
+                *recorder = None;
+            }
+        }
+    }
+
+    /// Splits a recording back into frames. A frame cut off by a crash ends the recording early.
+    pub fn parse_recording(bytes: &[u8]) -> Option<Vec<RecordedFrame>> {
+        let mut rest = bytes.strip_prefix(RECORDING_MAGIC.as_slice())?;
+        let mut frames = vec![];
+        while rest.len() >= RECORD_PREFIX_SIZE + HEADER_SIZE {
+            let direction = match rest[4] {
+                0 => Direction::Inbound,
+                1 => Direction::Outbound,
+                _ => return None,
+            };
+            let packet_size = HEADER_SIZE + u16::from_le_bytes([rest[6], rest[7]]) as usize;
+            if rest.len() < RECORD_PREFIX_SIZE + packet_size {
+                break;
+            }
+            frames.push(RecordedFrame {
+                at_ms: u32::from_le_bytes(rest[..4].try_into().unwrap()),
+                direction,
+                packet: rest[RECORD_PREFIX_SIZE..RECORD_PREFIX_SIZE + packet_size].to_vec(),
+            });
+            rest = &rest[RECORD_PREFIX_SIZE + packet_size..];
+        }
+        Some(frames)
+    }
+
+    pub fn read_recording(path: &str) -> std::io::Result<Vec<RecordedFrame>> {
+        parse_recording(&fs::read(path)?).ok_or_else(|| {
+            std::io::Error::new(ErrorKind::InvalidData, "not a Daybreak session recording")
+        })
+    }
+
+    // plays a recording into a loopback link with its original timing, standing in for the Runtime
+    fn replay_link(frames: Vec<RecordedFrame>) -> std::io::Result<TcpStream> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let stream = TcpStream::connect(listener.local_addr()?)?;
+        let (mut feeder, _) = listener.accept()?;
+        stream.set_read_timeout(Some(Duration::from_millis(10)))?;
+        thread::spawn(move || {
+            let started = Instant::now();
+            for frame in frames {
+                // old pings would only confuse the latency stats
+                if frame.packet[0] == MsgType::TimeStamps as u8 {
+                    continue;
+                }
+                let due = Duration::from_millis(frame.at_ms as u64);
+                if let Some(wait) = due.checked_sub(started.elapsed()) {
+                    thread::sleep(wait);
+                }
+                if feeder.write_all(&frame.packet).is_err() {
+                    return;
+                }
+            }
+            println!("[Replay] Reached the end of the recording.");
+        });
+        Ok(stream)
+    }
+
+    fn report_link(logs: &mut LogBuffer, mode: Mode, link: LinkState) {
+        println!("[Connection] Link to Runtime is {:?}.", link);
+        logs.push(mode, &format!("[Connection] Link to Runtime is {:?}", link));
+    }
+
+    /// Session used when a command doesn't pick one with `--robot`.
+    pub const DEFAULT_SESSION: &str = "default";
+
+    // session names end up in file names, so keep them tame
+    pub fn is_valid_session_name(name: &str) -> bool {
+        !name.is_empty()
+            && name.len() <= 32
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    }
+
+    // the default session keeps the original file names
+    fn session_file(dir: &str, session: &str, prefix: &str, extension: &str) -> String {
+        if session == DEFAULT_SESSION {
+            format!("{}/{}.{}", dir, prefix, extension)
+        } else {
+            format!("{}/{}.{}.{}", dir, prefix, session, extension)
+        }
+    }
+
+    // creates dir if needed, then makes sure nobody else owns it or can get in, since whoever made it could swap our files
+    fn private_dir(dir: String) -> io::Result<String> {
+        let _ = fs::DirBuilder::new().recursive(true).mode(0o700).create(&dir);
+        let metadata = fs::symlink_metadata(&dir)?;
+        // SAFETY: getuid can't fail
+        let uid = unsafe { libc::getuid() };
+        if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o777 != 0o700 {
+            return Err(io::Error::new(
+                ErrorKind::PermissionDenied,
+                format!("{} isn't a directory only this user can use, so Daybreak won't use it", dir),
+            ));
+        }
+        Ok(dir)
+    }
+
+    /// This user's directory for Daybreak's sockets, `$XDG_RUNTIME_DIR/daybreak`, created if it isn't there yet.
+    /// Without `XDG_RUNTIME_DIR` it falls back to `daybreak-<uid>` in the temp dir.
+    /// Fails rather than use a directory this user doesn't own or that isn't mode 0700.
+    pub fn runtime_dir() -> io::Result<String> {
+        private_dir(match std::env::var("XDG_RUNTIME_DIR") {
+            Ok(base) if !base.is_empty() => format!("{}/daybreak", base),
+            // SAFETY: getuid can't fail
+            _ => format!("{}/daybreak-{}", std::env::temp_dir().display(), unsafe { libc::getuid() }),
+        })
+    }
+
+    /// Where recordings and persisted logs outlive the daemon, `$XDG_STATE_HOME/daybreak` or `~/.local/state/daybreak`.
+    /// Held to the same checks as `runtime_dir`, which it falls back to without a home.
+    pub fn state_dir() -> io::Result<String> {
+        match (std::env::var("XDG_STATE_HOME"), std::env::var("HOME")) {
+            (Ok(base), _) if !base.is_empty() => private_dir(format!("{}/daybreak", base)),
+            (_, Ok(home)) if !home.is_empty() => private_dir(format!("{}/.local/state/daybreak", home)),
+            _ => runtime_dir(),
+        }
+    }
+
+    /// `$XDG_CONFIG_HOME/daybreak`, or `~/.config/daybreak`.
+    pub fn config_dir() -> PathBuf {
+        let config = match (std::env::var_os("XDG_CONFIG_HOME"), std::env::var_os("HOME")) {
+            (Some(config), _) if !config.is_empty() => PathBuf::from(config),
+            (_, Some(home)) => PathBuf::from(home).join(".config"),
+            _ => std::env::temp_dir(),
+        };
+        config.join("daybreak")
+    }
+
+    /// daybreak.ini in the working directory when there is one, otherwise in `config_dir`.
+    /// The daemon resolves this once as it starts, before it leaves the directory it was started from.
+    pub fn config_path() -> PathBuf {
+        let local = std::env::current_dir().unwrap_or_default().join("daybreak.ini");
+        if local.exists() {
+            return local;
+        }
+        config_dir().join("daybreak.ini")
+    }
+
+    /// Socket clients use to reach the daemon.
+    pub fn daemon_socket_path() -> io::Result<String> {
+        Ok(format!("{}/daybreak.sock", runtime_dir()?))
+    }
+
+    /// Holds the running daemon's PID.
+    pub fn daemon_pid_path() -> io::Result<String> {
+        Ok(format!("{}/daybreak.pid", runtime_dir()?))
+    }
+
+    /// Where a daemon started with --detach writes its output.
+    pub fn daemon_log_path() -> io::Result<String> {
+        Ok(format!("{}/daybreak.log", runtime_dir()?))
+    }
+
+    /// Socket the daemon uses to talk to a session's robot thread.
+    pub fn robot_socket_path(session: &str) -> io::Result<String> {
+        Ok(session_file(&runtime_dir()?, session, "daybreak.robot", "sock"))
+    }
+
+    /// Default file a session's log is persisted to, in the state directory `state`.
+    pub fn run_log_path(state: &str, session: &str) -> String {
+        session_file(state, session, "robot.run", "txt")
+    }
+
+    /// Recording of the session's Runtime traffic in the state directory `state`, started over on every connect.
+    pub fn recording_path(state: &str, session: &str) -> String {
+        session_file(state, session, "robot.session", "rec")
+    }
+
+    // the robot socket is non-blocking for requests, but a big reply has to go out whole
+    fn reply_to_daemon(daemon_socket: &mut UnixStream, reply: &RobotReply) -> std::io::Result<()> {
+        daemon_socket.set_nonblocking(false)?;
+        send(daemon_socket, reply)?;
+        daemon_socket.set_nonblocking(true)
+    }
+
+    /// What the daemon asks of a session's robot thread, framed like the daemon's own messages.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub enum RobotRequest {
+        Run(RunCommand),
+        /// A protobuf `Input`.
+        Input(Vec<u8>),
+        SetStartPos(StartSide),
+        /// The log lines after `since`, for a subscriber catching up. New lines are pushed as events.
+        Logs { since: u64 },
+        /// Idles Runtime, closes the link and ends the robot thread, answered with `Disconnected` first.
+        Disconnect,
+    }
+
+    /// The robot thread's answer to a `RobotRequest` that has one.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub enum RobotReply {
+        /// A batch from `encode_log_lines`.
+        Logs(Vec<u8>),
+        Disconnected,
+    }
+
+    pub struct Robot {
+        pub session: String,
+        /// The daybreak.ini the daemon was started with.
+        pub config: PathBuf,
+        /// Where recordings and persisted logs go, `state_dir` unless it couldn't be used.
+        pub state: Option<String>,
+        /// Where the robot thread reports devices, logs and the link as they change.
+        pub events: Sender<Event>,
+    }
+
+    impl Robot {
+        pub fn query_message_type(&self, message: &[u8]) -> Option<MsgType> {
+            message_type_from_byte(message[0])
+        }
+
+        // leaves Runtime idle and closes the link
+        fn hang_up(&self, stream: &mut Option<TcpStream>, recorder: &mut Option<SessionRecorder>) {
+            report_send("the idle run mode", send_to_runtime(stream, recorder, self.run_mode_message(Mode::IDLE)));
+            if let Some(runtime) = stream.take() {
+                let _ = runtime.shutdown(Shutdown::Both);
+            }
+        }
+
+        /// Serves the daemon's events for this session. Without a `host` the link is a replay and is never reconnected.
+        /// `decoder` holds whatever Runtime sent while the link was being opened.
+        /// Returns once the daemon disconnects or goes away, leaving Runtime idle.
+        pub fn main_loop(&self, stream: TcpStream, decoder: FrameDecoder, host: Option<String>, options: ConnectOptions) -> Result<(), DaybreakError> {
+            let socket_path = robot_socket_path(&self.session)?;
+            let mut logs = LogBuffer::new(&self.session, LOG_HISTORY, persisted_log_path(&self.config, self.state.as_deref(), &self.session).as_deref());
+            let mut recorder = None;
+            if host.is_some() {
+                match &self.state {
+                    Some(state) => {
+                        let recording = recording_path(state, &self.session);
+                        match SessionRecorder::create(&recording) {
+                            Ok(new_recorder) => {
+                                println!("[Recorder] Recording Runtime traffic to {}", recording);
+                                recorder = Some(new_recorder);
                             }
+                            Err(e) => println!("[Recorder] Failed to create {}: {:?}", recording, e),
+                        }
+                    }
+                    None => println!("[Recorder] Not recording: there's no state directory"),
+                }
+            }
+            if fs::metadata(&socket_path).is_ok() {
+                let _ = fs::remove_file(&socket_path);
+            }
+            let listener = UnixListener::bind(&socket_path)?;
+
+            println!("[Connection] Listening on {}", socket_path);
+            println!("[Connection] Waiting for Daemon to connect...");
+            let (mut daemon_socket, _) = listener.accept()?;
+            // nobody else may attach to this thread, and the next robot thread needs the path
+            drop(listener);
+            let _ = fs::remove_file(&socket_path);
+            println!("[Connection] Accepted connection from Daemon.");
+            println!("[Connection] Started Main loop.");
+            daemon_socket.set_nonblocking(true)?;
+
+            let mut latency = LatencyTracker::new(LATENCY_HISTORY);
+            let mut last_ping = Instant::now() - PING_INTERVAL;
+            let mut start_pos: Option<Pos> = None;
+            let mut game_alert: Option<GameAlert> = None;
+            // what the supervisor replays after a reconnect
+            let mut run_mode = Mode::IDLE;
+
+            let mut stream = Some(stream);
+            let mut link = LinkState::Connected;
+            let mut backoff = Backoff::new(options.backoff, RECONNECT_MAX);
+            let mut next_attempt = Instant::now();
+            let mut dialing: Option<Receiver<Result<(TcpStream, FrameDecoder), ConnectError>>> = None;
+            let mut last_heard = Instant::now();
+
+            let mut decoder = decoder;
+            let mut read_buffer = [0u8; 4096];
+            let mut last_input: Option<Input> = None;
+            let mut requests = vec![];
+            // what's been reported to the daemon, so only changes go out
+            let mut reported_logs = logs.head();
+            let mut reported_link = None;
+            let mut reported_status = None;
+            // Runtime has no status frame, so the status is pieced together from what crosses the link
+            let mut battery = None;
+            let mut shepherd_heard = false;
+            loop {
+                let fresh = logs.since(reported_logs);
+                if let Some(last) = fresh.last() {
+                    reported_logs = last.seq;
+                    let _ = self.events.send(Event::Logs(encode_log_lines(logs.head(), &fresh)));
+                }
+                if reported_link != Some(link) {
+                    reported_link = Some(link);
+                    let _ = self.events.send(Event::Link(link));
+                }
+                let status = RuntimeStatus::observed(link, run_mode, battery, shepherd_heard);
+                if reported_status != Some(status) {
+                    reported_status = Some(status);
+                    let _ = self.events.send(Event::RuntimeStatus(status));
+                }
+                let received = daemon_socket.read(&mut read_buffer);
+                if let Ok(0) = received {
+                    println!("[Connection] Daemon closed the robot socket, stopping.");
+                    self.hang_up(&mut stream, &mut recorder);
+                    return Ok(());
+                }
+                if let Ok(read) = received {
+                    requests.extend_from_slice(&read_buffer[..read]);
+                }
+                // a daemon gone mid-reply is no reason to leave the robot running
+                let mut replied = Ok(());
+                while let Some(request) = take_message::<RobotRequest>(&mut requests) {
+                    let request = match request {
+                        Ok(request) => request,
+                        Err(e) => {
+                            println!("[Event] Couldn't read the daemon's request: {}", e);
+                            continue;
+                        }
+                    };
+                    let reply = match request {
+                        RobotRequest::Run(command) => {
+                            run_mode = match command {
+                                RunCommand::Teleop => Mode::TELEOP,
+                                RunCommand::Auto => Mode::AUTO,
+                                RunCommand::Stop => Mode::IDLE,
+                            };
+                            report_send("the run mode", send_to_runtime(&mut stream, &mut recorder, self.run_mode_message(run_mode)));
+                            println!("[RunMode] Now {:?}.", run_mode);
+                            continue;
+                        }
+                        RobotRequest::Input(input) => {
+                            let input = match Input::parse_from_bytes(&input) {
+                                Ok(input) => input,
+                                Err(_) => {
+                                    println!("[Robot @Inputs] Failed to parse input from Daemon.");
+                                    continue;
+                                }
+                            };
                             let input = UserInputs {
-                                inputs: vec![
-                                    input.unwrap()
-                                ],
+                                inputs: vec![input],
                                 special_fields: SpecialFields::new()
                             };
-                            let message = self.send_inputs(&input);
-                            stream.write(message.as_slice()).unwrap();
-                            stream.flush().unwrap(); 
+                            report_send("inputs", send_to_runtime(&mut stream, &mut recorder, self.send_inputs(&input)));
+                            continue;
+                        }
+                        RobotRequest::SetStartPos(side) => {
+                            let pos = Pos::from(side);
+                            start_pos = Some(pos);
+                            report_send("the start position", send_to_runtime(&mut stream, &mut recorder, self.send_start_pos(pos)));
+                            println!("[StartPos] Starting on the {:?} side.", pos);
+                            continue;
+                        }
+                        RobotRequest::Disconnect => {
+                            println!("[Connection] Disconnecting.");
+                            self.hang_up(&mut stream, &mut recorder);
+                            return Ok(reply_to_daemon(&mut daemon_socket, &RobotReply::Disconnected)?);
                         }
+                        RobotRequest::Logs { since } => {
+                            RobotReply::Logs(encode_log_lines(logs.head(), &logs.since(since)))
+                        }
+                    };
+                    replied = reply_to_daemon(&mut daemon_socket, &reply);
+                    if replied.is_err() {
+                        break;
                     }
                 }
-                let mut buffer: [u8; HEADER_SIZE] = [0; HEADER_SIZE];
-                let _dawn_read = stream.read(&mut buffer);
-                if _dawn_read.is_err() {
-                    println!("[Connection] Failed to read from stream.");
-                    continue;
+                if let Err(e) = replied {
+                    println!("[Connection] Lost the daemon mid-reply, stopping.");
+                    self.hang_up(&mut stream, &mut recorder);
+                    return Err(e.into());
                 }
-                // println!("[MessageHandler] Caught a message!");
-                let buffer = vec![leftover_bytes.to_vec(), buffer.to_vec()].concat(); 
-                let header = buffer.clone();
-
-                if HEADER_SIZE > buffer.len() {
-                    // SAVE THE HEADER SO WE KNOW TO READ THE REST OF THE MESSAGE
-                    leftover_bytes = buffer;
-                    println!("Pushed to leftover stack.");
-                    continue;
+                if stream.is_none() {
+                    if link == LinkState::Connected {
+                        // a replay has no Runtime to go back to
+                        link = if host.is_some() { LinkState::Reconnecting } else { LinkState::Lost };
+                        report_link(&mut logs, run_mode, link);
+                        // a new link has to show Shepherd and the battery again
+                        shepherd_heard = false;
+                        battery = None;
+                        next_attempt = Instant::now() + backoff.next_delay();
+                    }
+                    let due = dialing.is_none() && Instant::now() >= next_attempt;
+                    if let Some(host) = host.as_deref().filter(|_| due) {
+                        let once = ConnectOptions {
+                            retries: 0,
+                            timeout: options.timeout.min(RECONNECT_TIMEOUT),
+                            ..options
+                        };
+                        dialing = Some(self.dial(host, once));
+                    }
+                    let dialed = match dialing.as_ref().map(Receiver::try_recv) {
+                        Some(Ok(dialed)) => Some(dialed),
+                        Some(Err(TryRecvError::Disconnected)) => Some(Err(ConnectError::Failed)),
+                        Some(Err(TryRecvError::Empty)) | None => None,
+                    };
+                    if let Some(dialed) = dialed {
+                        dialing = None;
+                        match dialed {
+                            Ok((new_stream, new_decoder)) => {
+                                stream = Some(new_stream);
+                                decoder = new_decoder;
+                                last_heard = Instant::now();
+                                backoff.reset();
+                                link = LinkState::Connected;
+                                report_link(&mut logs, run_mode, link);
+                                // pick up where the old link left off
+                                if let Some(pos) = start_pos {
+                                    report_send("the start position", send_to_runtime(&mut stream, &mut recorder, self.send_start_pos(pos)));
+                                }
+                                if run_mode != Mode::IDLE {
+                                    report_send("the run mode", send_to_runtime(&mut stream, &mut recorder, self.run_mode_message(run_mode)));
+                                }
+                            }
+                            Err(_) => {
+                                // a lost link keeps being dialled at the capped backoff
+                                if link == LinkState::Reconnecting && backoff.attempts() >= RECONNECT_ATTEMPTS {
+                                    link = LinkState::Lost;
+                                    report_link(&mut logs, run_mode, link);
+                                }
+                                next_attempt = Instant::now() + backoff.next_delay();
+                            }
+                        }
+                    }
+                    if stream.is_none() {
+                        thread::sleep(Duration::from_millis(10));
+                        continue;
+                    }
                 }
-                let msg_type = self.query_message_type(&header);
-                if msg_type.is_none() {
-                    println!("[MessageHandler] Unknown message type: {:?}", header[0]);
-                    continue;
+
+                if last_ping.elapsed() >= PING_INTERVAL {
+                    last_ping = Instant::now();
+                    let ping = Frame::TimeStamps(TimeStamps {
+                        dawn_timestamp: now_millis(),
+                        runtime_timestamp: 0,
+                        special_fields: SpecialFields::default(),
+                    });
+                    report_send("a ping", send_to_runtime(&mut stream, &mut recorder, ping.encode()));
+                }
+
+                let runtime = match stream.as_mut() {
+                    Some(runtime) => runtime,
+                    None => continue,
+                };
+                match runtime.read(&mut read_buffer) {
+                    Ok(0) => {
+                        println!("[Connection] Runtime closed the stream.");
+                        stream = None;
+                        continue;
+                    }
+                    Ok(read) => {
+                        decoder.push(&read_buffer[..read]);
+                        last_heard = Instant::now();
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {}
+                    Err(_) => {
+                        println!("[Connection] Failed to read from stream.");
+                        stream = None;
+                        continue;
+                    }
                 }
-                let msg_length = (header[2] as usize) << 8 | header[1] as usize;
-                let mut buffer: Vec<u8> = vec![0; msg_length as usize];
-                let _read_buffer = stream.read_exact(&mut buffer);
-                if let Err(_) = _read_buffer {
-                    leftover_bytes = vec![header, buffer].concat();
-                    println!("Pushed to leftover stack.");
+                if last_heard.elapsed() > HEARTBEAT_TIMEOUT {
+                    println!("[Connection] Nothing from Runtime in {:?}, assuming the link is dead.", HEARTBEAT_TIMEOUT);
+                    stream = None;
                     continue;
                 }
-                // remove all 0 bytes trailing the buffer
-
-                let payload = buffer;
 
-                match msg_type.unwrap() {
-                    MsgType::RunMode => {
-                        let run_mode = RunMode::parse_from_bytes(&payload);
-                        if run_mode.is_err() {
-                            println!("[RunMode] Failed to parse RunMode: {:?}", run_mode.err());
+                while let Some(packet) = decoder.next_packet() {
+                    // as received, so a replay sees exactly what Runtime sent, frames we can't parse included
+                    record_frames(&mut recorder, Direction::Inbound, &packet);
+                    let frame = match parse_packet(&packet) {
+                        Ok(frame) => frame,
+                        Err(e) => {
+                            println!("[MessageHandler] {}", e);
                             continue;
                         }
-                        is_running = run_mode.unwrap().mode == EnumOrUnknown::new(Mode::IDLE);
-                    }
-                    MsgType::StartPos => {
-                        // println!("[StartPos] Unimplemented.");
-                    }
-                    MsgType::Log => {
-                        let log = Text::parse_from_bytes(&payload);
-                        if log.is_err() {
-                            continue;
+                    };
+                    // Runtime never sends run modes or inputs, but replays play back what Dawn sent
+                    match frame {
+                        Frame::RunMode(replayed) => {
+                            run_mode = replayed.mode.enum_value_or_default();
                         }
-                        let log = log.unwrap();
-                        // println!("[Log] {:?}", log.payload);
-                        if is_running {
-                            // wriute to a file
-                            // create the file if it doesn't exist
-                            if fs::metadata(format!("{}/robot.run.txt", temp_dir)).is_err()  {
-                                let _ = fs::File::create(format!("{}/robot.run.txt", temp_dir));
+                        Frame::StartPos(runtime_pos) => {
+                            let runtime_pos = runtime_pos.pos.enum_value_or_default();
+                            if start_pos.is_some_and(|pos| pos != runtime_pos) {
+                                println!("[StartPos] Runtime reports the {:?} side, but we chose {:?}.", runtime_pos, start_pos.unwrap());
                             }
-                            let file = fs::OpenOptions::new().append(true).open(format!("{}/robot.run.txt", temp_dir));
-                            if file.is_err() {
-                                println!("[Log] Failed to open file: {:?}", file.err());
-                                continue;
+                        }
+                        Frame::Log(log) => {
+                            // println!("[Log] {:?}", log.payload);
+                            for text in &log.payload {
+                                logs.push(run_mode, text);
                             }
-                            let mut file = file.unwrap();
-                            let _write = file.write_all(log.payload.concat().as_bytes());
-                            if _write.is_err() {
-                                println!("[Log] Failed to write to file: {:?}", _write.err());
-                                continue;
+                        }
+                        Frame::GameState(game_state) => {
+                            let state = game_state.state.enum_value_or_default();
+                            println!("[GameState] {:?}", state);
+                            let alert = GameAlert {
+                                seq: game_alert.map_or(1, |alert| alert.seq.wrapping_add(1)),
+                                state,
+                            };
+                            game_alert = Some(alert);
+                            // game states only come from Shepherd, by way of Runtime
+                            shepherd_heard = true;
+                            let _ = self.events.send(Event::GameAlert(alert));
+                            logs.push(run_mode, &format!("[GameState] {:?}", state));
+                        }
+                        Frame::DeviceData(sensors) => {
+                            battery = battery_volts(&sensors).or(battery);
+                            // the daemon tells subscribers only when they change, but still learns they're fresh
+                            let _ = self.events.send(Event::Devices(sensors.write_to_bytes().unwrap()));
+                        }
+                        Frame::Inputs(inputs) => {
+                            for input in inputs.inputs {
+                                if last_input.as_ref() == Some(&input) {
+                                    continue;
+                                }
+                                logs.push(run_mode, &format!("[Inputs] buttons {:#b} axes {:?}", input.buttons, input.axes));
+                                last_input = Some(input);
                             }
-                            let _flush = file.flush();
-                            if _flush.is_err() {
-                                println!("[Log] Failed to flush file: {:?}", _flush.err());
-                                continue;
+                        }
+                        Frame::TimeStamps(echo) => {
+                            // only echoes of our own pings carry a dawn timestamp
+                            if echo.dawn_timestamp != 0 {
+                                latency.record(&echo, now_millis());
+                                if let Some(stats) = latency.stats() {
+                                    let _ = self.events.send(Event::Latency(stats));
+                                }
                             }
                         }
-                    }
-                    MsgType::DeviceData => {
-                        let sensors = DevData::parse_from_bytes(&payload);
-                        if sensors.is_err() {
-                            println!("Failed to parse DevData: {:?}", sensors.err());
-                            continue;
+                        Frame::Security(_) => {
+                            println!("[Security] Ignoring security message after the handshake.");
                         }
-                        // println!("Received DevData: {:?}", sensors.as_ref().unwrap());
-                        recent_dev_data = Some(sensors.unwrap().write_to_bytes().unwrap());
-                    }
-                    MsgType::Inputs => {
-                        println!("[Inputs] Unsupported");
-                    }
-                    MsgType::TimeStamps => {
-                        continue;
                     }
                 }
-
             }
         }
 
-        pub fn send_inputs(&self, inputs: &UserInputs) -> Vec<u8> {
-            let message = inputs.write_to_bytes().unwrap();
+        pub fn send_inputs(&self, inputs: &UserInputs) -> io::Result<Vec<u8>> {
+            let message = inputs.write_to_bytes()?;
             let msg_type = MsgType::Inputs;
-            return self.compose_packet(message, msg_type)
+            self.compose_packet(message, msg_type)
         }
 
-        pub fn compose_packet(&self, message: Vec<u8>, msg_type: MsgType) -> Vec<u8> {
-            let message_length = message.len();
-            let mut packet = vec![0u8; 1 + 2 + message_length]; // Pre-allocate the packet buffer
-
-            // Set the message type at index 0
-            packet[0] = msg_type as u8;
-
-            // Set the message length in little-endian order at indices 1 and 2
-            let length_bytes = (message_length as u16).to_le_bytes();
-            packet[1..3].copy_from_slice(&length_bytes);
-
-            // Copy the message payload starting at index 3
-            packet[3..].copy_from_slice(&message);
+        pub fn send_start_pos(&self, pos: Pos) -> io::Result<Vec<u8>> {
+            Frame::StartPos(StartPos {
+                pos: EnumOrUnknown::new(pos),
+                special_fields: SpecialFields::default(),
+            })
+            .encode()
+        }
 
-            packet
+        pub fn compose_packet(&self, message: Vec<u8>, msg_type: MsgType) -> io::Result<Vec<u8>> {
+            encode_packet(msg_type, &message)
         }
 
-        pub fn send_run_mode(&self, run_mode_data: &RunMode) -> Vec<u8> {
-            let message = run_mode_data.write_to_bytes().unwrap();
+        pub fn send_run_mode(&self, run_mode_data: &RunMode) -> io::Result<Vec<u8>> {
+            let message = run_mode_data.write_to_bytes()?;
             let msg_type = MsgType::RunMode;
-            return self.compose_packet(message, msg_type)
+            self.compose_packet(message, msg_type)
         }
-        pub fn connect(self: Arc<Self>, default_host: &str) -> u8 {
-            let default_port = 8101;
-            println!("[Connection] Attempting to connect...");
-            let socket = SocketAddr::from_str(format!("{}:{}", default_host, default_port).as_str());
-            if socket.is_err() {
-                println!("[Connection] Failed to create connection socket.");
-                return 100;
-            }
-            let socket = socket.unwrap();
-            let mut stream = TcpStream::connect_timeout(&socket, Duration::from_secs(3));
-            for i in 1..4 {
-                stream = TcpStream::connect_timeout(&socket, Duration::from_secs(5));
-                if stream.is_err() {
-                    println!("[Connection] Failed to connect to stream. Retrying... {}/4", i);
-                    // wait 2 seconds before retrying
-                    std::thread::sleep(std::time::Duration::from_secs(1));
-                    continue;
+        pub fn run_mode_message(&self, mode: Mode) -> io::Result<Vec<u8>> {
+            self.send_run_mode(&RunMode {
+                mode: EnumOrUnknown::from(mode),
+                special_fields: SpecialFields::default(),
+            })
+        }
+
+        /// Opens a new link on a thread of its own, so the daemon keeps being served while Runtime is slow to answer.
+        fn dial(&self, host: &str, options: ConnectOptions) -> Receiver<Result<(TcpStream, FrameDecoder), ConnectError>> {
+            let (dialed, result) = mpsc::channel();
+            let (host, config) = (host.to_string(), self.config.clone());
+            thread::spawn(move || {
+                let _ = dialed.send(Robot::open_link(&host, &options, &config));
+            });
+            result
+        }
+
+        // opens the TCP link, identifies as Dawn and runs the security handshake if configured,
+        // handing back what Runtime sent past the handshake so the first frames aren't cut short
+        fn open_link(host: &str, options: &ConnectOptions, config: &Path) -> Result<(TcpStream, FrameDecoder), ConnectError> {
+            let ip = IpAddr::from_str(host.trim());
+            if ip.is_err() {
+                println!("[Connection] {:?} is not an IP address.", host);
+                return Err(ConnectError::BadAddress);
+            }
+            let socket = SocketAddr::new(ip.unwrap(), options.port);
+            let mut backoff = Backoff::new(options.backoff, RECONNECT_MAX);
+            let mut stream = TcpStream::connect_timeout(&socket, options.timeout);
+            while let Err(e) = &stream {
+                if backoff.attempts() >= options.retries {
+                    break;
                 }
-                break;
+                let delay = backoff.next_delay();
+                println!(
+                    "[Connection] Failed to connect to {}: {}. Retrying in {:?}... {}/{}",
+                    socket, e, delay, backoff.attempts(), options.retries
+                );
+                thread::sleep(delay);
+                stream = TcpStream::connect_timeout(&socket, options.timeout);
             }
-            if stream.is_err() {
-                println!("[Connection] Failed to connect to stream.");
-                return 100;
+            if let Err(e) = &stream {
+                println!("[Connection] Failed to connect to {}: {}", socket, e);
+                return Err(ConnectError::from_io(e));
             }
             let mut stream = stream.unwrap();
             // write a 1 bit Uint8Array to the stream to welcome myself.
@@ -338,15 +1188,570 @@ pub mod robotmanager {
             let _dawn_connect = stream.flush();
             if _dawn_connect.is_err() {
                 println!("[Identification] Error when welcoming myself. {:?}", _dawn_connect);
-                return 100;
+                return Err(ConnectError::Failed);
+            }
+
+            let mut decoder = FrameDecoder::new();
+            match SecurityConfig::load(config, host) {
+                Ok(Some(config)) => {
+                    println!("[Security] Authenticating with Runtime...");
+                    if let Err(e) = handshake(&mut stream, &mut decoder, &config) {
+                        println!("[Security] Handshake failed: {}", e);
+                        return Err(ConnectError::Security);
+                    }
+                    println!("[Security] Authenticated with Runtime.");
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    println!("[Security] {}", e);
+                    return Err(ConnectError::Security);
+                }
             }
+            // don't let a quiet runtime block daemon events
+            let _ = stream.set_read_timeout(Some(Duration::from_millis(10)));
+            Ok((stream, decoder))
+        }
+
+        /// Connects to Runtime and starts the robot thread, or says why it couldn't.
+        pub fn connect(self: Arc<Self>, default_host: &str, options: ConnectOptions) -> Result<(), ConnectError> {
+            println!("[Connection] Attempting to connect...");
+            let (stream, decoder) = Robot::open_link(default_host, &options, &self.config)?;
 
             println!("[Connection] Connected to Stream!");
             let robot = Arc::clone(&self);
-            thread::spawn(move || robot.main_loop(stream));
-            return 200;
+            let host = default_host.to_string();
+            thread::spawn(move || {
+                if let Err(e) = robot.main_loop(stream, decoder, Some(host), options) {
+                    println!("[Connection] Robot thread stopped: {}", e);
+                }
+            });
+            Ok(())
+        }
+
+        /// Plays a session recording back through the robot thread as if Runtime were sending it.
+        pub fn replay(self: Arc<Self>, path: &str) -> std::io::Result<()> {
+            let frames = read_recording(path)?;
+            println!("[Replay] Replaying {} frames from {}", frames.len(), path);
+            let stream = replay_link(frames)?;
+            let robot = Arc::clone(&self);
+            thread::spawn(move || {
+                if let Err(e) = robot.main_loop(stream, FrameDecoder::new(), None, ConnectOptions::default()) {
+                    println!("[Replay] Robot thread stopped: {}", e);
+                }
+            });
+            Ok(())
         }
     }
 
 
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use device::{param::Val, Device, Param};
+        use input::Source;
+        use proptest::prelude::*;
+        use crate::ipc::ipc::receive;
+        use std::sync::mpsc;
+        use crate::run_log::run_log::decode_log_lines;
+
+        fn arb_mode() -> impl Strategy<Value = Mode> {
+            prop_oneof![Just(Mode::IDLE), Just(Mode::AUTO), Just(Mode::TELEOP)]
+        }
+
+        fn arb_param() -> impl Strategy<Value = Param> {
+            let val = prop_oneof![
+                any::<f32>().prop_filter("NaN never compares equal", |f| !f.is_nan()).prop_map(Val::Fval),
+                any::<i32>().prop_map(Val::Ival),
+                any::<bool>().prop_map(Val::Bval),
+            ];
+            ("[a-z_]{1,12}", val, any::<bool>()).prop_map(|(name, val, readonly)| Param {
+                name,
+                val: Some(val),
+                readonly,
+                special_fields: SpecialFields::default(),
+            })
+        }
+
+        fn arb_device() -> impl Strategy<Value = Device> {
+            ("[A-Za-z]{1,12}", any::<u64>(), any::<u32>(), prop::collection::vec(arb_param(), 0..6)).prop_map(
+                |(name, uid, type_, params)| Device {
+                    name,
+                    uid,
+                    type_,
+                    params,
+                    special_fields: SpecialFields::default(),
+                },
+            )
+        }
+
+        fn arb_state() -> impl Strategy<Value = State> {
+            prop_oneof![
+                Just(State::POISON_IVY),
+                Just(State::DEHYDRATION),
+                Just(State::HYPOTHERMIA_START),
+                Just(State::HYPOTHERMIA_END)
+            ]
+        }
+
+        fn arb_frame() -> impl Strategy<Value = Frame> {
+            prop_oneof![
+                arb_mode().prop_map(|mode| Frame::RunMode(RunMode {
+                    mode: EnumOrUnknown::new(mode),
+                    special_fields: SpecialFields::default(),
+                })),
+                prop_oneof![Just(Pos::LEFT), Just(Pos::RIGHT)].prop_map(|pos| Frame::StartPos(StartPos {
+                    pos: EnumOrUnknown::new(pos),
+                    special_fields: SpecialFields::default(),
+                })),
+                prop::collection::vec(".{0,40}", 0..5).prop_map(|payload| Frame::Log(Text {
+                    payload,
+                    special_fields: SpecialFields::default(),
+                })),
+                prop::collection::vec(arb_device(), 0..8).prop_map(|devices| Frame::DeviceData(DevData {
+                    devices,
+                    special_fields: SpecialFields::default(),
+                })),
+                arb_state().prop_map(|state| Frame::GameState(GameState {
+                    state: EnumOrUnknown::new(state),
+                    special_fields: SpecialFields::default(),
+                })),
+                (any::<bool>(), any::<u64>(), prop::collection::vec(-1.0f32..1.0, 4)).prop_map(
+                    |(connected, buttons, axes)| Frame::Inputs(UserInputs {
+                        inputs: vec![Input {
+                            connected,
+                            buttons,
+                            axes,
+                            source: EnumOrUnknown::new(Source::GAMEPAD),
+                            special_fields: SpecialFields::default(),
+                        }],
+                        special_fields: SpecialFields::default(),
+                    })
+                ),
+                (any::<u64>(), any::<u64>()).prop_map(|(dawn_timestamp, runtime_timestamp)| {
+                    Frame::TimeStamps(TimeStamps {
+                        dawn_timestamp,
+                        runtime_timestamp,
+                        special_fields: SpecialFields::default(),
+                    })
+                }),
+            ]
+        }
+
+        // turn a list of cut points into chunks of the stream
+        fn split_at_points(stream: &[u8], mut points: Vec<usize>) -> Vec<&[u8]> {
+            points.iter_mut().for_each(|point| *point %= stream.len() + 1);
+            points.sort_unstable();
+            let mut chunks = vec![];
+            let mut start = 0;
+            for point in points {
+                chunks.push(&stream[start..point]);
+                start = point;
+            }
+            chunks.push(&stream[start..]);
+            chunks
+        }
+
+        proptest! {
+            #[test]
+            fn decodes_arbitrarily_split_streams(
+                frames in prop::collection::vec(arb_frame(), 0..12),
+                points in prop::collection::vec(any::<usize>(), 0..32),
+            ) {
+                let stream: Vec<u8> = frames.iter().flat_map(|frame| frame.encode().unwrap()).collect();
+                let mut decoder = FrameDecoder::new();
+                let mut decoded = vec![];
+                for chunk in split_at_points(&stream, points) {
+                    decoder.push(chunk);
+                    while let Some(frame) = decoder.next_frame() {
+                        decoded.push(frame.unwrap());
+                    }
+                }
+                prop_assert_eq!(decoded, frames);
+                prop_assert_eq!(decoder.pending(), 0);
+            }
+
+            #[test]
+            fn game_alert_round_trips(seq in any::<u32>(), state in arb_state()) {
+                let alert = GameAlert { seq, state };
+                let bytes = bincode::serialize(&alert).unwrap();
+                prop_assert_eq!(bincode::deserialize::<GameAlert>(&bytes).unwrap(), alert);
+            }
+
+            #[test]
+            fn byte_at_a_time_matches_single_push(frames in prop::collection::vec(arb_frame(), 1..6)) {
+                let stream: Vec<u8> = frames.iter().flat_map(|frame| frame.encode().unwrap()).collect();
+                let mut decoder = FrameDecoder::new();
+                let mut decoded = vec![];
+                for byte in &stream {
+                    decoder.push(&[*byte]);
+                    if let Some(frame) = decoder.next_frame() {
+                        decoded.push(frame.unwrap());
+                    }
+                }
+                prop_assert_eq!(decoded, frames);
+            }
+        }
+
+        #[test]
+        fn skips_unknown_message_types() {
+            let log = Frame::Log(Text {
+                payload: vec!["hello".to_string()],
+                special_fields: SpecialFields::default(),
+            });
+            let mut stream = vec![42, 3, 0, 1, 2, 3];
+            stream.extend(log.encode().unwrap());
+
+            let mut decoder = FrameDecoder::new();
+            decoder.push(&stream);
+            assert!(matches!(decoder.next_frame(), Some(Err(FrameError::UnknownType(42)))));
+            assert_eq!(decoder.next_frame().unwrap().unwrap(), log);
+            assert!(decoder.next_frame().is_none());
+
+            // Runtime's challenge id is reserved, and field events come in as 7
+            decoder.push(&[4, 0, 0]);
+            assert!(matches!(decoder.next_frame(), Some(Err(FrameError::UnknownType(4)))));
+            let game_state = Frame::GameState(GameState {
+                state: EnumOrUnknown::new(State::POISON_IVY),
+                special_fields: SpecialFields::default(),
+            });
+            assert_eq!(game_state.encode().unwrap()[0], 7);
+        }
+
+        #[test]
+        fn malformed_payload_keeps_stream_in_sync() {
+            let run_mode = Frame::RunMode(RunMode {
+                mode: EnumOrUnknown::new(Mode::AUTO),
+                special_fields: SpecialFields::default(),
+            });
+            let mut stream = encode_packet(MsgType::DeviceData, &[0xff, 0xff, 0xff]).unwrap();
+            stream.extend(run_mode.encode().unwrap());
+
+            let mut decoder = FrameDecoder::new();
+            decoder.push(&stream);
+            assert!(matches!(
+                decoder.next_frame(),
+                Some(Err(FrameError::Malformed(MsgType::DeviceData, _)))
+            ));
+            assert_eq!(decoder.next_frame().unwrap().unwrap(), run_mode);
+
+            // recordings take packets as sent, even the ones that don't parse
+            decoder.push(&stream);
+            assert_eq!(decoder.next_packet().unwrap(), &stream[..HEADER_SIZE + 3]);
+            assert_eq!(decoder.next_packet().unwrap(), &stream[HEADER_SIZE + 3..]);
+            assert!(decoder.next_packet().is_none());
+        }
+
+        #[test]
+        fn reconnect_backoff_doubles_up_to_the_cap() {
+            let mut backoff = Backoff::new(Duration::from_millis(500), Duration::from_secs(4));
+            let delays: Vec<u64> = (0..6).map(|_| backoff.next_delay().as_millis() as u64).collect();
+            assert_eq!(delays, vec![500, 1_000, 2_000, 4_000, 4_000, 4_000]);
+            assert_eq!(backoff.attempts(), 6);
+            backoff.reset();
+            assert_eq!(backoff.next_delay(), Duration::from_millis(500));
+        }
+
+        #[test]
+        fn runtime_files_stay_in_private_directories() {
+            use std::os::unix::fs::{symlink, PermissionsExt};
+            let root = format!("{}/daybreak-private-{}", std::env::temp_dir().display(), std::process::id());
+            let _ = fs::remove_dir_all(&root);
+            let made = private_dir(format!("{}/made", root)).unwrap();
+            assert_eq!(fs::metadata(&made).unwrap().mode() & 0o777, 0o700);
+
+            // anyone could have made these, so they're refused rather than used
+            let shared = format!("{}/shared", root);
+            fs::create_dir(&shared).unwrap();
+            fs::set_permissions(&shared, fs::Permissions::from_mode(0o777)).unwrap();
+            assert_eq!(private_dir(shared).unwrap_err().kind(), ErrorKind::PermissionDenied);
+            let link = format!("{}/link", root);
+            symlink(&made, &link).unwrap();
+            assert_eq!(private_dir(link).unwrap_err().kind(), ErrorKind::PermissionDenied);
+            let _ = fs::remove_dir_all(&root);
+        }
+
+        #[test]
+        fn sessions_get_their_own_socket_and_log() {
+            assert!(robot_socket_path(DEFAULT_SESSION).unwrap().ends_with("/daybreak.robot.sock"));
+            assert_eq!(run_log_path("/state", DEFAULT_SESSION), "/state/robot.run.txt");
+            assert!(robot_socket_path("red").unwrap().ends_with("/daybreak.robot.red.sock"));
+            assert_eq!(run_log_path("/state", "red"), "/state/robot.run.red.txt");
+            assert!(is_valid_session_name("blue-2"));
+            assert!(!is_valid_session_name(""));
+            assert!(!is_valid_session_name("../red"));
+            assert!(!is_valid_session_name(&"a".repeat(33)));
+        }
+
+        fn many_devices(count: u64) -> DevData {
+            DevData {
+                devices: (0..count)
+                    .map(|uid| Device {
+                        name: format!("KoalaBear{}", uid),
+                        uid,
+                        type_: 6,
+                        params: ["velocity_a", "velocity_b", "enc_a", "enc_b", "pid_enabled_a"]
+                            .iter()
+                            .map(|name| Param {
+                                name: name.to_string(),
+                                val: Some(Val::Fval(uid as f32 / 3.0)),
+                                readonly: false,
+                                special_fields: SpecialFields::default(),
+                            })
+                            .collect(),
+                        special_fields: SpecialFields::default(),
+                    })
+                    .collect(),
+                special_fields: SpecialFields::default(),
+            }
+        }
+
+        #[test]
+        fn a_full_log_batch_fits_through_the_robot_socket() {
+            let mut logs = LogBuffer::new("batch", LOG_HISTORY, None);
+            for _ in 0..LOG_HISTORY {
+                logs.push(Mode::TELEOP, &"x".repeat(1000));
+            }
+            let batch = encode_log_lines(logs.head(), &logs.since(0));
+            assert!(batch.len() > 256 * 1024);
+
+            let (mut robot_side, mut daemon_side) = UnixStream::pair().unwrap();
+            // the robot socket is non-blocking, like the robot thread's end
+            robot_side.set_nonblocking(true).unwrap();
+            let reader = thread::spawn(move || {
+                let received = receive::<RobotReply>(&mut daemon_side).unwrap();
+                assert_eq!(receive::<RobotReply>(&mut daemon_side).unwrap(), RobotReply::Disconnected);
+                received
+            });
+            reply_to_daemon(&mut robot_side, &RobotReply::Logs(batch.clone())).unwrap();
+            reply_to_daemon(&mut robot_side, &RobotReply::Disconnected).unwrap();
+            assert_eq!(reader.join().unwrap(), RobotReply::Logs(batch));
+
+            // with the daemon gone, the robot thread hears about it instead of panicking
+            assert!(reply_to_daemon(&mut robot_side, &RobotReply::Disconnected).is_err());
+        }
+
+        #[test]
+        fn devices_for_the_robot_thread_go_out_whole() {
+            // hundreds of devices still fit a Runtime frame, and the robot hands them on unchanged
+            let devices = many_devices(300);
+            let packet = Frame::DeviceData(devices.clone()).encode().unwrap();
+            assert!(packet.len() > 16 * 1024);
+            let mut decoder = FrameDecoder::new();
+            decoder.push(&packet);
+            assert_eq!(decoder.next_frame().unwrap().unwrap(), Frame::DeviceData(devices));
+
+            // but one too big for the u16 length is refused rather than sent with a wrapped length
+            assert_eq!(
+                Frame::DeviceData(many_devices(800)).encode().unwrap_err().kind(),
+                ErrorKind::InvalidInput
+            );
+        }
+
+        #[test]
+        fn oversize_log_bursts_are_split_across_frames() {
+            let mut lines: Vec<String> = (0..4000).map(|i| format!("line {} of a very chatty robot\n", i)).collect();
+            lines.push("é".repeat(40_000));
+            let log = Text {
+                payload: lines.clone(),
+                special_fields: SpecialFields::default(),
+            };
+            let mut decoder = FrameDecoder::new();
+            decoder.push(&Frame::Log(log).encode().unwrap());
+            let mut received = String::new();
+            let mut frames = 0;
+            while let Some(frame) = decoder.next_frame() {
+                match frame.unwrap() {
+                    Frame::Log(chunk) => received.push_str(&chunk.payload.concat()),
+                    other => panic!("unexpected {:?}", other),
+                }
+                frames += 1;
+            }
+            assert!(frames > 1);
+            assert_eq!(received, lines.concat());
+        }
+
+        #[test]
+        fn connect_options_from_text() {
+            let mut options = ConnectOptions::default();
+            assert_eq!(options.port, 8101);
+            options.set("port", "18101").unwrap();
+            options.set("timeout", "250").unwrap();
+            options.set("retries", "0").unwrap();
+            options.set("backoff", " 20 ").unwrap();
+            assert_eq!(options.timeout, Duration::from_millis(250));
+            assert_eq!(options.backoff, Duration::from_millis(20));
+            assert!(options.set("port", "0").is_err());
+            assert!(options.set("retries", "-1").is_err());
+            assert!(options.set("speed", "1").is_err());
+        }
+
+        #[test]
+        fn connect_errors_say_what_went_wrong() {
+            // nothing listens on a port we just gave back
+            let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+            let options = ConnectOptions {
+                port,
+                timeout: Duration::from_millis(500),
+                retries: 2,
+                backoff: Duration::from_millis(5),
+            };
+            let config = std::env::temp_dir().join(format!("daybreak-no-config-{}.ini", std::process::id()));
+            assert_eq!(Robot::open_link("127.0.0.1", &options, &config).err(), Some(ConnectError::Refused));
+            assert_eq!(Robot::open_link("robot.local", &options, &config).err(), Some(ConnectError::BadAddress));
+        }
+
+        #[test]
+        fn latency_stats_over_rolling_history() {
+            let mut tracker = LatencyTracker::new(20);
+            assert!(tracker.stats().is_none());
+            // 25 pings, the first 5 fall out of the history
+            for rtt in 1..=25u64 {
+                let echo = TimeStamps {
+                    dawn_timestamp: 1_000,
+                    runtime_timestamp: 1_000 + rtt / 2 + 40,
+                    special_fields: SpecialFields::default(),
+                };
+                tracker.record(&echo, 1_000 + rtt);
+            }
+            let stats = tracker.stats().unwrap();
+            assert_eq!(stats.samples, 20);
+            assert_eq!(stats.min_ms, 6);
+            assert_eq!(stats.avg_ms, 15.5);
+            assert_eq!(stats.p95_ms, 24);
+            assert_eq!(stats.skew_ms, 40);
+            assert!(!stats.is_degraded());
+        }
+
+        #[test]
+        fn recordings_keep_frames_and_directions() {
+            let log = Frame::Log(Text {
+                payload: vec!["hello".to_string()],
+                special_fields: SpecialFields::default(),
+            });
+            let run_mode = Frame::RunMode(RunMode {
+                mode: EnumOrUnknown::new(Mode::TELEOP),
+                special_fields: SpecialFields::default(),
+            });
+            let path = format!("{}/daybreak-recording-{}.rec", std::env::temp_dir().display(), std::process::id());
+            let mut recorder = SessionRecorder::create(&path).unwrap();
+            recorder.record(Direction::Outbound, &run_mode.encode().unwrap()).unwrap();
+            // one read can carry several frames
+            recorder.record(Direction::Inbound, &[log.encode().unwrap(), log.encode().unwrap()].concat()).unwrap();
+            drop(recorder);
+
+            let mut bytes = fs::read(&path).unwrap();
+            let _ = fs::remove_file(&path);
+            let recorded = parse_recording(&bytes).unwrap();
+            let directions: Vec<Direction> = recorded.iter().map(|frame| frame.direction).collect();
+            assert_eq!(directions, vec![Direction::Outbound, Direction::Inbound, Direction::Inbound]);
+            assert_eq!(recorded[0].packet, run_mode.encode().unwrap());
+            assert_eq!(recorded[2].packet, log.encode().unwrap());
+
+            // a recording cut off mid-frame keeps everything before the cut
+            bytes.truncate(bytes.len() - 2);
+            assert_eq!(parse_recording(&bytes).unwrap().len(), 2);
+            assert!(parse_recording(b"not a recording").is_none());
+        }
+
+        #[test]
+        fn replay_plays_through_the_robot_thread() {
+            let session = format!("replay-{}", std::process::id());
+            let path = format!("{}/daybreak-replay-{}.rec", std::env::temp_dir().display(), std::process::id());
+            // a config and state dir of the test's own, so nothing on this machine is read or written
+            let config = std::env::temp_dir().join(format!("daybreak-no-config-{}.ini", std::process::id()));
+            let state = private_dir(format!("{}/daybreak-replay-state-{}", std::env::temp_dir().display(), std::process::id())).unwrap();
+            let devices = DevData {
+                devices: vec![Device {
+                    name: "KoalaBear".to_string(),
+                    uid: 7,
+                    type_: 6,
+                    params: vec![],
+                    special_fields: SpecialFields::default(),
+                }],
+                special_fields: SpecialFields::default(),
+            };
+            let input = UserInputs {
+                inputs: vec![Input {
+                    connected: true,
+                    buttons: 0b11,
+                    axes: vec![1.0],
+                    source: EnumOrUnknown::new(Source::KEYBOARD),
+                    special_fields: SpecialFields::default(),
+                }],
+                special_fields: SpecialFields::default(),
+            };
+            let mut recorder = SessionRecorder::create(&path).unwrap();
+            recorder.record(Direction::Inbound, &Frame::DeviceData(devices.clone()).encode().unwrap()).unwrap();
+            recorder.record(Direction::Outbound, &Robot { session: session.clone(), config: config.clone(), state: Some(state.clone()), events: mpsc::channel().0 }.run_mode_message(Mode::TELEOP).unwrap()).unwrap();
+            recorder.record(Direction::Outbound, &Frame::Inputs(input).encode().unwrap()).unwrap();
+            let log = Frame::Log(Text {
+                payload: vec!["replayed line\n".to_string()],
+                special_fields: SpecialFields::default(),
+            });
+            recorder.record(Direction::Inbound, &log.encode().unwrap()).unwrap();
+            drop(recorder);
+
+            let (events, reports) = mpsc::channel();
+            let robot = Arc::new(Robot { session: session.clone(), config, state: Some(state.clone()), events });
+            robot.replay(&path).unwrap();
+            let mut daemon = None;
+            for _ in 0..100 {
+                daemon = UnixStream::connect(robot_socket_path(&session).unwrap()).ok();
+                if daemon.is_some() {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(20));
+            }
+            let mut daemon = daemon.expect("robot socket never came up");
+
+            let ask = |daemon: &mut UnixStream, request: RobotRequest| -> RobotReply {
+                send(daemon, &request).unwrap();
+                receive(daemon).unwrap()
+            };
+            // once the recording runs out the replay's link is lost for good
+            let mut replayed = None;
+            loop {
+                match reports.recv_timeout(Duration::from_secs(5)).expect("replay never finished") {
+                    Event::Devices(devices) => replayed = Some(devices),
+                    Event::Link(LinkState::Lost) => break,
+                    _ => {}
+                }
+            }
+            assert_eq!(DevData::parse_from_bytes(&replayed.unwrap()).unwrap(), devices);
+            let RobotReply::Logs(batch) = ask(&mut daemon, RobotRequest::Logs { since: 0 }) else {
+                panic!("no logs came back");
+            };
+            let (head, lines) = decode_log_lines(&batch).unwrap();
+            assert_eq!(head, lines.len() as u64);
+            let input_line = lines
+                .iter()
+                .find(|line| line.text == "[Inputs] buttons 0b11 axes [1.0]")
+                .unwrap();
+            assert_eq!(input_line.mode, Mode::TELEOP);
+            assert_eq!(input_line.session, session);
+            assert!(lines.iter().any(|line| line.text == "replayed line"));
+            assert!(!fs::exists(recording_path(&state, &session)).unwrap());
+
+            drop(daemon);
+            let _ = fs::remove_file(&path);
+            let _ = fs::remove_dir_all(&state);
+        }
+
+        #[test]
+        fn waits_for_split_header() {
+            let run_mode = Frame::RunMode(RunMode {
+                mode: EnumOrUnknown::new(Mode::TELEOP),
+                special_fields: SpecialFields::default(),
+            });
+            let packet = run_mode.encode().unwrap();
+            let mut decoder = FrameDecoder::new();
+            decoder.push(&packet[..1]);
+            assert!(decoder.next_frame().is_none());
+            decoder.push(&packet[1..2]);
+            assert!(decoder.next_frame().is_none());
+            decoder.push(&packet[2..]);
+            assert_eq!(decoder.next_frame().unwrap().unwrap(), run_mode);
+        }
+    }
 }
@@ -0,0 +1,242 @@
+#[allow(clippy::module_inception)]
+pub mod run_log {
+    use std::{
+        collections::VecDeque,
+        fs,
+        io::Write,
+        path::Path,
+    };
+
+    use chrono::{Local, TimeZone};
+    use ini::Ini;
+
+    use crate::robot::robotmanager::{now_millis, run_log_path, run_mode::Mode};
+
+    /// Lines each robot thread keeps for subscribers that connect late.
+    pub const LOG_HISTORY: usize = 2000;
+    // most lines a single subscriber batch carries, so one reply stays small
+    const MAX_BATCH: usize = 500;
+
+    /// One line of Runtime output, tagged when the robot thread received it.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct LogLine {
+        /// Counts up from 1 for the life of the robot thread.
+        pub seq: u64,
+        pub received_ms: u64,
+        pub mode: Mode,
+        pub session: String,
+        pub text: String,
+    }
+
+    impl LogLine {
+        /// `[12:03:07.250 TELEOP] text`, in local time.
+        pub fn display(&self) -> String {
+            let time = Local
+                .timestamp_millis_opt(self.received_ms as i64)
+                .single()
+                .map(|time| time.format("%H:%M:%S%.3f").to_string())
+                .unwrap_or_default();
+            format!("[{} {:?}] {}", time, self.mode, self.text)
+        }
+
+        // [seq u64][received u64][mode u8][session length u8][session][text length u32][text]
+        fn write_to(&self, bytes: &mut Vec<u8>) {
+            bytes.extend_from_slice(&self.seq.to_le_bytes());
+            bytes.extend_from_slice(&self.received_ms.to_le_bytes());
+            bytes.push(self.mode as u8);
+            bytes.push(self.session.len() as u8);
+            bytes.extend_from_slice(self.session.as_bytes());
+            bytes.extend_from_slice(&(self.text.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(self.text.as_bytes());
+        }
+
+        fn read_from(bytes: &[u8]) -> Option<(LogLine, usize)> {
+            let u64_at = |i: usize| Some(u64::from_le_bytes(bytes.get(i..i + 8)?.try_into().ok()?));
+            let seq = u64_at(0)?;
+            let received_ms = u64_at(8)?;
+            let mode = match *bytes.get(16)? {
+                0 => Mode::IDLE,
+                1 => Mode::AUTO,
+                2 => Mode::TELEOP,
+                _ => return None,
+            };
+            let session_end = 18 + *bytes.get(17)? as usize;
+            let session = String::from_utf8(bytes.get(18..session_end)?.to_vec()).ok()?;
+            let text_length = u32::from_le_bytes(bytes.get(session_end..session_end + 4)?.try_into().ok()?) as usize;
+            let text_end = session_end + 4 + text_length;
+            let text = String::from_utf8(bytes.get(session_end + 4..text_end)?.to_vec()).ok()?;
+            Some((
+                LogLine {
+                    seq,
+                    received_ms,
+                    mode,
+                    session,
+                    text,
+                },
+                text_end,
+            ))
+        }
+    }
+
+    /// A batch of lines for a subscriber, led by the newest sequence number the robot thread has handed out.
+    pub fn encode_log_lines(head: u64, lines: &[LogLine]) -> Vec<u8> {
+        let mut bytes = head.to_le_bytes().to_vec();
+        for line in lines {
+            line.write_to(&mut bytes);
+        }
+        bytes
+    }
+
+    pub fn decode_log_lines(bytes: &[u8]) -> Option<(u64, Vec<LogLine>)> {
+        let head = u64::from_le_bytes(bytes.get(..8)?.try_into().ok()?);
+        let mut rest = &bytes[8..];
+        let mut lines = vec![];
+        while !rest.is_empty() {
+            let (line, used) = LogLine::read_from(rest)?;
+            lines.push(line);
+            rest = &rest[used..];
+        }
+        Some((head, lines))
+    }
+
+    /// Ring buffer of a session's log lines, optionally mirrored to disk.
+    pub struct LogBuffer {
+        session: String,
+        lines: VecDeque<LogLine>,
+        capacity: usize,
+        head: u64,
+        persist: Option<fs::File>,
+    }
+
+    impl LogBuffer {
+        pub fn new(session: &str, capacity: usize, persist: Option<&str>) -> LogBuffer {
+            let persist = persist.and_then(|path| {
+                match fs::OpenOptions::new().create(true).append(true).open(path) {
+                    Ok(file) => {
+                        println!("[Log] Keeping session log in {}", path);
+                        Some(file)
+                    }
+                    Err(e) => {
+                        println!("[Log] Failed to open {}: {:?}", path, e);
+                        None
+                    }
+                }
+            });
+            LogBuffer {
+                session: session.to_string(),
+                lines: VecDeque::with_capacity(capacity),
+                capacity,
+                head: 0,
+                persist,
+            }
+        }
+
+        /// Adds every line in `text`. Runtime sends whole prints, which may hold several lines or none.
+        pub fn push(&mut self, mode: Mode, text: &str) {
+            let received_ms = now_millis();
+            for text in text.lines() {
+                self.head += 1;
+                let line = LogLine {
+                    seq: self.head,
+                    received_ms,
+                    mode,
+                    session: self.session.clone(),
+                    text: text.to_string(),
+                };
+                if let Some(file) = self.persist.as_mut() {
+                    if let Err(e) = writeln!(file, "{}", line.display()) {
+                        println!("[Log] Failed to write to file, no longer persisting: {:?}", e);
+                        self.persist = None;
+                    }
+                }
+                if self.lines.len() == self.capacity {
+                    self.lines.pop_front();
+                }
+                self.lines.push_back(line);
+            }
+        }
+
+        pub fn head(&self) -> u64 {
+            self.head
+        }
+
+        /// Lines newer than `seq`, oldest first, at most one batch's worth.
+        pub fn since(&self, seq: u64) -> Vec<LogLine> {
+            self.lines
+                .iter()
+                .filter(|line| line.seq > seq)
+                .take(MAX_BATCH)
+                .cloned()
+                .collect()
+        }
+    }
+
+    /// Where a session's log is kept on disk, if `persist = true` in its `[logs NAME]` or the `[logs]` section.
+    /// Without a `path` it goes in the state directory `state`.
+    pub fn persisted_log_path(config: &Path, state: Option<&str>, session: &str) -> Option<String> {
+        let ini = Ini::load_from_file(config).ok()?;
+        let section = ini
+            .section(Some(format!("logs {}", session)))
+            .or_else(|| ini.section(Some("logs")))?;
+        if section.get("persist").map(str::trim) != Some("true") {
+            return None;
+        }
+        match section.get("path") {
+            Some(path) => Some(path.to_string()),
+            None => match state {
+                Some(state) => Some(run_log_path(state, session)),
+                None => {
+                    println!("[Logs] Keeping the log in memory only: there's no state directory");
+                    None
+                }
+            },
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn buffer_keeps_the_newest_lines() {
+            let mut logs = LogBuffer::new("red", 3, None);
+            logs.push(Mode::IDLE, "booting\n");
+            logs.push(Mode::TELEOP, "one\ntwo\n");
+            logs.push(Mode::TELEOP, "");
+            logs.push(Mode::TELEOP, "three");
+            assert_eq!(logs.head(), 4);
+
+            let lines = logs.since(0);
+            let texts: Vec<&str> = lines.iter().map(|line| line.text.as_str()).collect();
+            assert_eq!(texts, vec!["one", "two", "three"]);
+            assert_eq!(lines[0].seq, 2);
+            assert_eq!(lines[0].mode, Mode::TELEOP);
+            assert_eq!(lines[0].session, "red");
+            assert_eq!(logs.since(3).len(), 1);
+            assert!(logs.since(4).is_empty());
+        }
+
+        #[test]
+        fn batches_round_trip() {
+            let mut logs = LogBuffer::new("default", 10, None);
+            logs.push(Mode::AUTO, "héllo\nworld\n");
+            let lines = logs.since(0);
+            let bytes = encode_log_lines(logs.head(), &lines);
+            assert_eq!(decode_log_lines(&bytes), Some((2, lines)));
+            assert_eq!(decode_log_lines(&bytes[..bytes.len() - 1]), None);
+            assert_eq!(decode_log_lines(&encode_log_lines(7, &[])), Some((7, vec![])));
+        }
+
+        #[test]
+        fn persisted_lines_are_tagged() {
+            let path = format!("{}/daybreak-log-{}.txt", std::env::temp_dir().display(), std::process::id());
+            let _ = fs::remove_file(&path);
+            let mut logs = LogBuffer::new("blue", 10, Some(&path));
+            logs.push(Mode::TELEOP, "driving\n");
+            drop(logs);
+            let file = fs::read_to_string(&path).unwrap();
+            let _ = fs::remove_file(&path);
+            assert!(file.ends_with(" TELEOP] driving\n"), "{:?}", file);
+        }
+    }
+}
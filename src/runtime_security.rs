@@ -0,0 +1,446 @@
+#[allow(clippy::module_inception)]
+pub mod runtime_security {
+    use std::{
+        fmt, fs,
+        io::{self, ErrorKind, Read, Write},
+        net::TcpStream,
+        path::Path,
+        time::{Duration, Instant},
+    };
+
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use ini::Ini;
+    use openssl::{
+        error::ErrorStack,
+        hash::MessageDigest,
+        pkey::{PKey, Private, Public},
+        rsa::{Padding, Rsa},
+        sign::{Signer, Verifier},
+    };
+    use protobuf::{EnumOrUnknown, SpecialFields};
+
+    use crate::robot::robotmanager::{
+        security::{SecurityMessage, Type},
+        Frame, FrameDecoder,
+    };
+
+    const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Keys and password used to authenticate with one robot's Runtime.
+    pub struct SecurityConfig {
+        /// Dawn's long-lived identity key, the Runtime knows its public half.
+        pub private_key: PKey<Private>,
+        /// The only Runtime key we'll accept.
+        pub runtime_public_key: PKey<Public>,
+        pub password: String,
+    }
+
+    #[derive(Debug)]
+    pub enum HandshakeError {
+        Config(String),
+        Io(io::Error),
+        Crypto(ErrorStack),
+        /// The Runtime answered with something other than the step we expected.
+        Unexpected(&'static str),
+        /// The Runtime presented a key that isn't the one configured for this robot.
+        UnknownRuntimeKey,
+        BadSignature,
+        /// The Runtime hung up instead of accepting our password.
+        Rejected,
+    }
+
+    impl fmt::Display for HandshakeError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                HandshakeError::Config(reason) => write!(f, "invalid security config: {}", reason),
+                HandshakeError::Io(e) => write!(f, "connection error during handshake: {}", e),
+                HandshakeError::Crypto(e) => write!(f, "crypto error: {}", e),
+                HandshakeError::Unexpected(step) => write!(f, "expected {} from Runtime", step),
+                HandshakeError::UnknownRuntimeKey => {
+                    write!(f, "Runtime key does not match runtime_public_key")
+                }
+                HandshakeError::BadSignature => write!(f, "Runtime signature did not verify"),
+                HandshakeError::Rejected => write!(f, "Runtime rejected the password"),
+            }
+        }
+    }
+
+    impl From<io::Error> for HandshakeError {
+        fn from(e: io::Error) -> Self {
+            HandshakeError::Io(e)
+        }
+    }
+
+    impl From<ErrorStack> for HandshakeError {
+        fn from(e: ErrorStack) -> Self {
+            HandshakeError::Crypto(e)
+        }
+    }
+
+    impl SecurityConfig {
+        /// Reads `[security <ip>]` from daybreak.ini, falling back to `[security]`.
+        /// Stock Runtime doesn't speak the handshake, so robots skip it unless their section says `enabled = true`.
+        pub fn load(config: &Path, ip: &str) -> Result<Option<SecurityConfig>, HandshakeError> {
+            match Ini::load_from_file(config) {
+                Ok(ini) => SecurityConfig::from_ini(&ini, ip),
+                Err(_) => Ok(None),
+            }
+        }
+
+        pub fn from_ini(ini: &Ini, ip: &str) -> Result<Option<SecurityConfig>, HandshakeError> {
+            let section = ini
+                .section(Some(format!("security {}", ip)))
+                .or_else(|| ini.section(Some("security")));
+            let section = match section {
+                Some(section) if section.get("enabled").map(str::trim) == Some("true") => section,
+                _ => return Ok(None),
+            };
+            let get = |key: &str| {
+                section
+                    .get(key)
+                    .ok_or_else(|| HandshakeError::Config(format!("missing {}", key)))
+            };
+            let read_pem = |key: &str| -> Result<Vec<u8>, HandshakeError> {
+                let path = get(key)?;
+                fs::read(path)
+                    .map_err(|e| HandshakeError::Config(format!("{} {}: {}", key, path, e)))
+            };
+            Ok(Some(SecurityConfig {
+                private_key: PKey::private_key_from_pem(&read_pem("private_key")?)?,
+                runtime_public_key: PKey::public_key_from_pem(&read_pem("runtime_public_key")?)?,
+                password: get("password")?.to_string(),
+            }))
+        }
+    }
+
+    pub fn sign(key: &PKey<Private>, data: &[u8]) -> Result<String, ErrorStack> {
+        let mut signer = Signer::new(MessageDigest::sha256(), key)?;
+        signer.update(data)?;
+        Ok(STANDARD.encode(signer.sign_to_vec()?))
+    }
+
+    pub fn verify(key: &PKey<Public>, data: &[u8], signature: &str) -> bool {
+        let signature = match STANDARD.decode(signature) {
+            Ok(signature) => signature,
+            Err(_) => return false,
+        };
+        let verified = Verifier::new(MessageDigest::sha256(), key).and_then(|mut verifier| {
+            verifier.update(data)?;
+            verifier.verify(&signature)
+        });
+        verified.unwrap_or(false)
+    }
+
+    pub fn security_message(
+        message_type: Type,
+        public_key: Vec<String>,
+        signature: Vec<String>,
+        encrypted_password: Vec<String>,
+        signature_public_key: Vec<String>,
+    ) -> SecurityMessage {
+        SecurityMessage {
+            type_: EnumOrUnknown::new(message_type),
+            publicKey: public_key,
+            signature,
+            encryptedPassword: encrypted_password,
+            signaturePublicKey: signature_public_key,
+            special_fields: SpecialFields::default(),
+        }
+    }
+
+    pub fn write_security_message(
+        stream: &mut TcpStream,
+        message: SecurityMessage,
+    ) -> io::Result<()> {
+        stream.write_all(&Frame::Security(message).encode()?)?;
+        stream.flush()
+    }
+
+    /// Blocks until the next SecurityMessage, skipping any other traffic.
+    /// `Ok(None)` means the peer closed the stream.
+    pub fn read_security_message(
+        stream: &mut TcpStream,
+        decoder: &mut FrameDecoder,
+    ) -> Result<Option<SecurityMessage>, HandshakeError> {
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        let mut read_buffer = [0u8; 4096];
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        loop {
+            while let Some(frame) = decoder.next_frame() {
+                if let Ok(Frame::Security(message)) = frame {
+                    return Ok(Some(message));
+                }
+            }
+            if Instant::now() >= deadline {
+                return Err(HandshakeError::Io(ErrorKind::TimedOut.into()));
+            }
+            match stream.read(&mut read_buffer) {
+                Ok(0) => return Ok(None),
+                Ok(read) => decoder.push(&read_buffer[..read]),
+                Err(e) if e.kind() == ErrorKind::ConnectionReset => return Ok(None),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Authenticates with a Runtime patched for Daybreak's handshake, right after the identification byte.
+    /// The steps travel as `SecurityMessage`s in frames of type 8, which stock Runtime doesn't use:
+    ///
+    /// 1. Dawn sends a `Request` carrying a fresh session key.
+    /// 2. The Runtime answers `EncryptKey` with its public key and a signature over the session key.
+    /// 3. Dawn sends `EncryptKey` with the password encrypted to the Runtime key, signed with Dawn's identity key.
+    /// 4. The Runtime acknowledges by signing the encrypted password, or closes the connection.
+    ///
+    /// Anything Runtime sends after the acknowledgement is left in `decoder` for the link to carry on with.
+    pub fn handshake(
+        stream: &mut TcpStream,
+        decoder: &mut FrameDecoder,
+        config: &SecurityConfig,
+    ) -> Result<(), HandshakeError> {
+        let session_key = PKey::from_rsa(Rsa::generate(2048)?)?;
+        let session_public = session_key.public_key_to_pem()?;
+        write_security_message(
+            stream,
+            security_message(
+                Type::Request,
+                vec![String::from_utf8_lossy(&session_public).to_string()],
+                vec![],
+                vec![],
+                vec![],
+            ),
+        )?;
+
+        let reply = read_security_message(stream, decoder)?.ok_or(HandshakeError::Rejected)?;
+        if reply.type_.enum_value() != Ok(Type::EncryptKey)
+            || reply.publicKey.is_empty()
+            || reply.signature.is_empty()
+        {
+            return Err(HandshakeError::Unexpected("the Runtime's key"));
+        }
+        let runtime_key = PKey::public_key_from_pem(reply.publicKey[0].as_bytes())?;
+        if !runtime_key.public_eq(&config.runtime_public_key) {
+            return Err(HandshakeError::UnknownRuntimeKey);
+        }
+        if !verify(&runtime_key, &session_public, &reply.signature[0]) {
+            return Err(HandshakeError::BadSignature);
+        }
+
+        let runtime_rsa = runtime_key.rsa()?;
+        let mut encrypted = vec![0; runtime_rsa.size() as usize];
+        let length = runtime_rsa.public_encrypt(
+            config.password.as_bytes(),
+            &mut encrypted,
+            Padding::PKCS1_OAEP,
+        )?;
+        encrypted.truncate(length);
+        let encrypted_password = STANDARD.encode(&encrypted);
+        let identity_public = config.private_key.public_key_to_pem()?;
+        write_security_message(
+            stream,
+            security_message(
+                Type::EncryptKey,
+                vec![],
+                vec![sign(&config.private_key, encrypted_password.as_bytes())?],
+                vec![encrypted_password.clone()],
+                vec![String::from_utf8_lossy(&identity_public).to_string()],
+            ),
+        )?;
+
+        let ack = read_security_message(stream, decoder)?.ok_or(HandshakeError::Rejected)?;
+        if ack.signature.is_empty() {
+            return Err(HandshakeError::Unexpected("an acknowledgement"));
+        }
+        if !verify(
+            &runtime_key,
+            encrypted_password.as_bytes(),
+            &ack.signature[0],
+        ) {
+            return Err(HandshakeError::BadSignature);
+        }
+        stream.set_read_timeout(None)?;
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::robot::robotmanager::device::{DevData, Device};
+        use std::{net::TcpListener, thread};
+
+        fn key_pair() -> (PKey<Private>, PKey<Public>) {
+            let private = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+            let public = PKey::public_key_from_pem(&private.public_key_to_pem().unwrap()).unwrap();
+            (private, public)
+        }
+
+        // a Runtime that refuses Dawn traffic until the handshake passes, then sends `after_ack` in the same write as its ack
+        fn mock_runtime(
+            runtime_key: PKey<Private>,
+            dawn_public: PKey<Public>,
+            password: &'static str,
+            after_ack: Vec<u8>,
+        ) -> (u16, thread::JoinHandle<bool>) {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let handle = thread::spawn(move || {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut identify = [0; 1];
+                stream.read_exact(&mut identify).unwrap();
+                let mut decoder = FrameDecoder::new();
+
+                let request = read_security_message(&mut stream, &mut decoder)
+                    .unwrap()
+                    .unwrap();
+                let runtime_public = runtime_key.public_key_to_pem().unwrap();
+                write_security_message(
+                    &mut stream,
+                    security_message(
+                        Type::EncryptKey,
+                        vec![String::from_utf8(runtime_public).unwrap()],
+                        vec![sign(&runtime_key, request.publicKey[0].as_bytes()).unwrap()],
+                        vec![],
+                        vec![],
+                    ),
+                )
+                .unwrap();
+
+                let answer = match read_security_message(&mut stream, &mut decoder) {
+                    Ok(Some(answer)) => answer,
+                    _ => return false,
+                };
+                let signer =
+                    PKey::public_key_from_pem(answer.signaturePublicKey[0].as_bytes()).unwrap();
+                let encrypted = STANDARD.decode(&answer.encryptedPassword[0]).unwrap();
+                let rsa = runtime_key.rsa().unwrap();
+                let mut decrypted = vec![0; rsa.size() as usize];
+                let length = rsa
+                    .private_decrypt(&encrypted, &mut decrypted, Padding::PKCS1_OAEP)
+                    .unwrap();
+                let accepted = signer.public_eq(&dawn_public)
+                    && verify(
+                        &signer,
+                        answer.encryptedPassword[0].as_bytes(),
+                        &answer.signature[0],
+                    )
+                    && &decrypted[..length] == password.as_bytes();
+                if accepted {
+                    let ack = security_message(
+                        Type::EncryptKey,
+                        vec![],
+                        vec![sign(&runtime_key, answer.encryptedPassword[0].as_bytes()).unwrap()],
+                        vec![],
+                        vec![],
+                    );
+                    let mut packet = Frame::Security(ack).encode().unwrap();
+                    packet.extend(after_ack);
+                    stream.write_all(&packet).unwrap();
+                }
+                accepted
+            });
+            (port, handle)
+        }
+
+        fn connect(port: u16) -> TcpStream {
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            stream.write_all(&[1]).unwrap();
+            stream
+        }
+
+        #[test]
+        fn handshake_with_matching_keys_and_password() {
+            let (runtime_private, runtime_public) = key_pair();
+            let (dawn_private, dawn_public) = key_pair();
+            let (port, runtime) = mock_runtime(runtime_private, dawn_public, "hunter2", vec![]);
+            let config = SecurityConfig {
+                private_key: dawn_private,
+                runtime_public_key: runtime_public,
+                password: "hunter2".to_string(),
+            };
+            handshake(&mut connect(port), &mut FrameDecoder::new(), &config).unwrap();
+            assert!(runtime.join().unwrap());
+        }
+
+        #[test]
+        fn frames_behind_the_ack_are_kept() {
+            let (runtime_private, runtime_public) = key_pair();
+            let (dawn_private, dawn_public) = key_pair();
+            let devices = Frame::DeviceData(DevData {
+                devices: vec![Device {
+                    name: "LimitSwitch".to_string(),
+                    uid: 1,
+                    ..Device::default()
+                }],
+                special_fields: SpecialFields::default(),
+            });
+            // a whole frame and the start of the next, as one read might see them
+            let mut after_ack = devices.encode().unwrap();
+            after_ack.extend(&devices.encode().unwrap()[..4]);
+            let (port, runtime) = mock_runtime(runtime_private, dawn_public, "hunter2", after_ack);
+            let config = SecurityConfig {
+                private_key: dawn_private,
+                runtime_public_key: runtime_public,
+                password: "hunter2".to_string(),
+            };
+            let mut decoder = FrameDecoder::new();
+            handshake(&mut connect(port), &mut decoder, &config).unwrap();
+            assert!(runtime.join().unwrap());
+            assert_eq!(decoder.next_frame().unwrap().unwrap(), devices);
+            assert_eq!(decoder.pending(), 4);
+        }
+
+        #[test]
+        fn wrong_password_is_rejected() {
+            let (runtime_private, runtime_public) = key_pair();
+            let (dawn_private, dawn_public) = key_pair();
+            let (port, runtime) = mock_runtime(runtime_private, dawn_public, "hunter2", vec![]);
+            let config = SecurityConfig {
+                private_key: dawn_private,
+                runtime_public_key: runtime_public,
+                password: "password".to_string(),
+            };
+            let mut stream = connect(port);
+            let result = handshake(&mut stream, &mut FrameDecoder::new(), &config);
+            assert!(!runtime.join().unwrap());
+            drop(stream);
+            assert!(matches!(result, Err(HandshakeError::Rejected)));
+        }
+
+        #[test]
+        fn impostor_runtime_is_refused() {
+            let (runtime_private, _) = key_pair();
+            let (_, expected_runtime) = key_pair();
+            let (dawn_private, dawn_public) = key_pair();
+            let (port, runtime) = mock_runtime(runtime_private, dawn_public, "hunter2", vec![]);
+            let config = SecurityConfig {
+                private_key: dawn_private,
+                runtime_public_key: expected_runtime,
+                password: "hunter2".to_string(),
+            };
+            let mut stream = connect(port);
+            let result = handshake(&mut stream, &mut FrameDecoder::new(), &config);
+            // the password never goes out
+            drop(stream);
+            assert!(matches!(result, Err(HandshakeError::UnknownRuntimeKey)));
+            assert!(!runtime.join().unwrap());
+        }
+
+        #[test]
+        fn robots_without_an_enabled_section_skip_the_handshake() {
+            let ini = Ini::load_from_str("[connection]\nip = 192.168.0.140\n").unwrap();
+            assert!(SecurityConfig::from_ini(&ini, "192.168.0.140")
+                .unwrap()
+                .is_none());
+
+            let ini = Ini::load_from_str("[security 192.168.0.140]\npassword = hunter2\n").unwrap();
+            assert!(SecurityConfig::from_ini(&ini, "192.168.0.140")
+                .unwrap()
+                .is_none());
+
+            let ini = Ini::load_from_str("[security 192.168.0.140]\nenabled = true\npassword = hunter2\n").unwrap();
+            assert!(matches!(
+                SecurityConfig::from_ini(&ini, "192.168.0.140"),
+                Err(HandshakeError::Config(_))
+            ));
+        }
+    }
+}
@@ -1,19 +1,17 @@
 use std::{
     collections::HashMap,
     fs::{self, OpenOptions},
-    io::{BufReader, Read, Write},
+    io::{BufReader, Write},
     path::PathBuf,
     sync::{
         atomic::{AtomicBool, Ordering},
-        mpsc::{self, Receiver, Sender},
-        Arc, Mutex,
+        mpsc::{self, Sender},
+        Arc,
     },
     thread::{self, JoinHandle},
-    time::Duration,
 };
 
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use protobuf::well_known_types::duration::Duration as ProtoDuration;
 use rodio::{Decoder, Source};
 
 struct AudioData {
@@ -24,7 +22,6 @@ struct AudioData {
     stream: Option<cpal::Stream>,
     stop_sender: Option<Sender<()>>,
     is_once: bool,
-    name: String,
 }
 
 // Message enum for communication with the audio thread
@@ -73,7 +70,6 @@ impl SfxManager {
             }
 
             let mut audio_map = HashMap::new();
-            let mut active_streams: Vec<cpal::Stream> = Vec::new();
 
             // Load all audio files
             if let Ok(entries) = fs::read_dir(&sfx_dir) {
@@ -107,7 +103,6 @@ impl SfxManager {
                                                             stream: None,
                                                             stop_sender: None,
                                                             is_once: false,
-                                                            name: name_str.to_string(),
                                                         },
                                                     );
                                                     println!(
@@ -0,0 +1,296 @@
+#[allow(clippy::module_inception)]
+pub mod ssh {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use base64::{engine::general_purpose::STANDARD_NO_PAD, Engine};
+    use ini::Ini;
+    use ssh2::{CheckResult, HashType, KnownHostFileKind, KnownHostKeyFormat, KnownHosts, Session};
+
+    use crate::{ipc::ipc::RobotType, robot::robotmanager::config_dir};
+
+    /// How to log in to a robot for uploads and downloads.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct SshLogin {
+        pub user: String,
+        pub password: Option<String>,
+        pub private_key: Option<PathBuf>,
+        /// Found next to the private key when not given.
+        pub public_key: Option<PathBuf>,
+        pub passphrase: Option<String>,
+        /// Try the keys held by ssh-agent first.
+        pub agent: bool,
+    }
+
+    impl SshLogin {
+        /// The login each robot type ships with.
+        pub fn default_for(robot_type: RobotType) -> SshLogin {
+            let (user, password) = match robot_type {
+                RobotType::Raspberry => ("pi", "raspberry"),
+                RobotType::Potato => ("ubuntu", "potato"),
+            };
+            SshLogin {
+                user: user.to_string(),
+                password: Some(password.to_string()),
+                private_key: None,
+                public_key: None,
+                passphrase: None,
+                agent: false,
+            }
+        }
+
+        /// Reads `[ssh <ip>]` from daybreak.ini, falling back to `[ssh <robot_type>]`, `[ssh]`, then the type's default login.
+        pub fn load(config: &Path, ip: &str, robot_type: RobotType) -> SshLogin {
+            match Ini::load_from_file(config) {
+                Ok(ini) => SshLogin::from_ini(&ini, ip, robot_type),
+                Err(_) => SshLogin::default_for(robot_type),
+            }
+        }
+
+        pub fn from_ini(ini: &Ini, ip: &str, robot_type: RobotType) -> SshLogin {
+            let default = SshLogin::default_for(robot_type);
+            let type_name = match robot_type {
+                RobotType::Raspberry => "raspberry",
+                RobotType::Potato => "potato",
+            };
+            let section = ini
+                .section(Some(format!("ssh {}", ip)))
+                .or_else(|| ini.section(Some(format!("ssh {}", type_name))))
+                .or_else(|| ini.section(Some("ssh")));
+            let section = match section {
+                Some(section) => section,
+                None => return default,
+            };
+            let get = |key: &str| section.get(key).map(str::trim).filter(|value| !value.is_empty());
+            let private_key = get("private_key").map(expand_home);
+            let agent = get("agent") == Some("true");
+            // a robot moved to keys shouldn't be offered the old password
+            let password = match get("password") {
+                Some(password) => Some(password.to_string()),
+                None if private_key.is_none() && !agent => default.password,
+                None => None,
+            };
+            SshLogin {
+                user: get("user").unwrap_or(&default.user).to_string(),
+                password,
+                private_key,
+                public_key: get("public_key").map(expand_home),
+                passphrase: get("passphrase").map(str::to_string),
+                agent,
+            }
+        }
+
+        /// Tries ssh-agent, the private key and the password in turn, saying what was refused if none works.
+        pub fn authenticate(&self, sess: &Session) -> Result<(), String> {
+            let mut refused = vec![];
+            if self.agent {
+                match sess.userauth_agent(&self.user) {
+                    Ok(()) => return Ok(()),
+                    Err(e) => refused.push(format!("ssh-agent ({})", e)),
+                }
+            }
+            if let Some(private_key) = &self.private_key {
+                match sess.userauth_pubkey_file(
+                    &self.user,
+                    self.public_key.as_deref(),
+                    private_key,
+                    self.passphrase.as_deref(),
+                ) {
+                    Ok(()) => return Ok(()),
+                    Err(e) => refused.push(format!("key {} ({})", private_key.display(), e)),
+                }
+            }
+            if let Some(password) = &self.password {
+                match sess.userauth_password(&self.user, password) {
+                    Ok(()) => return Ok(()),
+                    Err(e) => refused.push(format!("password ({})", e)),
+                }
+            }
+            if refused.is_empty() {
+                return Err(format!("no way to log in as {} is configured", self.user));
+            }
+            Err(format!("{} was refused: {}", self.user, refused.join(", ")))
+        }
+    }
+
+    /// Daybreak's own known_hosts, kept apart from `~/.ssh/known_hosts`.
+    pub fn known_hosts_path() -> PathBuf {
+        config_dir().join("known_hosts")
+    }
+
+    /// What known_hosts says about a robot's host key.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum HostTrust {
+        Trusted,
+        Unknown,
+        /// The robot is known by another key.
+        Mismatch,
+    }
+
+    /// The host key a robot showed in the ssh handshake.
+    pub struct HostKey {
+        ip: String,
+        port: u16,
+        /// In the `SHA256:...` form ssh prints.
+        pub fingerprint: String,
+        key: Vec<u8>,
+        format: KnownHostKeyFormat,
+    }
+
+    impl HostKey {
+        pub fn of(sess: &Session, ip: &str, port: u16) -> Option<HostKey> {
+            let (key, kind) = sess.host_key()?;
+            Some(HostKey {
+                ip: ip.to_string(),
+                port,
+                fingerprint: fingerprint(sess.host_key_hash(HashType::Sha256)?),
+                key: key.to_vec(),
+                format: kind.into(),
+            })
+        }
+
+        /// The robot as known_hosts names it, `[ip]:port` off the standard port.
+        pub fn host(&self) -> String {
+            if self.port == 22 {
+                self.ip.clone()
+            } else {
+                format!("[{}]:{}", self.ip, self.port)
+            }
+        }
+
+        /// A known_hosts file that doesn't exist yet trusts nothing.
+        pub fn check(&self, known_hosts: &Path) -> Result<HostTrust, String> {
+            // by name, since libssh2's port lookup falls back to the bare address
+            match read_known_hosts(known_hosts)?.check(&self.host(), &self.key) {
+                CheckResult::Match => Ok(HostTrust::Trusted),
+                CheckResult::Mismatch => Ok(HostTrust::Mismatch),
+                CheckResult::NotFound => Ok(HostTrust::Unknown),
+                CheckResult::Failure => Err(format!("couldn't check {} against {}", self.host(), known_hosts.display())),
+            }
+        }
+
+        /// Writes this key to known_hosts in place of any the robot had before.
+        pub fn trust(&self, known_hosts: &Path) -> Result<(), String> {
+            let file_error = |e: &dyn std::fmt::Display| format!("{}: {}", known_hosts.display(), e);
+            let mut hosts = read_known_hosts(known_hosts)?;
+            let host = self.host();
+            for old in hosts.hosts().map_err(|e| file_error(&e))? {
+                if old.name() == Some(host.as_str()) {
+                    hosts.remove(&old).map_err(|e| file_error(&e))?;
+                }
+            }
+            hosts
+                .add(&host, &self.key, "daybreak trust", self.format)
+                .map_err(|e| file_error(&e))?;
+            if let Some(dir) = known_hosts.parent() {
+                fs::create_dir_all(dir).map_err(|e| file_error(&e))?;
+            }
+            hosts
+                .write_file(known_hosts, KnownHostFileKind::OpenSSH)
+                .map_err(|e| file_error(&e))
+        }
+    }
+
+    fn fingerprint(hash: &[u8]) -> String {
+        format!("SHA256:{}", STANDARD_NO_PAD.encode(hash))
+    }
+
+    fn read_known_hosts(path: &Path) -> Result<KnownHosts, String> {
+        let mut hosts = Session::new()
+            .and_then(|sess| sess.known_hosts())
+            .map_err(|e| e.to_string())?;
+        if path.exists() {
+            hosts
+                .read_file(path, KnownHostFileKind::OpenSSH)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+        }
+        Ok(hosts)
+    }
+
+    fn expand_home(path: &str) -> PathBuf {
+        match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+            (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+            _ => PathBuf::from(path),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn robots_without_a_section_keep_their_default_login() {
+            let ini = Ini::load_from_str("[connection]\nip = 192.168.0.140\n").unwrap();
+            let login = SshLogin::from_ini(&ini, "192.168.0.140", RobotType::Raspberry);
+            assert_eq!(login, SshLogin::default_for(RobotType::Raspberry));
+            assert_eq!((login.user.as_str(), login.password.as_deref()), ("pi", Some("raspberry")));
+        }
+
+        #[test]
+        fn the_most_specific_section_wins() {
+            let ini = Ini::load_from_str(
+                "[ssh]\nuser = team\npassword = shared\n\
+                 [ssh potato]\npassword = spud\n\
+                 [ssh 192.168.0.140]\nprivate_key = /keys/robot\nagent = true\n",
+            )
+            .unwrap();
+
+            let login = SshLogin::from_ini(&ini, "192.168.0.140", RobotType::Potato);
+            // a key or the agent replaces the default password rather than adding to it
+            assert_eq!(login.user, "ubuntu");
+            assert_eq!(login.password, None);
+            assert_eq!(login.private_key, Some(PathBuf::from("/keys/robot")));
+            assert!(login.agent);
+
+            let login = SshLogin::from_ini(&ini, "192.168.0.141", RobotType::Potato);
+            assert_eq!((login.user.as_str(), login.password.as_deref()), ("ubuntu", Some("spud")));
+
+            let login = SshLogin::from_ini(&ini, "192.168.0.141", RobotType::Raspberry);
+            assert_eq!((login.user.as_str(), login.password.as_deref()), ("team", Some("shared")));
+            assert!(!login.agent);
+        }
+
+        // ssh-rsa, since the ed25519 variant is spelled differently across ssh2 0.9 releases
+        fn host_key(port: u16, seed: u8) -> HostKey {
+            let mut key = vec![];
+            for part in [&b"ssh-rsa"[..], &[1, 0, 1][..], &[seed; 32][..]] {
+                key.extend_from_slice(&(part.len() as u32).to_be_bytes());
+                key.extend_from_slice(part);
+            }
+            HostKey {
+                ip: "192.168.0.140".to_string(),
+                port,
+                fingerprint: fingerprint(&[seed; 32]),
+                key,
+                format: KnownHostKeyFormat::SshRsa,
+            }
+        }
+
+        #[test]
+        fn robots_are_trusted_by_their_first_key_until_replaced() {
+            let path = std::env::temp_dir().join(format!("daybreak-test-known-hosts-{}", std::process::id()));
+            let _ = fs::remove_file(&path);
+            let robot = host_key(22, 1);
+            let imposter = host_key(22, 2);
+            assert_eq!(robot.fingerprint, format!("SHA256:{}", "AQEB".repeat(10) + "AQE"));
+
+            assert_eq!(robot.check(&path).unwrap(), HostTrust::Unknown);
+            robot.trust(&path).unwrap();
+            assert_eq!(robot.check(&path).unwrap(), HostTrust::Trusted);
+            assert_eq!(imposter.check(&path).unwrap(), HostTrust::Mismatch);
+            // the same address on another port is another robot
+            assert_eq!(host_key(2222, 2).check(&path).unwrap(), HostTrust::Unknown);
+
+            imposter.trust(&path).unwrap();
+            assert_eq!(imposter.check(&path).unwrap(), HostTrust::Trusted);
+            assert_eq!(robot.check(&path).unwrap(), HostTrust::Mismatch);
+            host_key(2222, 1).trust(&path).unwrap();
+            let written = fs::read_to_string(&path).unwrap();
+            assert_eq!(written.lines().count(), 2, "{}", written);
+            assert!(written.contains("[192.168.0.140]:2222 ssh-rsa "), "{}", written);
+            let _ = fs::remove_file(&path);
+        }
+    }
+}
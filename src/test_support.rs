@@ -0,0 +1,45 @@
+#[allow(clippy::module_inception)]
+pub mod test_support {
+    use std::{
+        os::unix::net::UnixListener,
+        sync::Arc,
+        thread::{self, JoinHandle},
+        time::{Duration, Instant},
+    };
+
+    use crate::daemon::daemonhandler::{serve, DaemonState};
+
+    /// A daemon on its own socket, so tests don't fight over the real one.
+    pub fn spawn_daemon(name: &str) -> (String, JoinHandle<()>) {
+        serve_state(name, DaemonState::new)
+    }
+
+    /// Like `spawn_daemon`, with the state built from the socket path by the test.
+    pub fn serve_state(name: &str, state: impl FnOnce(&str) -> DaemonState) -> (String, JoinHandle<()>) {
+        let path = std::env::temp_dir()
+            .join(format!("daybreak-test-{}-{}.sock", name, std::process::id()))
+            .display()
+            .to_string();
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let state = Arc::new(state(&path));
+        let daemon = thread::spawn(move || serve(listener, state));
+        (path, daemon)
+    }
+
+    pub fn start_daemon(name: &str) -> String {
+        spawn_daemon(name).0
+    }
+
+    /// Polls `check` until it finds something, failing the test after ten seconds.
+    pub fn eventually<T>(what: &str, mut check: impl FnMut() -> Option<T>) -> T {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            if let Some(found) = check() {
+                return found;
+            }
+            assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
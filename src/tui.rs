@@ -1,7 +1,8 @@
+#[allow(clippy::module_inception)]
 pub mod tui {
-    use crossterm::{event::{self, Event, KeyEvent}, style::Stylize};
-    use ratatui::{layout::{Alignment, Constraint, Layout}, style::{Style}, text::{Text, ToLine, ToText}, widgets::{Block, Paragraph, Wrap}, Frame};
-    use Constraint::{Fill, Length, Min, Percentage};
+    use crate::error::error::DaybreakError;
+
+    #[derive(Default)]
     pub struct App {
         pub scroll: usize,
     }
@@ -23,4 +24,12 @@ pub mod tui {
             }
         }
     }
+
+    /// Hands the terminal back before a drawing error reaches the user.
+    pub fn restore_on_error<T>(result: std::io::Result<T>) -> Result<T, DaybreakError> {
+        result.map_err(|e| {
+            ratatui::restore();
+            DaybreakError::Terminal(e)
+        })
+    }
 }
@@ -1,15 +1,27 @@
 pub mod read_devices_tui {
+    use ini::Ini;
     use std::{
-        io::{Read, Write},
+        io::{self, ErrorKind},
         os::unix::net::UnixStream,
-        process::exit,
-        sync::{Arc, Mutex},
+        process::{Command, Stdio},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex, OnceLock,
+        },
         thread,
+        time::{Duration, Instant},
     };
 
     use crate::{
-        robot::robotmanager::device::{param::Val, DevData},
-        tui::tui::App,
+        client::client::DaybreakClient,
+        daemon::daemonhandler::DAEMON_START_TIMEOUT,
+        error::error::DaybreakError,
+        robot::robotmanager::{
+            device::{param::Val, DevData},
+            config_path, daemon_log_path, daemon_socket_path, DEFAULT_SESSION,
+        },
+        ipc::ipc::{ConnectionState, Event},
+        tui::tui::{restore_on_error, App},
     };
     use crossterm::event;
     use protobuf::Message;
@@ -20,15 +32,110 @@ pub mod read_devices_tui {
     };
     use Constraint::Percentage;
 
-    pub fn tui() {
+    static SESSION: OnceLock<String> = OnceLock::new();
+
+    /// Points every following daemon request at a named robot session.
+    pub fn select_session(name: &str) {
+        let _ = SESSION.set(name.to_string());
+    }
+
+    pub fn selected_session() -> &'static str {
+        SESSION.get().map_or(DEFAULT_SESSION, String::as_str)
+    }
+
+    /// A client for the session picked with `select_session`.
+    pub fn daemon_client() -> DaybreakClient {
+        DaybreakClient::new().with_session(selected_session())
+    }
+
+    // `autostart = false` under [daemon] in daybreak.ini leaves starting the daemon to the user
+    fn autostart_enabled() -> bool {
+        let ini = Ini::load_from_file(config_path());
+        let autostart = ini.as_ref().ok().and_then(|ini| ini.get_from(Some("daemon"), "autostart"));
+        autostart.is_none_or(|value| value.trim() != "false")
+    }
+
+    /// Starts the daemon in the background when nothing answers on its socket, and waits until it does.
+    pub fn ensure_daemon() -> io::Result<()> {
+        match UnixStream::connect(daemon_socket_path()?) {
+            Ok(_) => return Ok(()),
+            Err(e) if !matches!(e.kind(), ErrorKind::NotFound | ErrorKind::ConnectionRefused) => return Err(e),
+            Err(_) if !autostart_enabled() => return Ok(()),
+            Err(_) => {}
+        }
+        // stderr, so output like status --json stays parseable
+        eprintln!("[Daemon] Not running, starting it in the background...");
+        let status = Command::new(std::env::current_exe()?)
+            .args(["--start", "--detach"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .status()?;
+        // another command may have started it first, in which case ours lost the lock to it
+        let deadline = Instant::now() + DAEMON_START_TIMEOUT;
+        while !status.success() && Instant::now() < deadline {
+            if UnixStream::connect(daemon_socket_path()?).is_ok() {
+                return Ok(());
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "the daemon didn't start, see {}",
+                daemon_log_path()?
+            )));
+        }
+        Ok(())
+    }
+
+    /// Shows the robot's devices until the user quits with `q` or Esc.
+    pub fn tui() -> Result<(), DaybreakError> {
         // In your main loop:
         let app = Arc::new(Mutex::new(App::new()));
         let app_clone = Arc::clone(&app);
-        let mut devices_string = Arc::new(read_devices());
+        let client = daemon_client();
+        let devices_string = Arc::new(Mutex::new(match client.devices() {
+            Ok(Some(devices)) => describe_devices(&devices),
+            Ok(None) => "[List Devices] No robot available.".to_string(),
+            Err(e) => format!("[List Devices] {}", e),
+        }));
 
+        // the daemon pushes devices as they change, so there's nothing to poll
+        let devices_string_clone = Arc::clone(&devices_string);
+        thread::spawn(move || loop {
+            let events = client.subscribe(u64::MAX);
+            if let Err(e) = events {
+                *devices_string_clone.lock().unwrap() = format!("[List Devices] {}", e);
+                thread::sleep(Duration::from_secs(1));
+                continue;
+            }
+            let mut events = events.unwrap();
+            while let Ok(event) = events.next_event() {
+                match event {
+                    Some(Event::Devices(devices)) => {
+                        if let Ok(devices) = DevData::parse_from_bytes(&devices) {
+                            *devices_string_clone.lock().unwrap() = describe_devices(&devices);
+                        }
+                    }
+                    Some(Event::Connection(ConnectionState::Disconnected | ConnectionState::Failed(_))) => {
+                        *devices_string_clone.lock().unwrap() =
+                            "[List Devices] No robot available.".to_string();
+                    }
+                    _ => {}
+                }
+            }
+            thread::sleep(Duration::from_secs(1));
+        });
+
+        let devices_string_clone = Arc::clone(&devices_string);
+        let quit = Arc::new(AtomicBool::new(false));
+        let quit_clone = Arc::clone(&quit);
         let mut terminal = ratatui::init();
         thread::spawn(move || loop {
-            match event::read().unwrap() {
+            let Ok(event) = event::read() else {
+                quit_clone.store(true, Ordering::Relaxed);
+                return;
+            };
+            match event {
                 event::Event::Key(event::KeyEvent {
                     code: event::KeyCode::Esc,
                     ..
@@ -37,8 +144,8 @@ pub mod read_devices_tui {
                     code: event::KeyCode::Char('q'),
                     ..
                 }) => {
-                    ratatui::restore();
-                    exit(0);
+                    quit_clone.store(true, Ordering::Relaxed);
+                    return;
                 }
                 event::Event::Key(event::KeyEvent {
                     code: event::KeyCode::Up,
@@ -47,22 +154,22 @@ pub mod read_devices_tui {
                 event::Event::Key(event::KeyEvent {
                     code: event::KeyCode::Down,
                     ..
-                }) => app_clone
-                    .lock()
-                    .unwrap()
-                    .scroll_down(devices_string.lines().count()),
+                }) => {
+                    let count = devices_string_clone.lock().unwrap().lines().count();
+                    app_clone.lock().unwrap().scroll_down(count)
+                }
                 _ => (),
             }
         });
-        loop {
-            devices_string = Arc::new(read_devices());
-
+        while !quit.load(Ordering::Relaxed) {
             let lines: Vec<ListItem> = devices_string
+                .lock()
+                .unwrap()
                 .lines()
                 .map(|line| ListItem::new(line.to_string()))
                 .collect();
 
-            terminal
+            restore_on_error(terminal
                 .draw(|frame| {
                     let vertical = Layout::vertical([Percentage(100)]);
                     let [main_area] = vertical.areas(frame.area());
@@ -76,78 +183,45 @@ pub mod read_devices_tui {
                         main_area,
                         &mut ListState::default().with_offset(app.lock().unwrap().scroll),
                     );
-                })
-                .unwrap();
+                }))?;
 
-            // Handle scrolling input
+            thread::sleep(Duration::from_millis(50));
         }
+        ratatui::restore();
+        Ok(())
     }
-    pub fn read_devices() -> String {
-        for _ in 1..10 {
-            let stream = UnixStream::connect(format!(
-                "{}/daybreak.sock",
-                std::env::temp_dir().into_os_string().into_string().unwrap()
-            ));
-            if stream.is_err() {
-                return "[List Devices] Failed to connect to daemon.".to_string();
-            }
-
-            let mut stream = stream.unwrap();
-            stream.write(&[4]).unwrap();
-            stream.flush().unwrap();
-            let mut buffer = [0; 3];
-            stream.read(&mut buffer).unwrap();
-            if buffer[0] == 0 {
-                // println!("[List Devices] No robot available.");
-                return "[List Devices] No robot available.".to_string();
-            }
 
-            let msg_length = (buffer[2] as usize) << 8 | buffer[1] as usize;
-            let mut buffer = vec![0; msg_length];
-            stream.read_exact(&mut buffer).unwrap();
-            let device_data = DevData::parse_from_bytes(&buffer);
-            if device_data.is_err() {
-                // println!("[List Devices] Failed to parse devices list.");
-                // println!("{:?}", device_data.err().unwrap());
-                return "".to_string();
-            }
-            let device_data = device_data.unwrap();
-            // TODO - Work on Parsing Device Data, make it pretty
-            let devices = device_data.devices;
+    /// Lists a `DevData`'s devices and their parameters, one per line.
+    pub fn describe_devices(device_data: &DevData) -> String {
+        // TODO - Work on Parsing Device Data, make it pretty
+        let devices = &device_data.devices;
 
-            if devices.len() == 0 {
-                // println!("No devices available.");
-                continue;
+        let mut built_str = String::new();
+        for device in devices {
+            if device.name == "CustomData" {
+                // println!("{} (Stopwatch)", device.uid);
+                built_str.push_str(&format!("{} (Stopwatch)\n", device.uid));
+            } else {
+                // println!("{} ({})", device.uid, device.name);
+                built_str.push_str(&format!(
+                    "{}_{} ({})\n",
+                    device.type_, device.uid, device.name
+                ));
             }
-            let mut built_str = String::new();
-            for device in devices {
-                if device.name == "CustomData" {
-                    // println!("{} (Stopwatch)", device.uid);
-                    built_str.push_str(&format!("{} (Stopwatch)\n", device.uid));
-                } else {
-                    // println!("{} ({})", device.uid, device.name);
-                    built_str.push_str(&format!(
-                        "{}_{} ({})\n",
-                        device.type_, device.uid, device.name
-                    ));
-                }
-                for field in device.params {
-                    // turn the val into its respective data type
-                    let val = field.val.as_ref().unwrap();
-                    let val = match val {
-                        Val::Bval(val) => val.to_string(),
-                        Val::Fval(val) => val.to_string(),
-                        Val::Ival(val) => val.to_string(),
-                        _ => "Unknown".to_string(),
-                    };
-                    built_str.push_str(&format!("{} - {}\n", field.name, val));
-                    // println!("{} - {}", field.name, val);
-                }
-                built_str.push_str("\n\n");
-                // println!("\n");
+            for field in &device.params {
+                // turn the val into its respective data type
+                let val = field.val.as_ref().unwrap();
+                let val = match val {
+                    Val::Bval(val) => val.to_string(),
+                    Val::Fval(val) => val.to_string(),
+                    Val::Ival(val) => val.to_string(),
+                };
+                built_str.push_str(&format!("{} - {}\n", field.name, val));
+                // println!("{} - {}", field.name, val);
             }
-            return built_str;
+            built_str.push_str("\n\n");
+            // println!("\n");
         }
-        return "".to_string();
+        built_str
     }
 }
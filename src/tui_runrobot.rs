@@ -2,23 +2,17 @@ pub mod run_robot_tui {
     use std::{
         collections::HashMap,
         fs,
-        io::{BufReader, Cursor, Read, Seek, Write},
-        net::{TcpListener, TcpStream},
-        os::unix::net::UnixStream,
-        path::{Path, PathBuf},
-        process::exit,
         sync::{
             atomic::{AtomicBool, Ordering},
-            mpsc::{self, Sender},
-            Arc, Mutex,
+            mpsc, Arc, Mutex, PoisonError,
         },
         thread,
-        time::Duration,
+        time::{Duration, Instant},
     };
 
-    use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-    use crossterm::event::{self, Event as CrosstermEvent, KeyCode, KeyEvent};
+    use crossterm::event;
     use gilrs::{Axis, Button, Event, GamepadId, Gilrs};
+    use ini::Ini;
     use protobuf::{EnumOrUnknown, Message, SpecialFields};
     use ratatui::{
         layout::{Constraint, Layout},
@@ -26,58 +20,209 @@ pub mod run_robot_tui {
         text::Line,
         widgets::{Block, List, ListItem, ListState},
     };
-    use rodio::source::Source as RodioSource;
-    use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink};
     use signal_hook::{consts::SIGINT, iterator::Signals};
-    use Constraint::Percentage;
+    use Constraint::{Fill, Length, Percentage};
 
     use crate::{
+        client::client::{DaybreakClient, RunStream},
+        error::error::DaybreakError,
+        ipc::ipc::{self, ConnectionState, RunCommand},
         keymap::gamepad_mapped,
-        keymap::key_map,
-        robot::robotmanager::input::{Input, Source as InputSource},
+        robot::robotmanager::{
+            device::DevData,
+            gamestate::State,
+            input::{Input, Source as InputSource},
+            config_path,
+            start_pos::Pos,
+            LatencyStats, LinkState, RuntimeStatus,
+        },
+        run_log::run_log::decode_log_lines,
         sfx_manager::SfxManager,
-        tui::tui::App,
-        tui_readdevices::read_devices_tui::read_devices,
+        tui::tui::{restore_on_error, App},
+        tui_readdevices::read_devices_tui::describe_devices,
     };
 
-    pub fn tui(stream: Arc<Mutex<UnixStream>>) {
+    // how long a one-off field event stays on screen
+    const GAME_BANNER_DURATION: Duration = Duration::from_secs(5);
+
+    fn game_state_banner(state: State, received: Instant) -> Option<Line<'static>> {
+        let banner = match state {
+            State::POISON_IVY => " POISON IVY ".reset().on_green().white().bold(),
+            State::DEHYDRATION => " DEHYDRATION ".reset().on_yellow().black().bold(),
+            // hypothermia lasts until the field ends it
+            State::HYPOTHERMIA_START => " HYPOTHERMIA ".reset().on_blue().white().bold(),
+            State::HYPOTHERMIA_END => " HYPOTHERMIA OVER ".reset().on_dark_gray().white().bold(),
+        };
+        if state != State::HYPOTHERMIA_START && received.elapsed() > GAME_BANNER_DURATION {
+            return None;
+        }
+        Some(Line::from(vec![banner]).centered())
+    }
+
+    // the [sfx] section of daybreak.ini, read once when the TUI opens
+    fn sfx_overrides() -> HashMap<String, String> {
+        let ini = Ini::load_from_file(config_path());
+        let section = ini.ok().and_then(|ini| ini.section(Some("sfx")).cloned());
+        section.map_or_else(HashMap::new, |section| {
+            section.iter().map(|(key, name)| (key.to_string(), name.to_string())).collect()
+        })
+    }
+
+    // the sound for a field event, overridable under [sfx] in daybreak.ini
+    fn game_state_sound(overrides: &HashMap<String, String>, state: State) -> String {
+        let key = format!("{:?}", state).to_lowercase();
+        overrides.get(&key).cloned().unwrap_or(key)
+    }
+
+    fn status_header(
+        status: &Option<RuntimeStatus>,
+        latency: &Option<LatencyStats>,
+        start_pos: &Option<Pos>,
+        link: &Option<LinkState>,
+    ) -> Line<'static> {
+        let mut line = match link {
+            Some(LinkState::Reconnecting) => {
+                Line::from(vec![" Reconnecting ".reset().on_yellow().black().bold()])
+            }
+            Some(LinkState::Lost) => Line::from(vec![" Link lost ".reset().on_red().white().bold()]),
+            _ => Line::default(),
+        };
+        match status {
+            Some(status) => line.spans.extend(runtime_status_spans(status).spans),
+            None => line.push_span(" No Runtime status ".reset().on_dark_gray().white()),
+        }
+        if let Some(start_pos) = start_pos {
+            line.push_span(" Start ".reset());
+            line.push_span(format!("{:?}", start_pos).blue().bold());
+        }
+        if let Some(latency) = latency {
+            line.push_span(" Latency ".reset());
+            line.push_span(
+                format!("{:.1}/{}ms", latency.avg_ms, latency.p95_ms)
+                    .blue()
+                    .bold(),
+            );
+            if latency.is_degraded() {
+                line.push_span(" ");
+                line.push_span(" Link degraded ".reset().on_red().white().bold());
+            }
+        }
+        line
+    }
+
+    fn runtime_status_spans(status: &RuntimeStatus) -> Line<'static> {
+        let connected = |name: &'static str, connected: bool| {
+            if connected {
+                format!(" {} ", name).reset().on_green().white().bold()
+            } else {
+                format!(" {} ", name).reset().on_red().white().bold()
+            }
+        };
+        let battery = status.battery.map_or("?".to_string(), |volts| format!("{:.2}V", volts));
+        Line::from(vec![
+            " Battery ".reset(),
+            battery.blue().bold(),
+            " Mode ".reset(),
+            format!("{:?}", status.mode).blue().bold(),
+            " ".into(),
+            connected("Shepherd", status.shep_connected),
+            " ".into(),
+            connected("Dawn", status.dawn_connected),
+        ])
+    }
+
+    /// `replaying` shows devices from the start, since a replay begins already running.
+    pub fn tui(client: DaybreakClient, replaying: bool) -> Result<(), DaybreakError> {
         println!("Starting TUI...");
         let devices_string: Arc<Mutex<String>> =
             Arc::new(Mutex::new(String::from("Disconnected from Robot")));
         let terminal_string = Arc::new(Mutex::new(String::new()));
         let selected_pane = Arc::new(Mutex::new(0));
 
-        let stream = Arc::clone(&stream);
+        let stream = Arc::new(Mutex::new(client.run_stream()?));
 
         println!("Initialized streams...");
 
         println!("Starting executor..");
-        let is_robot_running = Arc::new(Mutex::new(false));
+        let is_robot_running = Arc::new(Mutex::new(replaying));
 
-        println!("Starting log looker...");
+        let runtime_status: Arc<Mutex<Option<RuntimeStatus>>> = Arc::new(Mutex::new(None));
+        let latency: Arc<Mutex<Option<LatencyStats>>> = Arc::new(Mutex::new(None));
+        let link: Arc<Mutex<Option<LinkState>>> = Arc::new(Mutex::new(None));
+        let game_banner: Arc<Mutex<Option<(State, Instant)>>> = Arc::new(Mutex::new(None));
+
+        println!("Starting event listener...");
         let terminal_string_clone = Arc::clone(&terminal_string);
-        let temp_dir = std::env::temp_dir().into_os_string().into_string().unwrap();
+        let devices_string_clone = Arc::clone(&devices_string);
+        let is_robot_running_clone = Arc::clone(&is_robot_running);
+        let runtime_status_clone = Arc::clone(&runtime_status);
+        let latency_clone = Arc::clone(&latency);
+        let link_clone = Arc::clone(&link);
+        let game_banner_clone = Arc::clone(&game_banner);
+        let client_clone = client.clone();
         thread::spawn(move || {
-            let mut buffer = vec![];
+            // anything the field sent before we opened is old news
+            let mut last_alert = client_clone.game_alert().ok().flatten().map(|alert| alert.seq);
+            let mut sfx_manager: Option<SfxManager> = None;
+            let sfx_overrides = sfx_overrides();
+            // start with the history the robot thread still has, then follow along
+            let mut since = 0;
             loop {
-                // read from the {TEMP DIR}/robot.run.txt and update the log if there is any new data
-                let file = fs::read_to_string(format!("{}/robot.run.txt", temp_dir));
-                if file.is_err() {
-                    buffer = vec![];
+                let events = client_clone.subscribe(since);
+                if events.is_err() {
+                    thread::sleep(Duration::from_secs(1));
                     continue;
                 }
-                let file = file.unwrap();
-                if file.len() == 0 {
-                    continue;
-                }
-                let file = file.as_bytes().to_vec();
-                // now compare this file with the buffer, if there is new data at the end, then update the buffer, then send the text to console
-                if file.len() > buffer.len() {
-                    let new_data = &file[buffer.len()..];
-                    let new_data = String::from_utf8(new_data.to_vec()).unwrap();
-                    terminal_string_clone.lock().unwrap().push_str(&new_data);
-                    buffer = file;
+                let mut events = events.unwrap();
+                while let Ok(event) = events.next_event() {
+                    match event {
+                        Some(ipc::Event::Logs(batch)) => {
+                            let lines = decode_log_lines(&batch).map_or(vec![], |(_, lines)| lines);
+                            let mut terminal_string = terminal_string_clone.lock().unwrap();
+                            for line in lines {
+                                since = line.seq;
+                                terminal_string.push_str(&line.display());
+                                terminal_string.push('\n');
+                            }
+                        }
+                        Some(ipc::Event::Devices(devices)) if *is_robot_running_clone.lock().unwrap() => {
+                            if let Ok(devices) = DevData::parse_from_bytes(&devices) {
+                                *devices_string_clone.lock().unwrap() = describe_devices(&devices);
+                            }
+                        }
+                        Some(ipc::Event::RuntimeStatus(status)) => {
+                            *runtime_status_clone.lock().unwrap() = Some(status);
+                        }
+                        // another client may have started or stopped the robot
+                        Some(ipc::Event::RunMode(mode)) => {
+                            *is_robot_running_clone.lock().unwrap() = mode != RunCommand::Stop;
+                        }
+                        Some(ipc::Event::Latency(stats)) => {
+                            *latency_clone.lock().unwrap() = Some(stats);
+                        }
+                        Some(ipc::Event::Link(state)) => {
+                            *link_clone.lock().unwrap() = Some(state);
+                        }
+                        Some(ipc::Event::GameAlert(alert)) if last_alert != Some(alert.seq) => {
+                            last_alert = Some(alert.seq);
+                            *game_banner_clone.lock().unwrap() = Some((alert.state, Instant::now()));
+                            if sfx_manager.is_none() {
+                                sfx_manager = SfxManager::new().ok();
+                            }
+                            if let Some(ref sfx) = sfx_manager {
+                                let _ = sfx.play_sfx(&game_state_sound(&sfx_overrides, alert.state), true);
+                            }
+                        }
+                        Some(ipc::Event::Connection(ConnectionState::Disconnected | ConnectionState::Failed(_))) => {
+                            *runtime_status_clone.lock().unwrap() = None;
+                            *latency_clone.lock().unwrap() = None;
+                            *link_clone.lock().unwrap() = None;
+                            *devices_string_clone.lock().unwrap() = "Disconnected from Robot".to_string();
+                        }
+                        _ => {}
+                    }
                 }
+                thread::sleep(Duration::from_secs(1));
             }
         });
 
@@ -93,17 +238,17 @@ pub mod run_robot_tui {
 
         println!("Initializing event listener...");
         let is_robot_running_clone = Arc::clone(&is_robot_running);
-        let temp_dir = std::env::temp_dir().into_os_string().into_string().unwrap();
 
         let unripe_stream = Arc::new(Mutex::new(false));
 
         // update unripe streams
         let unripe_stream_clone = Arc::clone(&unripe_stream);
+        let client_clone = client.clone();
         thread::spawn(move || {
             loop {
                 if unripe_stream_clone.lock().unwrap().eq(&true) {
                     *unripe_stream_clone.lock().unwrap() = false;
-                    let stream_change = UnixStream::connect(format!("{}/daybreak.sock", temp_dir));
+                    let stream_change = client_clone.run_stream();
                     if stream_change.is_err() {
                         terminal_string_clone
                             .lock()
@@ -121,12 +266,22 @@ pub mod run_robot_tui {
         let stream_clone = Arc::clone(&stream);
 
         let atomic_break_loop = Arc::new(AtomicBool::new(false));
+        let quit = Arc::new(AtomicBool::new(false));
+        let quit_clone = Arc::clone(&quit);
 
         let selected_pane_clone = Arc::clone(&selected_pane);
         let devices_string_clone = Arc::clone(&devices_string);
+        // the daemon keeps the side across TUIs and reconnects, so start from what it has
+        let start_pos = client.status().ok().and_then(|status| status.start_pos).map(Pos::from);
+        let start_pos: Arc<Mutex<Option<Pos>>> = Arc::new(Mutex::new(start_pos));
+        let start_pos_clone = Arc::clone(&start_pos);
         thread::spawn(move || {
             loop {
-                match event::read().unwrap() {
+                let Ok(event) = event::read() else {
+                    quit_clone.store(true, Ordering::Relaxed);
+                    return;
+                };
+                match event {
                     event::Event::Key(event::KeyEvent {
                         code: event::KeyCode::Esc,
                         ..
@@ -135,11 +290,9 @@ pub mod run_robot_tui {
                         code: event::KeyCode::Char('q'),
                         ..
                     }) => {
-                        ratatui::restore();
-                        let mut stream = stream_clone.lock().unwrap();
-                        let _ = stream.write(&[4]);
-                        let _ = stream.flush();
-                        exit(0);
+                        let _ = stream_clone.lock().unwrap().stop();
+                        quit_clone.store(true, Ordering::Relaxed);
+                        return;
                     }
                     event::Event::Key(event::KeyEvent {
                         code: event::KeyCode::Char('s'),
@@ -151,40 +304,41 @@ pub mod run_robot_tui {
 
                         atomic_break_loop.store(false, Ordering::Release);
                         // start in teleop
-                        let mut stream = stream_clone.lock().unwrap();
-                        let _ = stream.write(&[3]);
-                        let _ = stream.write(&[1]);
-                        let _ = stream.flush();
+                        let _ = stream_clone.lock().unwrap().start(RunCommand::Teleop);
                         terminal_string_clone
                             .lock()
                             .unwrap()
                             .push_str("Starting in teleop mode\n");
 
-                        let gilrs = Gilrs::new().unwrap();
-                        for (_id, gamepad) in gilrs.gamepads() {
-                            terminal_string_clone.lock().unwrap().push_str(
-                                format!("{} is {:?}\n", gamepad.name(), gamepad.power_info())
-                                    .as_str(),
-                            );
-                        }
+                        // the executor reports it if gamepads can't be opened at all
+                        if let Ok(gilrs) = Gilrs::new() {
+                            for (_id, gamepad) in gilrs.gamepads() {
+                                terminal_string_clone.lock().unwrap().push_str(
+                                    format!("{} is {:?}\n", gamepad.name(), gamepad.power_info())
+                                        .as_str(),
+                                );
+                            }
 
-                        if gilrs.gamepads().count() == 0 {
-                            terminal_string_clone
-                                .lock()
-                                .unwrap()
-                                .push_str("No gamepads available.\n");
+                            if gilrs.gamepads().count() == 0 {
+                                terminal_string_clone
+                                    .lock()
+                                    .unwrap()
+                                    .push_str("No gamepads available.\n");
+                            }
                         }
                         *is_robot_running_clone.lock().unwrap() = true;
                         let stream_clone = Arc::clone(&stream_clone);
                         let atomic_break_loop = Arc::clone(&atomic_break_loop);
                         let terminal_string_clone = Arc::clone(&terminal_string_clone);
                         thread::spawn(move || {
-                            input_executor(
+                            if let Err(e) = input_executor(
                                 Arc::clone(&stream_clone),
                                 false,
                                 atomic_break_loop,
                                 Arc::clone(&terminal_string_clone),
-                            )
+                            ) {
+                                terminal_string_clone.lock().unwrap().push_str(&format!("{}\n", e));
+                            }
                         });
                     }
                     event::Event::Key(event::KeyEvent {
@@ -197,28 +351,27 @@ pub mod run_robot_tui {
 
                         atomic_break_loop.store(false, Ordering::Release);
                         // start in teleop
-                        let mut stream = stream_clone.lock().unwrap();
-                        let _ = stream.write(&[6]);
-                        let _ = stream.write(&[1]);
-                        let _ = stream.flush();
+                        let _ = stream_clone.lock().unwrap().start_listening();
                         terminal_string_clone
                             .lock()
                             .unwrap()
                             .push_str("Starting in input mode\n");
 
-                        let gilrs = Gilrs::new().unwrap();
-                        for (_id, gamepad) in gilrs.gamepads() {
-                            terminal_string_clone.lock().unwrap().push_str(
-                                format!("{} is {:?}\n", gamepad.name(), gamepad.power_info())
-                                    .as_str(),
-                            );
-                        }
+                        // the executor reports it if gamepads can't be opened at all
+                        if let Ok(gilrs) = Gilrs::new() {
+                            for (_id, gamepad) in gilrs.gamepads() {
+                                terminal_string_clone.lock().unwrap().push_str(
+                                    format!("{} is {:?}\n", gamepad.name(), gamepad.power_info())
+                                        .as_str(),
+                                );
+                            }
 
-                        if gilrs.gamepads().count() == 0 {
-                            terminal_string_clone
-                                .lock()
-                                .unwrap()
-                                .push_str("No gamepads available.\n");
+                            if gilrs.gamepads().count() == 0 {
+                                terminal_string_clone
+                                    .lock()
+                                    .unwrap()
+                                    .push_str("No gamepads available.\n");
+                            }
                         }
 
                         *is_robot_running_clone.lock().unwrap() = true;
@@ -226,12 +379,14 @@ pub mod run_robot_tui {
                         let atomic_break_loop = Arc::clone(&atomic_break_loop);
                         let terminal_string_clone = Arc::clone(&terminal_string_clone);
                         thread::spawn(move || {
-                            input_executor(
+                            if let Err(e) = input_executor(
                                 Arc::clone(&stream_clone),
                                 false,
                                 atomic_break_loop,
                                 Arc::clone(&terminal_string_clone),
-                            )
+                            ) {
+                                terminal_string_clone.lock().unwrap().push_str(&format!("{}\n", e));
+                            }
                         });
                     }
                     event::Event::Key(event::KeyEvent {
@@ -243,9 +398,7 @@ pub mod run_robot_tui {
                             continue;
                         }
                         // start in teleop
-                        let mut stream = stream_clone.lock().unwrap();
-                        let _ = stream.write(&[4]);
-                        let _ = stream.flush();
+                        let _ = stream_clone.lock().unwrap().stop();
                         terminal_string_clone
                             .lock()
                             .unwrap()
@@ -262,10 +415,7 @@ pub mod run_robot_tui {
                             continue;
                         }
                         // start in teleop
-                        let mut stream = stream_clone.lock().unwrap();
-                        let _ = stream.write(&[3]);
-                        let _ = stream.write(&[3]);
-                        let _ = stream.flush();
+                        let _ = stream_clone.lock().unwrap().start(RunCommand::Auto);
 
                         terminal_string_clone
                             .lock()
@@ -274,6 +424,38 @@ pub mod run_robot_tui {
 
                         *is_robot_running_clone.lock().unwrap() = true;
                     }
+                    event::Event::Key(event::KeyEvent {
+                        code: event::KeyCode::Char('p'),
+                        ..
+                    }) => {
+                        let mut start_pos = start_pos_clone.lock().unwrap();
+                        let pos = match *start_pos {
+                            Some(Pos::LEFT) => Pos::RIGHT,
+                            _ => Pos::LEFT,
+                        };
+                        match client.set_start_pos(pos) {
+                            Ok(true) => {
+                                terminal_string_clone
+                                    .lock()
+                                    .unwrap()
+                                    .push_str(&format!("Starting on the {:?} side\n", pos));
+                            }
+                            Ok(false) => {
+                                terminal_string_clone.lock().unwrap().push_str(&format!(
+                                    "Starting on the {:?} side once the robot connects\n",
+                                    pos
+                                ));
+                            }
+                            Err(e) => {
+                                terminal_string_clone
+                                    .lock()
+                                    .unwrap()
+                                    .push_str(&format!("Failed to send start position: {}\n", e));
+                                continue;
+                            }
+                        }
+                        *start_pos = Some(pos);
+                    }
                     event::Event::Key(event::KeyEvent {
                         code: event::KeyCode::Up,
                         ..
@@ -322,20 +504,27 @@ pub mod run_robot_tui {
         let mut previous_scroll_terminal = 0;
         let mut schedule_clear = false;
 
-        terminal.clear().unwrap();
+        restore_on_error(terminal.clear())?;
 
-        loop {
-            if is_robot_running.lock().as_ref().unwrap().eq(&true) {
-                *devices_string.lock().unwrap() = read_devices();
-            }
+        while !quit.load(Ordering::Relaxed) {
             if schedule_clear {
                 schedule_clear = false;
-                terminal.clear().unwrap();
+                restore_on_error(terminal.clear())?;
             }
-            terminal
+            restore_on_error(terminal
                 .draw(|frame| {
+                    let banner = game_banner
+                        .lock()
+                        .unwrap()
+                        .and_then(|(state, received)| game_state_banner(state, received));
+                    let vertical = Layout::vertical([
+                        Length(1),
+                        Length(if banner.is_some() { 1 } else { 0 }),
+                        Fill(1),
+                    ]);
+                    let [header_area, banner_area, body_area] = vertical.areas(frame.area());
                     let horizontal = Layout::horizontal([Percentage(70), Percentage(30)]);
-                    let [main_area, devices_area] = horizontal.areas(frame.area());
+                    let [main_area, devices_area] = horizontal.areas(body_area);
                     let instructions = Line::from(vec![
                         " Switch Pane ".reset(),
                         "<Left>/<Right>".blue().bold(),
@@ -345,6 +534,8 @@ pub mod run_robot_tui {
                         "<S>".blue().bold(),
                         " Input ".reset(),
                         "<I>".blue().bold(),
+                        " Start Side ".reset(),
+                        "<P>".blue().bold(),
                         " ".into(),
                     ]);
 
@@ -425,6 +616,18 @@ pub mod run_robot_tui {
                         schedule_clear = true;
                         previous_scroll_devices = app_devices_pane.lock().unwrap().scroll;
                     }
+                    frame.render_widget(
+                        status_header(
+                            &runtime_status.lock().unwrap(),
+                            &latency.lock().unwrap(),
+                            &start_pos.lock().unwrap(),
+                            &link.lock().unwrap(),
+                        ),
+                        header_area,
+                    );
+                    if let Some(banner) = banner {
+                        frame.render_widget(banner, banner_area);
+                    }
                     frame.render_stateful_widget(
                         devices_list,
                         devices_area,
@@ -438,49 +641,44 @@ pub mod run_robot_tui {
                         &mut ListState::default()
                             .with_offset(app_terminal_pane.lock().unwrap().scroll),
                     );
-                })
-                .unwrap();
+                }))?;
+            thread::sleep(Duration::from_millis(50));
         }
+        ratatui::restore();
+        Ok(())
     }
+
+    /// Sends gamepad input down `stream` until `receiver` is set. With `utilize_stopper`, Ctrl-C stops the robot and sets it.
     pub fn input_executor(
-        stream: Arc<Mutex<UnixStream>>,
+        stream: Arc<Mutex<RunStream>>,
         utilize_stopper: bool,
         receiver: Arc<AtomicBool>,
         terminal_string: Arc<Mutex<String>>,
-    ) -> () {
+    ) -> Result<(), DaybreakError> {
         let stream_clone = Arc::clone(&stream);
 
         // Create a channel for sound effect commands
-        let (sfx_tx, sfx_rx) = mpsc::channel();
-        let sfx_tx_clone = sfx_tx.clone();
+        let (sfx_tx, sfx_rx) = mpsc::channel::<(String, bool, bool)>();
 
         if utilize_stopper {
-            thread::spawn(move || {
-                for sig in Signals::new([SIGINT]).unwrap().forever() {
-                    println!("\n[Run] Received signal {:?}", sig);
-                    let stream = stream_clone.lock();
-                    if stream.is_err() {
-                        println!("[Run] Failed to connect to daemon.");
-                        exit(1);
-                    }
-                    let mut stream = stream.unwrap();
-                    let _ = stream.write(&[4]);
-                    let _ = stream.flush();
-                    println!("[Run] Sent stop message to daemon.");
-
-                    // Send stop command through channel and wait for it to complete
-                    if let Ok(_) = sfx_tx_clone.send(("stop".to_string(), true, false)) {
-                        // Give time for stop sound to play
-                        thread::sleep(Duration::from_millis(1000));
-                        // Then stop all sounds
-                        let _ = sfx_tx_clone.send(("".to_string(), false, true));
-                    }
-                    exit(0);
+            match Signals::new([SIGINT]) {
+                Ok(mut signals) => {
+                    let stopper = Arc::clone(&receiver);
+                    thread::spawn(move || {
+                        if let Some(sig) = signals.forever().next() {
+                            println!("\n[Run] Received signal {:?}", sig);
+                            let _ = stream_clone.lock().unwrap_or_else(PoisonError::into_inner).stop();
+                            println!("[Run] Sent stop message to daemon.");
+                            // the executor plays the stop sound on its way out
+                            stopper.store(true, Ordering::Release);
+                        }
+                    });
                 }
-            });
+                Err(e) => println!("[Run] Failed to catch Ctrl-C: {}", e),
+            }
         }
 
-        let mut gilrs = Gilrs::new().unwrap();
+        let mut gilrs = Gilrs::new().map_err(|e| DaybreakError::Gamepad(e.to_string()))?;
         let mut sfx_manager = match SfxManager::new() {
             Ok(manager) => {
                 terminal_string
@@ -646,14 +844,14 @@ pub mod run_robot_tui {
             ];
 
             // Wait for each button press
-            for (index, btn) in button_names.iter().enumerate() {
+            for btn in button_names.iter() {
                 terminal_string
                     .lock()
                     .unwrap()
                     .push_str(&format!("Press the {} button...\n", btn.0));
 
                 'button_wait: loop {
-                    while let Some(Event { id, event, .. }) = gilrs.next_event() {
+                    while let Some(Event { event, .. }) = gilrs.next_event() {
                         if let gilrs::EventType::ButtonPressed(button, _) = event {
                             terminal_string
                                 .lock()
@@ -762,7 +960,7 @@ pub mod run_robot_tui {
                 let start_time = std::time::Instant::now();
 
                 while start_time.elapsed() < Duration::from_secs(2) {
-                    while let Some(Event { id, event, .. }) = gilrs.next_event() {
+                    while let Some(Event { id, .. }) = gilrs.next_event() {
                         active_gamepad = Some(id);
                     }
 
@@ -832,7 +1030,6 @@ pub mod run_robot_tui {
         // button_map.insert(Button::LeftThumb, false);
         // button_map.insert(Button::RightThumb, false);
 
-        let stream_clone = Arc::clone(&stream);
         let terminal_string = Arc::new(Mutex::new(String::new()));
         let terminal_string_clone = Arc::clone(&terminal_string);
 
@@ -849,8 +1046,6 @@ pub mod run_robot_tui {
             }
         });
 
-        // open a socket to listen for external programs to send commands to the robot
-
         loop {
             // Handle any pending sound effect commands
             while let Ok((name, once, stop_all)) = sfx_rx.try_recv() {
@@ -903,12 +1098,12 @@ pub mod run_robot_tui {
             }
 
             while let Some(Event {
-                id, event, time, ..
+                id, event, ..
             }) = gilrs.next_event()
             {
                 match event {
                     gilrs::EventType::ButtonPressed(button, _) => {
-                        if let Some(gamepad) = active_gamepad.map(|id| gilrs.gamepad(id)) {
+                        if let Some(_gamepad) = active_gamepad.map(|id| gilrs.gamepad(id)) {
                             if let Some((&std_button, _)) =
                                 button_mapping.iter().find(|(_, &v)| v == button)
                             {
@@ -939,7 +1134,7 @@ pub mod run_robot_tui {
                         }
                     }
                     gilrs::EventType::ButtonReleased(button, _) => {
-                        if let Some(gamepad) = active_gamepad.map(|id| gilrs.gamepad(id)) {
+                        if let Some(_gamepad) = active_gamepad.map(|id| gilrs.gamepad(id)) {
                             if let Some((&std_button, _)) =
                                 button_mapping.iter().find(|(_, &v)| v == button)
                             {
@@ -961,7 +1156,7 @@ pub mod run_robot_tui {
             }
 
             // Handle stick movements
-            if let Some(gamepad) = active_gamepad.map(|id| gilrs.gamepad(id)) {
+            if let Some(_gamepad) = active_gamepad.map(|id| gilrs.gamepad(id)) {
                 let threshold = 0.5;
                 let left_up = axes[1] * joystick_multipliers[1] > threshold;
                 let left_down = axes[1] * joystick_multipliers[1] < -threshold;
@@ -1010,14 +1205,11 @@ pub mod run_robot_tui {
                 }
             }
 
-            // always look for external connections to send commands to the robot as a gamepad
-            let mut external_axes = vec![0.0, 0.0, 0.0, 0.0];
-
             let mut bitmap: u64 = 0;
             // Set bitmap based on current button_map state
             for (button, is_pressed) in button_map.iter() {
                 if *is_pressed {
-                    let mapped_index = gamepad_mapped(&button);
+                    let mapped_index = gamepad_mapped(button);
                     terminal_string
                         .lock()
                         .unwrap()
@@ -1026,9 +1218,6 @@ pub mod run_robot_tui {
                 }
             }
 
-            let mut stream = stream.lock().unwrap();
-
-            let _ = stream.write(&[5]);
             let input = Input {
                 connected: true,
                 buttons: bitmap,
@@ -1036,11 +1225,8 @@ pub mod run_robot_tui {
                 source: EnumOrUnknown::new(InputSource::GAMEPAD),
                 special_fields: SpecialFields::default(),
             };
-            let bytes = input.write_to_bytes().unwrap();
-            let _ = stream.write(&[(bytes.len() & 0x00ff) as u8]);
-            let _ = stream.write(&[((bytes.len() & 0xff00) >> 8) as u8]);
-            let _ = stream.write(&bytes);
-            let _ = stream.flush();
+            let _ = stream.lock().unwrap().send_input(&input);
         }
+        Ok(())
     }
 }